@@ -0,0 +1,116 @@
+// Compares saving a generated library one autocommitted statement at a time against saving it in
+// chunked transactions with a savepoint per song, the way content_scanner::save_chunk does. Run
+// with:
+//
+//     cargo bench --bench bulk_insert
+//
+// BENCH_SONGS sets the library size, 10000 songs by default.
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use musicbase::{
+    content_scanner::SCAN_CHUNK_SIZE,
+    database::ConnectionWrapper,
+    fs_utils::get_unique_path,
+    models::{
+        base_metadata::{Album, Artist, Song},
+        Quality,
+    },
+};
+
+const SONGS_PER_ALBUM: usize = 12;
+const ALBUMS_PER_ARTIST: usize = 4;
+
+fn generate_library(songs: usize) -> Vec<Song> {
+    (0..songs)
+        .map(|i| {
+            let album = i / SONGS_PER_ALBUM;
+            let artist = Artist {
+                artist_id: None,
+                name: format!("Artist {}", album / ALBUMS_PER_ARTIST),
+                artist_image_path: None,
+            };
+
+            Song {
+                song_id: None,
+                name: format!("Track {}", i % SONGS_PER_ALBUM + 1),
+                file_path: format!("/music/{}/{}.flac", album, i),
+                track: Some((i % SONGS_PER_ALBUM + 1) as u16),
+                disc: Some(1),
+                duration_s: Some(180.0),
+                quality: Quality::Lossless,
+                genre: Some("Generated".into()),
+                artist: Some(artist.clone()),
+                album: Some(Album {
+                    album_id: None,
+                    name: format!("Album {}", album),
+                    artist: Some(artist),
+                    cover_path: None,
+                    cover_path_small: None,
+                    cover_path_tiny: None,
+                    year: Some(2000 + (album % 25) as i64),
+                    total_tracks: Some(SONGS_PER_ALBUM as i64),
+                    total_discs: Some(1),
+                }),
+                missing: false,
+                audio: Default::default(),
+            }
+        })
+        .collect()
+}
+
+// Runs save against a fresh database file, since committing to disk is what's being measured
+fn measure(name: &str, library: &[Song], save: fn(&ConnectionWrapper, Vec<Song>)) -> Duration {
+    let path = get_unique_path(&env::temp_dir().to_string_lossy(), "db").unwrap();
+    let db = ConnectionWrapper::open(&path).unwrap();
+    db.migrate().unwrap();
+
+    let start = Instant::now();
+    save(&db, library.to_vec());
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<12} {:>8.2} s {:>10.0} songs/s",
+        name,
+        elapsed.as_secs_f64(),
+        library.len() as f64 / elapsed.as_secs_f64()
+    );
+
+    drop(db);
+    let _ = fs::remove_file(&path);
+    elapsed
+}
+
+fn main() {
+    let songs = env::var("BENCH_SONGS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10000);
+    let library = generate_library(songs);
+    println!("Saving {} songs", songs);
+
+    let autocommit = measure("autocommit", &library, |db, library| {
+        for mut song in library {
+            db.insert_full(&mut song).unwrap();
+        }
+    });
+
+    let chunked = measure("chunked", &library, |db, mut library| {
+        for chunk in library.chunks_mut(SCAN_CHUNK_SIZE) {
+            let transaction = db.transaction().unwrap();
+            for song in chunk {
+                let savepoint = transaction.savepoint("file").unwrap();
+                db.insert_full(song).unwrap();
+                savepoint.commit().unwrap();
+            }
+            transaction.commit().unwrap();
+        }
+    });
+
+    println!(
+        "Speedup      {:>8.1}x",
+        autocommit.as_secs_f64() / chunked.as_secs_f64()
+    );
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+};
+
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, FileType, TaggedFile},
+    iff::wav::{WavFile, WavFormat},
+    mp4::{Mp4Codec, Mp4File},
+    probe::Probe,
+    wavpack::WavPackFile,
+};
+
+use crate::{
+    error::Error,
+    models::{AudioProperties, Codec, Quality},
+};
+
+// Whether a file is lossless depends on more than its container: M4A files can hold AAC or ALAC,
+// WAV files can hold compressed audio and WavPack has a lossy hybrid mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioFormat {
+    pub codec: Codec,
+    pub quality: Quality,
+}
+
+impl AudioFormat {
+    fn lossless(codec: Codec) -> AudioFormat {
+        AudioFormat {
+            codec,
+            quality: Quality::Lossless,
+        }
+    }
+
+    fn lossy(codec: Codec) -> AudioFormat {
+        AudioFormat {
+            codec,
+            quality: Quality::Lossy,
+        }
+    }
+}
+
+// AIFF-C compression types that are plain samples in one byte order or another
+const AIFF_UNCOMPRESSED: [&[u8; 4]; 10] = [
+    b"NONE", b"sowt", b"twos", b"raw ", b"in24", b"in32", b"fl32", b"fl64", b"FL32", b"FL64",
+];
+
+// Tells the type of a file by its content, whatever its extension. Returns None for files that
+// aren't audio or are in a format that isn't supported.
+pub fn detect(file_path: &str) -> Result<Option<FileType>, Error> {
+    let probe = Probe::new(BufReader::new(File::open(file_path)?)).guess_file_type()?;
+    Ok(probe.file_type().filter(is_supported))
+}
+
+fn is_supported(file_type: &FileType) -> bool {
+    matches!(
+        file_type,
+        FileType::Mpeg
+            | FileType::Aac
+            | FileType::Flac
+            | FileType::Vorbis
+            | FileType::Opus
+            | FileType::Mp4
+            | FileType::Wav
+            | FileType::Aiff
+            | FileType::WavPack
+    )
+}
+
+// Reads the tags and properties of an audio file along with its format
+pub fn read_audio_file(file_path: &str) -> Result<(TaggedFile, AudioFormat), Error> {
+    let probe = Probe::new(BufReader::new(File::open(file_path)?)).guess_file_type()?;
+    let Some(file_type) = probe.file_type().filter(is_supported) else {
+        return Err(Error::Tag(format!(
+            "{} is not in a supported audio format",
+            file_path
+        )));
+    };
+    let mut reader = probe.into_inner();
+    let options = ParseOptions::new();
+
+    // Containers that can hold more than one codec need their format specific properties
+    Ok(match file_type {
+        FileType::Mp4 => {
+            let file = Mp4File::read_from(&mut reader, options)?;
+            let format = match file.properties().codec() {
+                Mp4Codec::ALAC => AudioFormat::lossless(Codec::Alac),
+                Mp4Codec::FLAC => AudioFormat::lossless(Codec::Flac),
+                Mp4Codec::MP3 => AudioFormat::lossy(Codec::Mp3),
+                _ => AudioFormat::lossy(Codec::Aac),
+            };
+            (file.into(), format)
+        }
+        FileType::Wav => {
+            let file = WavFile::read_from(&mut reader, options)?;
+            let format = match file.properties().format() {
+                WavFormat::PCM | WavFormat::IEEE_FLOAT => AudioFormat::lossless(Codec::Pcm),
+                _ => AudioFormat::lossy(Codec::Other),
+            };
+            (file.into(), format)
+        }
+        FileType::WavPack => {
+            let file = WavPackFile::read_from(&mut reader, options)?;
+            let format = if file.properties().is_lossless() {
+                AudioFormat::lossless(Codec::WavPack)
+            } else {
+                AudioFormat::lossy(Codec::WavPack)
+            };
+            (file.into(), format)
+        }
+        FileType::Aiff => {
+            let compression = aiff_compression(&mut reader)?;
+            let format = if AIFF_UNCOMPRESSED.contains(&&compression) {
+                AudioFormat::lossless(Codec::Pcm)
+            } else {
+                AudioFormat::lossy(Codec::Other)
+            };
+            reader.rewind()?;
+            let file = Probe::with_file_type(reader, file_type)
+                .options(options)
+                .read()?;
+            (file, format)
+        }
+        _ => {
+            let format = match file_type {
+                FileType::Mpeg => AudioFormat::lossy(Codec::Mp3),
+                FileType::Aac => AudioFormat::lossy(Codec::Aac),
+                FileType::Flac => AudioFormat::lossless(Codec::Flac),
+                FileType::Vorbis => AudioFormat::lossy(Codec::Vorbis),
+                _ => AudioFormat::lossy(Codec::Opus),
+            };
+            let file = Probe::with_file_type(reader, file_type)
+                .options(options)
+                .read()?;
+            (file, format)
+        }
+    })
+}
+
+// Properties of the audio stream of a file as read from the stream headers, the ones that can't be
+// told are left empty
+pub fn audio_properties(file: &TaggedFile, format: AudioFormat) -> AudioProperties {
+    let properties = file.properties();
+    AudioProperties {
+        codec: Some(format.codec),
+        bitrate_kbps: properties
+            .audio_bitrate()
+            .or(properties.overall_bitrate())
+            .filter(|bitrate| *bitrate > 0),
+        sample_rate_hz: properties.sample_rate().filter(|rate| *rate > 0),
+        // Lossy codecs don't have a bit depth, whatever their container claims
+        bit_depth: match format.quality {
+            Quality::Lossless => properties.bit_depth().filter(|depth| *depth > 0),
+            Quality::Lossy => None,
+        },
+        channels: properties.channels().filter(|channels| *channels > 0),
+    }
+}
+
+// AIFF files are always uncompressed, AIFF-C files name their compression type in the COMM chunk
+fn aiff_compression<R: Read + Seek>(reader: &mut R) -> Result<[u8; 4], Error> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[8..12] != b"AIFC" {
+        return Ok(*b"NONE");
+    }
+
+    loop {
+        let mut chunk = [0; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as i64;
+        if &chunk[0..4] == b"COMM" {
+            // The compression type comes after the channels, frames, sample size and sample rate
+            let mut comm = [0; 22];
+            reader.read_exact(&mut comm)?;
+            return Ok([comm[18], comm[19], comm[20], comm[21]]);
+        }
+        // Chunks are padded to an even size
+        reader.seek(SeekFrom::Current(size + size % 2))?;
+    }
+}
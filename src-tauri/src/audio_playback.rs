@@ -1,37 +1,731 @@
 use std::{
-    io::{Read, Write},
-    net::Shutdown,
+    io::{BufRead, BufReader, Write},
     os::unix::net::UnixStream,
+    path::{Path, PathBuf},
     process::Command,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
 };
 
-pub fn start_mpv_process() -> Result<(), ()> {
-    Command::new("mpv")
+use rand::seq::SliceRandom;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{error::Error, models::base_metadata::Song};
+
+pub const MPV_SOCKET: &str = "/tmp/mpvsocket";
+
+// How long to wait for mpv to answer a command before giving up
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn start_mpv_process(socket_path: &str) -> Result<(), Error> {
+    let result = Command::new("mpv")
         .arg("--no-audio-display")
         .arg("--idle")
         // .arg("--terminal=no")
-        .arg("--input-ipc-server=/tmp/mpvsocket")
-        .spawn()
-        .unwrap();
-    Ok(())
+        .arg(format!("--input-ipc-server={}", socket_path))
+        .spawn();
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::Playback(format!("Could not start mpv: {}", err))),
+    }
+}
+
+// Where loadfile puts the file in the mpv playlist
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadMode {
+    // Stops the current file and replaces the whole playlist
+    Replace,
+    Append,
+    // Appends and starts playing if nothing is playing
+    AppendPlay,
+}
+
+// Where songs the user picked go
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    // Replaces the whole queue
+    Now,
+    // Right after the current song
+    Next,
+    // After everything else in the queue
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekMode {
+    // Seconds from the start of the file
+    Absolute,
+    // Seconds from the current position, negative to go back
+    Relative,
+}
+
+// Commands of the mpv JSON IPC protocol, see https://mpv.io/manual/stable/#json-ipc
+#[derive(Debug, Clone, PartialEq)]
+pub enum MpvCommand {
+    LoadFile(String, LoadMode),
+    Stop,
+    Seek(f64, SeekMode),
+    PlaylistNext,
+    PlaylistPrev,
+    PlaylistClear,
+    PlaylistRemove(usize),
+    PlaylistMove(usize, usize),
+    GetProperty(String),
+    SetProperty(String, Value),
+    // mpv sends a property-change event with the given id whenever the property changes
+    ObserveProperty(u64, String),
+}
+
+impl MpvCommand {
+    pub fn args(&self) -> Vec<Value> {
+        match self {
+            MpvCommand::LoadFile(path, mode) => {
+                let mode = match mode {
+                    LoadMode::Replace => "replace",
+                    LoadMode::Append => "append",
+                    LoadMode::AppendPlay => "append-play",
+                };
+                vec![json!("loadfile"), json!(path), json!(mode)]
+            }
+            MpvCommand::Stop => vec![json!("stop")],
+            MpvCommand::Seek(seconds, mode) => {
+                let mode = match mode {
+                    SeekMode::Absolute => "absolute",
+                    SeekMode::Relative => "relative",
+                };
+                vec![json!("seek"), json!(seconds), json!(mode)]
+            }
+            MpvCommand::PlaylistNext => vec![json!("playlist-next")],
+            MpvCommand::PlaylistPrev => vec![json!("playlist-prev")],
+            MpvCommand::PlaylistClear => vec![json!("playlist-clear")],
+            MpvCommand::PlaylistRemove(index) => vec![json!("playlist-remove"), json!(index)],
+            MpvCommand::PlaylistMove(from, to) => {
+                vec![json!("playlist-move"), json!(from), json!(to)]
+            }
+            MpvCommand::GetProperty(name) => vec![json!("get_property"), json!(name)],
+            MpvCommand::SetProperty(name, value) => {
+                vec![json!("set_property"), json!(name), value.clone()]
+            }
+            MpvCommand::ObserveProperty(id, name) => {
+                vec![json!("observe_property"), json!(id), json!(name)]
+            }
+        }
+    }
+}
+
+// One line sent to mpv, serde takes care of escaping the file paths
+#[derive(Debug, Serialize)]
+pub struct MpvRequest {
+    pub command: Vec<Value>,
+    pub request_id: u64,
+}
+
+// One line received from mpv: either the response to a request or an event, which has no
+// request_id but an event name
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MpvResponse {
+    #[serde(default)]
+    pub request_id: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub event: Option<String>,
+    // The property of a property-change event
+    #[serde(default)]
+    pub name: Option<String>,
+    // Why the file stopped playing in an end-file event, such as eof or stop
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+// A client for the JSON IPC socket of mpv. Requests are sent one at a time over a connection that
+// is opened when needed and opened again if mpv went away.
+pub struct Mpv {
+    socket_path: PathBuf,
+    connection: Mutex<Option<Connection>>,
+    next_request_id: AtomicU64,
+}
+
+struct Connection {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Connection {
+    fn open(socket_path: &Path) -> Result<Connection, Error> {
+        let stream = UnixStream::connect(socket_path)?;
+        stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Connection { stream, reader })
+    }
+
+    // Skips events and responses to earlier requests that timed out
+    fn send(&mut self, request: &MpvRequest) -> Result<MpvResponse, Error> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.stream.write_all(line.as_bytes())?;
+
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(Error::Io("mpv closed the connection".into()));
+            }
+            let response: MpvResponse = serde_json::from_str(&line)?;
+            if response.request_id == Some(request.request_id) {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+impl Mpv {
+    pub fn new(socket_path: &str) -> Mpv {
+        Mpv {
+            socket_path: socket_path.into(),
+            connection: Mutex::new(None),
+            next_request_id: AtomicU64::new(1),
+        }
+    }
+
+    // Runs a command and returns its data, null for commands that don't return anything
+    pub fn command(&self, command: &MpvCommand) -> Result<Value, Error> {
+        let request = MpvRequest {
+            command: command.args(),
+            request_id: self.next_request_id.fetch_add(1, Ordering::Relaxed),
+        };
+
+        // The connection is only put back if it worked, otherwise the next command opens a new one
+        let mut connection = self.connection.lock()?;
+        let result = match connection.take() {
+            Some(open) => Ok(open),
+            None => Connection::open(&self.socket_path),
+        }
+        .and_then(|mut open| {
+            let response = open.send(&request)?;
+            *connection = Some(open);
+            Ok(response)
+        });
+
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                return Err(Error::Playback(format!(
+                    "Could not send command to mpv: {}",
+                    err.message()
+                )))
+            }
+        };
+
+        match response.error.as_deref() {
+            Some("success") | None => Ok(response.data.unwrap_or(Value::Null)),
+            Some(error) => {
+                let name = request.command[0].as_str().unwrap_or_default();
+                Err(Error::Playback(format!(
+                    "mpv could not run {}: {}",
+                    name, error
+                )))
+            }
+        }
+    }
+
+    pub fn load_file(&self, path: &str, mode: LoadMode) -> Result<(), Error> {
+        self.command(&MpvCommand::LoadFile(path.into(), mode))?;
+        Ok(())
+    }
+
+    pub fn pause(&self) -> Result<(), Error> {
+        self.set_property("pause", true)
+    }
+
+    pub fn resume(&self) -> Result<(), Error> {
+        self.set_property("pause", false)
+    }
+
+    // Stops playback and clears the playlist
+    pub fn stop(&self) -> Result<(), Error> {
+        self.command(&MpvCommand::Stop)?;
+        Ok(())
+    }
+
+    pub fn seek(&self, seconds: f64, mode: SeekMode) -> Result<(), Error> {
+        self.command(&MpvCommand::Seek(seconds, mode))?;
+        Ok(())
+    }
+
+    // Volume in percent, 100 is the original volume
+    pub fn set_volume(&self, volume: f64) -> Result<(), Error> {
+        self.set_property("volume", volume)
+    }
+
+    pub fn set_mute(&self, mute: bool) -> Result<(), Error> {
+        self.set_property("mute", mute)
+    }
+
+    pub fn next(&self) -> Result<(), Error> {
+        self.command(&MpvCommand::PlaylistNext)?;
+        Ok(())
+    }
+
+    pub fn previous(&self) -> Result<(), Error> {
+        self.command(&MpvCommand::PlaylistPrev)?;
+        Ok(())
+    }
+
+    // Removes everything from the playlist except the file that is playing
+    pub fn clear_playlist(&self) -> Result<(), Error> {
+        self.command(&MpvCommand::PlaylistClear)?;
+        Ok(())
+    }
+
+    // Index of the current entry in the playlist, None if nothing is playing
+    pub fn position(&self) -> Result<Option<usize>, Error> {
+        let position: i64 = self.get_property("playlist-pos")?;
+        Ok(usize::try_from(position).ok())
+    }
+
+    // Starts playing the entry at index
+    pub fn play_index(&self, index: usize) -> Result<(), Error> {
+        self.set_property("playlist-pos", index)
+    }
+
+    pub fn get_property<T: DeserializeOwned>(&self, name: &str) -> Result<T, Error> {
+        let data = self.command(&MpvCommand::GetProperty(name.into()))?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub fn set_property<T: Serialize>(&self, name: &str, value: T) -> Result<(), Error> {
+        let value = serde_json::to_value(value)?;
+        self.command(&MpvCommand::SetProperty(name.into(), value))?;
+        Ok(())
+    }
+}
+
+// The songs queued for playing and the one that is playing, payload of the queue_changed event
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PlayQueue {
+    pub songs: Vec<Song>,
+    pub position: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    Songs,
+    // Whole albums in a random order, the songs of each album in the order they were queued in
+    Albums,
 }
 
-// Plays file at path using the default audio device.
-// If parameter queue is true, the file will be added to a queue to be played gaplessly.
-pub fn play_file(path: &str, queue: bool) {
-    let command = format!(
-        "{{ \"command\": [\"loadfile\", \"{}\", \"append-play\"] }}\n",
-        path
-    );
-    let result = send_command(command);
-    println!("result: {}", result);
-}
-
-fn send_command(command: String) -> String {
-    let mut unix_stream = UnixStream::connect("/tmp/mpvsocket").unwrap();
-    unix_stream.write(command.as_bytes()).unwrap();
-    unix_stream.shutdown(Shutdown::Write).unwrap();
-    let mut response = String::new();
-    unix_stream.read_to_string(&mut response).unwrap();
-    response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+// What mpv reports about the file it plays, see playback_observer
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Playback {
+    // Seconds from the start of the file
+    pub time_pos: Option<f64>,
+    pub duration: Option<f64>,
+    pub paused: bool,
+    pub volume: Option<f64>,
+    // Nothing left to play, None until mpv has reported it
+    pub idle: Option<bool>,
+    // Index of the current entry in the playlist
+    pub playlist_pos: Option<usize>,
+}
+
+// Payload of the player_state event
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PlayerState {
+    pub shuffle: ShuffleMode,
+    pub repeat: RepeatMode,
+    pub playback: Playback,
+}
+
+// The play queue and the mpv playlist that mirrors it. Every change goes to mpv first and to the
+// queue once mpv has taken it, so the two have the same entries in the same order. Shuffling
+// reorders the songs that haven't been played yet, the ones that have stay in the order they were
+// played in so that going back to the previous song works the same either way.
+pub struct Player {
+    mpv: Mpv,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    queue: PlayQueue,
+    // One key for every song in the queue, giving the order of the queue without shuffling. Turning
+    // shuffle off sorts the songs by these.
+    keys: Vec<u64>,
+    next_key: u64,
+    shuffle: ShuffleMode,
+    repeat: RepeatMode,
+    playback: Playback,
+}
+
+impl QueueState {
+    fn clear(&mut self) {
+        self.queue = PlayQueue::default();
+        self.keys.clear();
+        self.next_key = 0;
+    }
+
+    // Key for a song inserted at index. Unless it goes last, it follows the song before it in the
+    // order without shuffling too, and the keys after that make room for it.
+    fn key_after(&mut self, index: usize) -> u64 {
+        let key = match index {
+            0 => 0,
+            i if i >= self.keys.len() => self.next_key,
+            i => self.keys[i - 1] + 1,
+        };
+        for k in self.keys.iter_mut().filter(|k| **k >= key) {
+            *k += 1;
+        }
+        self.next_key += 1;
+        key
+    }
+
+    // Unless shuffling, the queue is in the order the songs were queued in
+    fn renumber(&mut self) {
+        if self.shuffle == ShuffleMode::Off {
+            self.keys = (0..self.keys.len() as u64).collect();
+            self.next_key = self.keys.len() as u64;
+        }
+    }
+
+    fn player_state(&self) -> PlayerState {
+        PlayerState {
+            shuffle: self.shuffle,
+            repeat: self.repeat,
+            playback: self.playback.clone(),
+        }
+    }
+
+    // Index of the first song that hasn't been played yet
+    fn upcoming(&self) -> usize {
+        self.queue.position.map_or(0, |p| p + 1)
+    }
+}
+
+impl Player {
+    pub fn new(mpv: Mpv) -> Player {
+        Player {
+            mpv,
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    pub fn mpv(&self) -> &Mpv {
+        &self.mpv
+    }
+
+    pub fn queue(&self) -> Result<PlayQueue, Error> {
+        Ok(self.lock()?.queue.clone())
+    }
+
+    pub fn state(&self) -> Result<PlayerState, Error> {
+        Ok(self.state.lock()?.player_state())
+    }
+
+    // Takes in what mpv reported, the queue follows mpv moving on to the next song
+    pub fn set_playback(&self, playback: Playback) -> Result<PlayerState, Error> {
+        let mut state = self.state.lock()?;
+        state.queue.position = playback
+            .playlist_pos
+            .filter(|p| *p < state.queue.songs.len());
+        state.playback = playback;
+        Ok(state.player_state())
+    }
+
+    pub fn add(&self, songs: Vec<Song>, mode: PlayMode) -> Result<PlayQueue, Error> {
+        if mode == PlayMode::Now {
+            return self.replace(songs, 0);
+        }
+
+        let mut state = self.lock()?;
+        let index = match (mode, state.queue.position) {
+            (PlayMode::Next, Some(position)) => position + 1,
+            _ => state.queue.songs.len(),
+        };
+        self.insert_songs(&mut state, index, songs)?;
+        // Nothing was playing, start from the first new song
+        if state.queue.position.is_none() && index < state.queue.songs.len() {
+            self.play_at(&mut state, index)?;
+        }
+        Ok(state.queue.clone())
+    }
+
+    // Replaces the whole queue and starts playing from the song at index. When shuffling, the
+    // songs after it are shuffled.
+    pub fn replace(&self, songs: Vec<Song>, index: usize) -> Result<PlayQueue, Error> {
+        check_index(index, songs.len())?;
+        let mut state = self.lock()?;
+
+        self.mpv.stop()?;
+        state.clear();
+        self.insert_songs(&mut state, 0, songs)?;
+        self.play_at(&mut state, index)?;
+        let mode = state.shuffle;
+        if mode != ShuffleMode::Off {
+            self.shuffle(&mut state, mode)?;
+        }
+        Ok(state.queue.clone())
+    }
+
+    // Plays the song in place of the current one, the rest of the queue stays
+    pub fn replace_current(&self, song: Song) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        let Some(position) = state.queue.position else {
+            drop(state);
+            return self.add(vec![song], PlayMode::Last);
+        };
+
+        self.insert_songs(&mut state, position + 1, vec![song])?;
+        // mpv goes on to the next entry, the new song, when the current one is removed
+        self.remove_song(&mut state, position)?;
+        Ok(state.queue.clone())
+    }
+
+    // Inserts the songs before the song at index, or at the end if index is the length of the queue
+    pub fn insert(&self, index: usize, songs: Vec<Song>) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        check_index(index, state.queue.songs.len() + 1)?;
+        self.insert_songs(&mut state, index, songs)?;
+        Ok(state.queue.clone())
+    }
+
+    // Moves the song at from so that it ends up at index to
+    pub fn move_song(&self, from: usize, to: usize) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        check_index(from, state.queue.songs.len())?;
+        check_index(to, state.queue.songs.len())?;
+        if from == to {
+            return Ok(state.queue.clone());
+        }
+
+        // mpv moves the entry in front of the entry at the target index
+        let target = if to > from { to + 1 } else { to };
+        self.mpv.command(&MpvCommand::PlaylistMove(from, target))?;
+
+        let song = state.queue.songs.remove(from);
+        state.queue.songs.insert(to, song);
+        let key = state.keys.remove(from);
+        state.keys.insert(to, key);
+        state.renumber();
+        state.queue.position = state.queue.position.map(|p| match p {
+            p if p == from => to,
+            p if from < p && p <= to => p - 1,
+            p if to <= p && p < from => p + 1,
+            p => p,
+        });
+        Ok(state.queue.clone())
+    }
+
+    pub fn remove(&self, index: usize) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        check_index(index, state.queue.songs.len())?;
+        self.remove_song(&mut state, index)?;
+        Ok(state.queue.clone())
+    }
+
+    // Stops playing and empties the queue
+    pub fn clear(&self) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        self.mpv.stop()?;
+        state.clear();
+        Ok(state.queue.clone())
+    }
+
+    // Starts playing the song at index
+    pub fn jump(&self, index: usize) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        check_index(index, state.queue.songs.len())?;
+        self.play_at(&mut state, index)?;
+        Ok(state.queue.clone())
+    }
+
+    pub fn next(&self) -> Result<PlayQueue, Error> {
+        let mut state = self.state.lock()?;
+        self.mpv.next()?;
+        self.sync(&mut state)?;
+        Ok(state.queue.clone())
+    }
+
+    pub fn previous(&self) -> Result<PlayQueue, Error> {
+        let mut state = self.state.lock()?;
+        self.mpv.previous()?;
+        self.sync(&mut state)?;
+        Ok(state.queue.clone())
+    }
+
+    // Shuffles the songs that haven't been played yet, or puts them back in the order they were
+    // queued in
+    pub fn set_shuffle(&self, mode: ShuffleMode) -> Result<PlayQueue, Error> {
+        let mut state = self.lock()?;
+        self.shuffle(&mut state, mode)?;
+        state.shuffle = mode;
+        state.renumber();
+        Ok(state.queue.clone())
+    }
+
+    // mpv repeats the songs by itself
+    pub fn set_repeat(&self, mode: RepeatMode) -> Result<PlayerState, Error> {
+        let mut state = self.state.lock()?;
+        let (file, playlist) = match mode {
+            RepeatMode::Off => ("no", "no"),
+            RepeatMode::One => ("inf", "no"),
+            RepeatMode::All => ("no", "inf"),
+        };
+        self.mpv.set_property("loop-file", file)?;
+        self.mpv.set_property("loop-playlist", playlist)?;
+        state.repeat = mode;
+        Ok(state.player_state())
+    }
+
+    // Locks the queue with the position brought up to date
+    fn lock(&self) -> Result<MutexGuard<'_, QueueState>, Error> {
+        let mut state = self.state.lock()?;
+        self.sync(&mut state)?;
+        Ok(state)
+    }
+
+    // mpv moves on to the next song by itself
+    fn sync(&self, state: &mut QueueState) -> Result<(), Error> {
+        let position = self.mpv.position()?;
+        state.queue.position = position.filter(|p| *p < state.queue.songs.len());
+        Ok(())
+    }
+
+    // loadfile can only add to the end of the playlist, so the songs are moved into place from there
+    fn insert_songs(
+        &self,
+        state: &mut QueueState,
+        index: usize,
+        songs: Vec<Song>,
+    ) -> Result<(), Error> {
+        for (i, song) in songs.into_iter().enumerate() {
+            let last = state.queue.songs.len();
+            self.mpv.load_file(&song.file_path, LoadMode::Append)?;
+            if index + i < last {
+                self.mpv
+                    .command(&MpvCommand::PlaylistMove(last, index + i))?;
+            }
+
+            state.queue.songs.insert(index + i, song);
+            let key = state.key_after(index + i);
+            state.keys.insert(index + i, key);
+            if let Some(position) = &mut state.queue.position {
+                if *position >= index + i {
+                    *position += 1;
+                }
+            }
+        }
+        state.renumber();
+        Ok(())
+    }
+
+    fn remove_song(&self, state: &mut QueueState, index: usize) -> Result<(), Error> {
+        self.mpv.command(&MpvCommand::PlaylistRemove(index))?;
+        state.queue.songs.remove(index);
+        state.keys.remove(index);
+        state.queue.position = match state.queue.position {
+            Some(p) if p > index => Some(p - 1),
+            // Playback goes on from the song after it, if there is one
+            Some(p) if p == index && p >= state.queue.songs.len() => None,
+            position => position,
+        };
+        Ok(())
+    }
+
+    fn play_at(&self, state: &mut QueueState, index: usize) -> Result<(), Error> {
+        self.mpv.play_index(index)?;
+        state.queue.position = Some(index);
+        Ok(())
+    }
+
+    // Puts the songs that haven't been played yet in the order of the mode
+    fn shuffle(&self, state: &mut QueueState, mode: ShuffleMode) -> Result<(), Error> {
+        let start = state.upcoming();
+        let mut upcoming: Vec<usize> = (start..state.queue.songs.len()).collect();
+        let mut rng = rand::thread_rng();
+
+        match mode {
+            ShuffleMode::Off => upcoming.sort_by_key(|i| state.keys[*i]),
+            ShuffleMode::Songs => upcoming.shuffle(&mut rng),
+            ShuffleMode::Albums => {
+                // Songs without an album are albums of their own
+                let mut albums: Vec<(Option<i64>, Vec<usize>)> = vec![];
+                for i in upcoming {
+                    let album_id = state.queue.songs[i].album.as_ref().and_then(|a| a.album_id);
+                    match albums
+                        .iter_mut()
+                        .find(|(id, _)| id.is_some() && *id == album_id)
+                    {
+                        Some((_, songs)) => songs.push(i),
+                        None => albums.push((album_id, vec![i])),
+                    }
+                }
+
+                albums.shuffle(&mut rng);
+                upcoming = albums
+                    .into_iter()
+                    .flat_map(|(_, mut songs)| {
+                        songs.sort_by_key(|i| state.keys[*i]);
+                        songs
+                    })
+                    .collect();
+            }
+        }
+
+        let order: Vec<usize> = (0..start).chain(upcoming).collect();
+        self.reorder(state, &order)
+    }
+
+    // Reorders the queue so that the song at order[i] ends up at i
+    fn reorder(&self, state: &mut QueueState, order: &[usize]) -> Result<(), Error> {
+        let mut current: Vec<usize> = (0..order.len()).collect();
+        for (i, index) in order.iter().enumerate() {
+            let from = current.iter().position(|c| c == index).unwrap_or(i);
+            if from != i {
+                self.mpv.command(&MpvCommand::PlaylistMove(from, i))?;
+                let moved = current.remove(from);
+                current.insert(i, moved);
+            }
+        }
+
+        state.queue.songs = order
+            .iter()
+            .map(|i| state.queue.songs[*i].clone())
+            .collect();
+        state.keys = order.iter().map(|i| state.keys[*i]).collect();
+        state.queue.position = state
+            .queue
+            .position
+            .and_then(|p| order.iter().position(|i| *i == p));
+        Ok(())
+    }
+}
+
+fn check_index(index: usize, len: usize) -> Result<(), Error> {
+    if index >= len {
+        return Err(Error::Validation(format!(
+            "No entry {} in a queue of {}",
+            index, len
+        )));
+    }
+    Ok(())
 }
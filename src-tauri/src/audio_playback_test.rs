@@ -0,0 +1,472 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    audio_playback::{
+        LoadMode, Mpv, PlayMode, PlayQueue, Playback, Player, PlayerState, RepeatMode, SeekMode,
+        ShuffleMode,
+    },
+    error::Error,
+    fs_utils::get_unique_path,
+    models::{
+        base_metadata::{Album, Song},
+        Quality,
+    },
+};
+
+fn socket_path() -> String {
+    get_unique_path(&env::temp_dir().to_string_lossy(), "sock").unwrap()
+}
+
+// Stands in for mpv on the socket at path: answers every request with the lines from respond and
+// passes the requests on to the returned receiver
+fn fake_mpv<F>(path: &str, respond: F) -> Receiver<Value>
+where
+    F: Fn(&Value) -> Vec<Value> + Send + 'static,
+{
+    let listener = UnixListener::bind(path).unwrap();
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            for line in reader.lines() {
+                let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let responses = respond(&request);
+                let _ = sender.send(request);
+                for response in responses {
+                    writeln!(stream, "{}", response).unwrap();
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn success(request: &Value, data: Value) -> Value {
+    json!({ "request_id": request["request_id"], "error": "success", "data": data })
+}
+
+#[test]
+fn sends_commands() {
+    let path = socket_path();
+    let requests = fake_mpv(&path, |request| vec![success(request, Value::Null)]);
+    let mpv = Mpv::new(&path);
+
+    // Quotes, backslashes and line breaks in paths have to survive the trip
+    let file = "/music/\"Live\" \\ at\nhome.flac";
+    mpv.load_file(file, LoadMode::AppendPlay).unwrap();
+    mpv.load_file(file, LoadMode::Replace).unwrap();
+    mpv.pause().unwrap();
+    mpv.resume().unwrap();
+    mpv.seek(-5.0, SeekMode::Relative).unwrap();
+    mpv.seek(30.0, SeekMode::Absolute).unwrap();
+    mpv.set_volume(50.0).unwrap();
+    mpv.set_mute(true).unwrap();
+    mpv.next().unwrap();
+    mpv.previous().unwrap();
+    mpv.clear_playlist().unwrap();
+    mpv.stop().unwrap();
+
+    let requests: Vec<Value> = requests.try_iter().collect();
+    let commands: Vec<Value> = requests.iter().map(|r| r["command"].clone()).collect();
+    assert_eq!(
+        commands,
+        vec![
+            json!(["loadfile", file, "append-play"]),
+            json!(["loadfile", file, "replace"]),
+            json!(["set_property", "pause", true]),
+            json!(["set_property", "pause", false]),
+            json!(["seek", -5.0, "relative"]),
+            json!(["seek", 30.0, "absolute"]),
+            json!(["set_property", "volume", 50.0]),
+            json!(["set_property", "mute", true]),
+            json!(["playlist-next"]),
+            json!(["playlist-prev"]),
+            json!(["playlist-clear"]),
+            json!(["stop"]),
+        ]
+    );
+    // Every request has its own id
+    assert!(requests
+        .windows(2)
+        .all(|w| w[0]["request_id"].as_u64() < w[1]["request_id"].as_u64()));
+
+    fs::remove_file(path).unwrap();
+}
+
+// The playlist of a fake mpv, changed by the commands it receives the way mpv changes its own
+#[derive(Default)]
+struct FakePlaylist {
+    entries: Vec<String>,
+    position: Option<usize>,
+    properties: HashMap<String, Value>,
+}
+
+impl FakePlaylist {
+    fn run(&mut self, command: &Value) -> Value {
+        let index = |i: usize| command[i].as_u64().unwrap() as usize;
+        let current = self.position.map(|p| self.entries[p].clone());
+        match command[0].as_str().unwrap() {
+            "loadfile" => self.entries.push(command[1].as_str().unwrap().into()),
+            "stop" => {
+                self.entries.clear();
+                self.position = None;
+            }
+            "playlist-move" => {
+                let (from, to) = (index(1), index(2));
+                // The entry takes the place of the one at to
+                let entry = self.entries.remove(from);
+                let to = if from < to { to - 1 } else { to };
+                self.entries.insert(to, entry);
+            }
+            "playlist-remove" => {
+                self.entries.remove(index(1));
+                // Goes on to the next entry if the current one was removed
+                if self.position == Some(index(1)) {
+                    return self.follow(self.entries.get(index(1)).cloned());
+                }
+            }
+            "get_property" => return json!(self.position.map_or(-1, |p| p as i64)),
+            "set_property" if command[1] == "playlist-pos" => {
+                return self.follow(Some(self.entries[index(2)].clone()))
+            }
+            "set_property" => {
+                let name = command[1].as_str().unwrap().to_string();
+                self.properties.insert(name, command[2].clone());
+            }
+            "playlist-next" => {
+                let next = self.position.and_then(|p| self.entries.get(p + 1));
+                return self.follow(next.cloned());
+            }
+            "playlist-prev" => {
+                let previous = self.position.and_then(|p| p.checked_sub(1));
+                return self.follow(previous.map(|p| self.entries[p].clone()));
+            }
+            _ => {}
+        }
+        self.follow(current)
+    }
+
+    // Finds the current entry after the entries have moved
+    fn follow(&mut self, current: Option<String>) -> Value {
+        self.position = current.and_then(|c| self.entries.iter().position(|e| *e == c));
+        Value::Null
+    }
+}
+
+fn fake_player() -> (Player, Arc<Mutex<FakePlaylist>>, String) {
+    let path = socket_path();
+    let playlist = Arc::new(Mutex::new(FakePlaylist::default()));
+    let fake = playlist.clone();
+    fake_mpv(&path, move |request| {
+        let data = fake.lock().unwrap().run(&request["command"]);
+        vec![success(request, data)]
+    });
+    (Player::new(Mpv::new(&path)), playlist, path)
+}
+
+fn song(name: &str) -> Song {
+    Song {
+        song_id: None,
+        name: name.into(),
+        file_path: format!("/music/{}.flac", name),
+        track: None,
+        disc: None,
+        duration_s: None,
+        quality: Quality::Lossless,
+        genre: None,
+        artist: None,
+        album: None,
+        missing: false,
+        audio: Default::default(),
+    }
+}
+
+fn album_song(name: &str, album_id: i64) -> Song {
+    Song {
+        album: Some(Album {
+            album_id: Some(album_id),
+            name: format!("Album {}", album_id),
+            artist: None,
+            cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
+            year: None,
+            total_tracks: None,
+            total_discs: None,
+        }),
+        ..song(name)
+    }
+}
+
+fn songs(names: &[&str]) -> Vec<Song> {
+    names.iter().map(|name| song(name)).collect()
+}
+
+// Checks that the queue has the songs, the one at position playing, and that mpv has the same
+fn assert_queue(queue: PlayQueue, playlist: &Mutex<FakePlaylist>, names: &[&str], position: usize) {
+    let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    let queued: Vec<String> = queue.songs.iter().map(|song| song.name.clone()).collect();
+    assert_eq!(queued, names);
+    assert_eq!(queue.position, Some(position));
+
+    let playlist = playlist.lock().unwrap();
+    let paths: Vec<String> = queue
+        .songs
+        .iter()
+        .map(|song| song.file_path.clone())
+        .collect();
+    assert_eq!(playlist.entries, paths);
+    assert_eq!(playlist.position, Some(position));
+}
+
+#[test]
+fn queue_follows_changes() {
+    let (player, playlist, path) = fake_player();
+
+    // Starts playing when nothing was playing
+    let queue = player.add(songs(&["a", "b"]), PlayMode::Last).unwrap();
+    assert_queue(queue, &playlist, &["a", "b"], 0);
+
+    let queue = player.add(songs(&["c", "d"]), PlayMode::Next).unwrap();
+    assert_queue(queue, &playlist, &["a", "c", "d", "b"], 0);
+
+    let queue = player.insert(0, songs(&["e"])).unwrap();
+    assert_queue(queue, &playlist, &["e", "a", "c", "d", "b"], 1);
+    let queue = player.insert(5, songs(&["f"])).unwrap();
+    assert_queue(queue, &playlist, &["e", "a", "c", "d", "b", "f"], 1);
+
+    // Moving songs forward and back, the current one too
+    let queue = player.move_song(0, 3).unwrap();
+    assert_queue(queue, &playlist, &["a", "c", "d", "e", "b", "f"], 0);
+    let queue = player.move_song(5, 1).unwrap();
+    assert_queue(queue, &playlist, &["a", "f", "c", "d", "e", "b"], 0);
+    let queue = player.move_song(0, 2).unwrap();
+    assert_queue(queue, &playlist, &["f", "c", "a", "d", "e", "b"], 2);
+
+    let queue = player.remove(0).unwrap();
+    assert_queue(queue, &playlist, &["c", "a", "d", "e", "b"], 1);
+    // The song after the removed one takes its place
+    let queue = player.remove(1).unwrap();
+    assert_queue(queue, &playlist, &["c", "d", "e", "b"], 1);
+
+    let queue = player.jump(3).unwrap();
+    assert_queue(queue, &playlist, &["c", "d", "e", "b"], 3);
+    let queue = player.replace_current(song("g")).unwrap();
+    assert_queue(queue, &playlist, &["c", "d", "e", "g"], 3);
+
+    let queue = player.add(songs(&["h"]), PlayMode::Now).unwrap();
+    assert_queue(queue, &playlist, &["h"], 0);
+    let queue = player.replace(songs(&["i", "j", "k"]), 1).unwrap();
+    assert_queue(queue, &playlist, &["i", "j", "k"], 1);
+
+    assert_eq!(player.clear().unwrap(), PlayQueue::default());
+    assert!(playlist.lock().unwrap().entries.is_empty());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn queue_follows_mpv() {
+    let (player, playlist, path) = fake_player();
+    player.replace(songs(&["a", "b", "c"]), 0).unwrap();
+
+    // mpv moving on to the next song by itself
+    playlist.lock().unwrap().position = Some(1);
+    assert_eq!(player.queue().unwrap().position, Some(1));
+    let queue = player.add(songs(&["d"]), PlayMode::Next).unwrap();
+    assert_queue(queue, &playlist, &["a", "b", "d", "c"], 1);
+
+    // The end of the queue
+    playlist.lock().unwrap().position = None;
+    assert_eq!(player.queue().unwrap().position, None);
+    let queue = player.add(songs(&["e"]), PlayMode::Next).unwrap();
+    assert_queue(queue, &playlist, &["a", "b", "d", "c", "e"], 4);
+
+    // What the observer reports shows up in the state
+    let playback = Playback {
+        time_pos: Some(12.5),
+        playlist_pos: Some(4),
+        ..Default::default()
+    };
+    let state = player.set_playback(playback.clone()).unwrap();
+    assert_eq!(state.playback, playback);
+    assert_eq!(player.state().unwrap().playback, playback);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rejects_missing_entries() {
+    let (player, playlist, path) = fake_player();
+    player.add(songs(&["a", "b"]), PlayMode::Last).unwrap();
+
+    let invalid = |result: Result<PlayQueue, Error>| matches!(result, Err(Error::Validation(_)));
+    assert!(invalid(player.replace(songs(&["c"]), 1)));
+    assert!(invalid(player.replace(vec![], 0)));
+    assert!(invalid(player.insert(3, songs(&["c"]))));
+    assert!(invalid(player.move_song(0, 2)));
+    assert!(invalid(player.remove(2)));
+    assert!(invalid(player.jump(2)));
+
+    // Nothing changed
+    assert_queue(player.queue().unwrap(), &playlist, &["a", "b"], 0);
+
+    fs::remove_file(path).unwrap();
+}
+
+fn names(queue: &PlayQueue) -> Vec<String> {
+    queue.songs.iter().map(|song| song.name.clone()).collect()
+}
+
+#[test]
+fn shuffles_upcoming_songs() {
+    let (player, playlist, path) = fake_player();
+    let all: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+    let all: Vec<&str> = all.iter().map(|name| name.as_str()).collect();
+    player.replace(songs(&all), 5).unwrap();
+
+    // The songs up to the current one stay where they are
+    let queue = player.set_shuffle(ShuffleMode::Songs).unwrap();
+    let shuffled = names(&queue);
+    assert_eq!(shuffled[..6], all[..6]);
+    let mut sorted = shuffled.clone();
+    sorted.sort_by_key(|name| name.parse::<usize>().unwrap());
+    assert_eq!(sorted, all);
+    let shuffled: Vec<&str> = shuffled.iter().map(|name| name.as_str()).collect();
+    assert_queue(queue, &playlist, &shuffled, 5);
+
+    // Previous goes back to the song that was played before
+    let queue = player.next().unwrap();
+    assert_eq!(queue.position, Some(6));
+    let queue = player.previous().unwrap();
+    assert_eq!(queue.songs[5].name, "5");
+
+    // Songs queued while shuffling stay after the song they were queued after once shuffle is off
+    player.add(songs(&["next"]), PlayMode::Next).unwrap();
+    player.add(songs(&["last"]), PlayMode::Last).unwrap();
+    let queue = player.set_shuffle(ShuffleMode::Off).unwrap();
+    let mut expected = all.clone();
+    expected.insert(6, "next");
+    expected.push("last");
+    assert_queue(queue, &playlist, &expected, 5);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn shuffles_albums() {
+    let (player, playlist, path) = fake_player();
+    let mut queued = vec![song("single")];
+    for album_id in 1..=4 {
+        for track in 1..=3 {
+            queued.push(album_song(&format!("{}-{}", album_id, track), album_id));
+        }
+    }
+    player.replace(queued, 0).unwrap();
+
+    let queue = player.set_shuffle(ShuffleMode::Albums).unwrap();
+    let shuffled = names(&queue);
+    assert_eq!(shuffled[0], "single");
+    // Every album is in one piece and in order
+    for album in shuffled[1..].chunks(3) {
+        let album_id = &album[0][..1];
+        assert_eq!(
+            album,
+            ["1", "2", "3"].map(|t| format!("{}-{}", album_id, t))
+        );
+    }
+    let shuffled: Vec<&str> = shuffled.iter().map(|name| name.as_str()).collect();
+    assert_queue(queue, &playlist, &shuffled, 0);
+
+    // New queues are shuffled after the song that is played first
+    let queue = player.replace(songs(&["a", "b", "c"]), 1).unwrap();
+    assert_eq!(names(&queue)[..2], ["a", "b"]);
+    assert_eq!(player.state().unwrap().shuffle, ShuffleMode::Albums);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn sets_repeat_mode() {
+    let (player, playlist, path) = fake_player();
+    let loops = || {
+        let playlist = playlist.lock().unwrap();
+        (
+            playlist.properties["loop-file"].clone(),
+            playlist.properties["loop-playlist"].clone(),
+        )
+    };
+
+    let state = player.set_repeat(RepeatMode::One).unwrap();
+    assert_eq!(
+        state,
+        PlayerState {
+            shuffle: ShuffleMode::Off,
+            repeat: RepeatMode::One,
+            playback: Default::default(),
+        }
+    );
+    assert_eq!(loops(), (json!("inf"), json!("no")));
+
+    player.set_repeat(RepeatMode::All).unwrap();
+    assert_eq!(loops(), (json!("no"), json!("inf")));
+    player.set_repeat(RepeatMode::Off).unwrap();
+    assert_eq!(loops(), (json!("no"), json!("no")));
+    assert_eq!(player.state().unwrap().repeat, RepeatMode::Off);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn matches_responses_to_requests() {
+    let path = socket_path();
+    // Events and responses to other requests can come before the response
+    fake_mpv(&path, |request| {
+        let id = request["request_id"].as_u64().unwrap();
+        vec![
+            json!({ "event": "playback-restart" }),
+            json!({ "request_id": id + 100, "error": "success", "data": 1.0 }),
+            success(request, json!(12.5)),
+        ]
+    });
+    let mpv = Mpv::new(&path);
+
+    assert_eq!(mpv.get_property::<f64>("time-pos").unwrap(), 12.5);
+    assert_eq!(mpv.get_property::<f64>("time-pos").unwrap(), 12.5);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn returns_errors() {
+    let path = socket_path();
+    let mpv = Mpv::new(&path);
+
+    // Nothing is listening yet
+    assert!(matches!(mpv.pause(), Err(Error::Playback(_))));
+
+    fake_mpv(&path, |request| {
+        vec![json!({ "request_id": request["request_id"], "error": "property unavailable" })]
+    });
+    assert_eq!(
+        mpv.get_property::<f64>("duration"),
+        Err(Error::Playback(
+            "mpv could not run get_property: property unavailable".into()
+        ))
+    );
+
+    fs::remove_file(path).unwrap();
+}
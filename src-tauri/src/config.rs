@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+pub const DEFAULT_PROFILE: &str = "default";
+pub const DATABASE_ENV_VAR: &str = "MUSICBASE_DB";
+pub const PROFILE_ENV_VAR: &str = "MUSICBASE_PROFILE";
+
+// A named library, each one has its own database and image cache. The scanned directories live in
+// the database so they are per profile as well.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    // Overrides for the default locations under the application data directory
+    #[serde(default)]
+    pub database_path: Option<String>,
+    #[serde(default)]
+    pub image_cache_dir: Option<String>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Profile {
+        Profile {
+            name: name.into(),
+            database_path: None,
+            image_cache_dir: None,
+        }
+    }
+}
+
+// Where the cover of a new album comes from while scanning, the other source is used when the
+// preferred one has nothing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverSource {
+    // The picture in the tags of the audio file
+    #[default]
+    Embedded,
+    // An image file in the same directory as the audio file
+    Folder,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoverConfig {
+    // Names of image files that are album covers without their extension, most wanted first.
+    // Case doesn't matter and a trailing * matches any ending, such as in AlbumArt_{...}_Large.
+    pub folder_names: Vec<String>,
+    pub prefer: CoverSource,
+}
+
+impl Default for CoverConfig {
+    fn default() -> CoverConfig {
+        CoverConfig {
+            folder_names: ["cover", "folder", "front", "albumart*", "album", "thumb"]
+                .map(String::from)
+                .to_vec(),
+            prefer: CoverSource::default(),
+        }
+    }
+}
+
+// Contents of config.json in the application config directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub active_profile: String,
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub covers: CoverConfig,
+    // Whether the library directories are watched for changes while the app is running
+    #[serde(default)]
+    pub watch_library: bool,
+    // How many files a scan reads at the same time, as many as there are cores if not set
+    #[serde(default)]
+    pub scan_threads: Option<usize>,
+
+    // Runtime only, not saved into the file
+    #[serde(skip)]
+    pub data_dir: PathBuf,
+    #[serde(skip)]
+    pub config_file: Option<PathBuf>,
+    // Database path given with the command line flag or environment variable, wins over everything
+    #[serde(skip)]
+    pub database_override: Option<String>,
+}
+
+impl Config {
+    pub fn new(data_dir: &Path) -> Config {
+        Config {
+            active_profile: DEFAULT_PROFILE.into(),
+            profiles: vec![Profile::new(DEFAULT_PROFILE)],
+            covers: CoverConfig::default(),
+            watch_library: false,
+            scan_threads: None,
+            data_dir: data_dir.into(),
+            config_file: None,
+            database_override: None,
+        }
+    }
+
+    // Reads the config file if there is one, otherwise uses the defaults. The file gets written on
+    // the first save.
+    pub fn load(config_file: &Path, data_dir: &Path) -> Config {
+        let mut config = match fs::read_to_string(config_file) {
+            Ok(contents) => match serde_json::from_str::<Config>(&contents) {
+                Ok(config) => config,
+                Err(err) => {
+                    println!("Ignoring malformed config file: {}", err);
+                    Config::new(data_dir)
+                }
+            },
+            Err(_) => Config::new(data_dir),
+        };
+
+        config.data_dir = data_dir.into();
+        config.config_file = Some(config_file.into());
+
+        if config.profiles.is_empty() {
+            config.profiles.push(Profile::new(DEFAULT_PROFILE));
+        }
+        if config.profile(&config.active_profile).is_none() {
+            config.active_profile = config.profiles[0].name.clone();
+        }
+
+        config
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(config_file) = &self.config_file else {
+            return Ok(());
+        };
+
+        if let Some(parent) = config_file.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                return Err(Error::Io(format!(
+                    "Could not create config directory: {}",
+                    err
+                )));
+            }
+        }
+
+        let contents = match serde_json::to_string_pretty(self) {
+            Ok(contents) => contents,
+            Err(err) => {
+                return Err(Error::Config(format!(
+                    "Could not serialize config: {}",
+                    err
+                )))
+            }
+        };
+
+        match fs::write(config_file, contents) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::Io(format!("Could not write config file: {}", err))),
+        }
+    }
+
+    // Applies the command line flags and environment variables on top of the config file. A profile
+    // that doesn't exist yet is created and saved.
+    pub fn apply_overrides(
+        &mut self,
+        args: &[String],
+        vars: &HashMap<String, String>,
+    ) -> Result<(), Error> {
+        self.database_override = arg_value(args, "--db").or(vars.get(DATABASE_ENV_VAR).cloned());
+
+        if let Some(name) = arg_value(args, "--profile").or(vars.get(PROFILE_ENV_VAR).cloned()) {
+            let name = name.trim();
+            if self.profile(name).is_none() {
+                self.add_profile(name)?;
+                self.save()?;
+            }
+            self.active_profile = name.into();
+        }
+
+        Ok(())
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn active(&self) -> Profile {
+        self.profile(&self.active_profile)
+            .cloned()
+            .unwrap_or(Profile::new(&self.active_profile))
+    }
+
+    fn profile_dir(&self, name: &str) -> PathBuf {
+        self.data_dir.join("profiles").join(name)
+    }
+
+    pub fn database_path(&self) -> PathBuf {
+        if let Some(path) = &self.database_override {
+            return path.into();
+        }
+
+        let profile = self.active();
+        match profile.database_path {
+            Some(path) => path.into(),
+            None => self.profile_dir(&profile.name).join("library.db"),
+        }
+    }
+
+    pub fn image_cache_dir(&self) -> PathBuf {
+        let profile = self.active();
+        match profile.image_cache_dir {
+            Some(path) => path.into(),
+            None => self.profile_dir(&profile.name).join("images"),
+        }
+    }
+
+    pub fn add_profile(&mut self, name: &str) -> Result<Profile, Error> {
+        let name = name.trim();
+        validate_profile_name(name)?;
+
+        if self.profile(name).is_some() {
+            return Err(Error::Config(format!("Profile {} already exists", name)));
+        }
+
+        let profile = Profile::new(name);
+        self.profiles.push(profile.clone());
+        Ok(profile)
+    }
+
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), Error> {
+        if self.profile(name).is_none() {
+            return Err(Error::NotFound(format!("No profile named {}", name)));
+        }
+
+        self.active_profile = name.into();
+        // The override only ever applies to the profile the app was started with
+        self.database_override = None;
+        Ok(())
+    }
+
+    // Creates the directories the active profile needs
+    pub fn ensure_dirs(&self) -> Result<(), Error> {
+        let mut dirs = vec![self.image_cache_dir()];
+        if let Some(parent) = self.database_path().parent() {
+            dirs.push(parent.into());
+        }
+
+        for dir in dirs {
+            if let Err(err) = fs::create_dir_all(&dir) {
+                return Err(Error::Io(format!(
+                    "Could not create {}: {}",
+                    dir.to_string_lossy(),
+                    err
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Profile names are used as directory names
+fn validate_profile_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(Error::Validation(format!("Invalid profile name {}", name)));
+    }
+    Ok(())
+}
+
+// Supports both "--flag value" and "--flag=value"
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+
+    for (i, arg) in args.iter().enumerate() {
+        if arg == flag {
+            return args.get(i + 1).cloned();
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Some(value.into());
+        }
+    }
+
+    None
+}
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use crate::{
+    config::{
+        Config, CoverConfig, CoverSource, Profile, DATABASE_ENV_VAR, DEFAULT_PROFILE,
+        PROFILE_ENV_VAR,
+    },
+    fs_utils::get_unique_path,
+};
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn vars(values: &[(&str, &str)]) -> HashMap<String, String> {
+    values
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn default_paths() {
+    let config = Config::new(&PathBuf::from("/data"));
+
+    assert_eq!(config.active_profile, DEFAULT_PROFILE);
+    assert_eq!(
+        config.database_path(),
+        PathBuf::from("/data/profiles/default/library.db")
+    );
+    assert_eq!(
+        config.image_cache_dir(),
+        PathBuf::from("/data/profiles/default/images")
+    );
+}
+
+#[test]
+fn database_flag_overrides_profile() {
+    let mut config = Config::new(&PathBuf::from("/data"));
+    config.profiles[0].database_path = Some("/music/library.db".into());
+    assert_eq!(config.database_path(), PathBuf::from("/music/library.db"));
+
+    let no_vars = vars(&[]);
+    config
+        .apply_overrides(&args(&["musicbase", "--db", "/tmp/test.db"]), &no_vars)
+        .unwrap();
+    assert_eq!(config.database_path(), PathBuf::from("/tmp/test.db"));
+
+    config
+        .apply_overrides(&args(&["musicbase", "--db=/tmp/other.db"]), &no_vars)
+        .unwrap();
+    assert_eq!(config.database_path(), PathBuf::from("/tmp/other.db"));
+
+    // The flag wins over the environment variable
+    let env_db = vars(&[(DATABASE_ENV_VAR, "/tmp/env.db")]);
+    config
+        .apply_overrides(&args(&["musicbase"]), &env_db)
+        .unwrap();
+    assert_eq!(config.database_path(), PathBuf::from("/tmp/env.db"));
+    config
+        .apply_overrides(&args(&["musicbase", "--db", "/tmp/test.db"]), &env_db)
+        .unwrap();
+    assert_eq!(config.database_path(), PathBuf::from("/tmp/test.db"));
+}
+
+#[test]
+fn profile_flag() {
+    let dir = get_unique_path(&env::temp_dir().to_string_lossy(), "d").unwrap();
+    let config_file = PathBuf::from(&dir).join("config.json");
+    let data_dir = PathBuf::from("/data");
+
+    let mut config = Config::load(&config_file, &data_dir);
+    config
+        .apply_overrides(&args(&["musicbase", "--profile", "DJ set"]), &vars(&[]))
+        .unwrap();
+    assert_eq!(config.active_profile, "DJ set");
+    assert_eq!(
+        config.database_path(),
+        PathBuf::from("/data/profiles/DJ set/library.db")
+    );
+
+    // The new profile is saved along with the others
+    let loaded = Config::load(&config_file, &data_dir);
+    assert!(loaded.profile("DJ set").is_some());
+
+    let mut config = Config::load(&config_file, &data_dir);
+    config
+        .apply_overrides(&args(&["musicbase"]), &vars(&[(PROFILE_ENV_VAR, "work")]))
+        .unwrap();
+    assert_eq!(config.active_profile, "work");
+
+    assert!(config
+        .apply_overrides(&args(&["musicbase", "--profile", "../escape"]), &vars(&[]))
+        .is_err());
+    assert_eq!(config.active_profile, "work");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn add_and_switch_profiles() {
+    let mut config = Config::new(&PathBuf::from("/data"));
+    config.database_override = Some("/tmp/test.db".into());
+
+    config.add_profile("home").unwrap();
+    config.add_profile("work").unwrap();
+    assert!(config.add_profile("work").is_err());
+    assert!(config.add_profile("../escape").is_err());
+    assert!(config.add_profile("  ").is_err());
+    assert!(config.switch_profile("does not exist").is_err());
+
+    config.switch_profile("work").unwrap();
+    assert_eq!(config.active(), Profile::new("work"));
+    assert_eq!(
+        config.database_path(),
+        PathBuf::from("/data/profiles/work/library.db")
+    );
+}
+
+#[test]
+fn save_and_load() {
+    let dir = get_unique_path(&env::temp_dir().to_string_lossy(), "d").unwrap();
+    let config_file = PathBuf::from(&dir).join("config.json");
+    let data_dir = PathBuf::from(&dir).join("data");
+
+    let mut config = Config::load(&config_file, &data_dir);
+    assert_eq!(config.profiles, vec![Profile::new(DEFAULT_PROFILE)]);
+
+    config.add_profile("home").unwrap();
+    config.switch_profile("home").unwrap();
+    config.covers.prefer = CoverSource::Folder;
+    config.save().unwrap();
+
+    let loaded = Config::load(&config_file, &data_dir);
+    assert_eq!(loaded.active_profile, "home");
+    assert_eq!(loaded.profiles.len(), 2);
+    assert_eq!(loaded.covers.prefer, CoverSource::Folder);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cover_settings() {
+    let dir = get_unique_path(&env::temp_dir().to_string_lossy(), "d").unwrap();
+    let config_file = PathBuf::from(&dir).join("config.json");
+    fs::create_dir(&dir).unwrap();
+
+    // Config files from before cover settings existed get the defaults
+    fs::write(
+        &config_file,
+        r#"{"active_profile": "default", "profiles": [{"name": "default"}]}"#,
+    )
+    .unwrap();
+    let config = Config::load(&config_file, &PathBuf::from("/data"));
+    assert_eq!(config.covers, CoverConfig::default());
+
+    // Missing fields are filled in
+    fs::write(
+        &config_file,
+        r#"{"active_profile": "default", "profiles": [], "covers": {"prefer": "folder"}}"#,
+    )
+    .unwrap();
+    let config = Config::load(&config_file, &PathBuf::from("/data"));
+    assert_eq!(config.covers.prefer, CoverSource::Folder);
+    assert_eq!(
+        config.covers.folder_names,
+        CoverConfig::default().folder_names
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,239 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+// How much of the beginning and the end of the audio data is hashed. Reading all of it would make
+// the first scan of a large library very slow, the length and two samples are plenty to tell
+// recordings apart.
+const SAMPLE_SIZE: u64 = 256 * 1024;
+
+// Identifies an audio file by its audio data, ignoring the metadata tags around it, so that the
+// hash stays the same when the file is retagged, moved or renamed. Returns None if the file has no
+// audio data to identify it by.
+pub fn content_hash(file_path: &str) -> Result<Option<String>, Error> {
+    let mut file = File::open(file_path)?;
+    let ranges = audio_ranges(&mut file)?;
+    let len: u64 = ranges.iter().map(|(start, end)| end - start).sum();
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(len.to_le_bytes());
+
+    if len <= 2 * SAMPLE_SIZE {
+        hasher.update(read_ranges(&mut file, &ranges, 0, len)?);
+    } else {
+        hasher.update(read_ranges(&mut file, &ranges, 0, SAMPLE_SIZE)?);
+        hasher.update(read_ranges(
+            &mut file,
+            &ranges,
+            len - SAMPLE_SIZE,
+            SAMPLE_SIZE,
+        )?);
+    }
+
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+// Byte ranges of the audio data, skipping ID3v2 tags and FLAC metadata blocks at the start and
+// APEv2 and ID3v1 tags at the end. In WAV, AIFF and MP4 files the tags are kept apart from the
+// audio data, in chunks and boxes of their own. The audio data is in one piece except in Ogg files,
+// where it's split into pages.
+fn audio_ranges(file: &mut File) -> Result<Vec<(u64, u64)>, Error> {
+    let len = file.metadata()?.len();
+    let mut start = 0;
+
+    // There can be more than one ID3v2 tag, and FLAC files can start with one too
+    loop {
+        let header = read_at(file, start, 10)?;
+        if header.len() < 10 || &header[0..3] != b"ID3" {
+            break;
+        }
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        start += 10 + syncsafe(&header[6..10]) + footer;
+    }
+
+    let magic = read_at(file, start, 12)?;
+    if magic.len() == 12 {
+        let container = match (&magic[0..4], &magic[4..8], &magic[8..12]) {
+            (b"RIFF", _, b"WAVE") => Some(find_chunk(file, start + 12, len, b"data", false)?),
+            (b"FORM", _, b"AIFF" | b"AIFC") => {
+                Some(find_chunk(file, start + 12, len, b"SSND", true)?)
+            }
+            (_, b"ftyp", _) => Some(find_box(file, start, len, b"mdat")?),
+            _ => None,
+        };
+        if let Some(range) = container {
+            return Ok(range.into_iter().collect());
+        }
+        if &magic[0..4] == b"OggS" {
+            return ogg_payloads(file, start, len);
+        }
+    }
+
+    if read_at(file, start, 4)? == b"fLaC" {
+        start += 4;
+        loop {
+            let header = read_at(file, start, 4)?;
+            if header.len() < 4 {
+                break;
+            }
+            start += 4 + u64::from_be_bytes([0, 0, 0, 0, 0, header[1], header[2], header[3]]);
+            // The first bit marks the last metadata block
+            if header[0] & 0x80 != 0 {
+                break;
+            }
+        }
+    }
+
+    let mut end = len;
+    if end >= start + 128 && read_at(file, end - 128, 3)? == b"TAG" {
+        end -= 128;
+    }
+    if end >= start + 32 {
+        let footer = read_at(file, end - 32, 32)?;
+        if &footer[0..8] == b"APETAGEX" {
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let has_header = footer[23] & 0x80 != 0;
+            end = end.saturating_sub(size + if has_header { 32 } else { 0 });
+        }
+    }
+
+    Ok(vec![(start.min(len), end.max(start.min(len)))])
+}
+
+// Range of the data of the first chunk with the given id in a RIFF or IFF file, which only differ
+// in the byte order of the chunk sizes
+fn find_chunk(
+    file: &mut File,
+    mut offset: u64,
+    len: u64,
+    id: &[u8],
+    big_endian: bool,
+) -> Result<Option<(u64, u64)>, Error> {
+    loop {
+        let header = read_at(file, offset, 8)?;
+        if header.len() < 8 {
+            return Ok(None);
+        }
+        let size = match big_endian {
+            true => read_u32_be(&header[4..8]),
+            false => u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+        } as u64;
+        if &header[0..4] == id {
+            return Ok(Some((offset + 8, (offset + 8 + size).min(len))));
+        }
+        // Chunks are padded to an even size
+        offset += 8 + size + size % 2;
+    }
+}
+
+// Range of the data of the first top level box of the given type in an MP4 file
+fn find_box(
+    file: &mut File,
+    mut offset: u64,
+    len: u64,
+    box_type: &[u8],
+) -> Result<Option<(u64, u64)>, Error> {
+    loop {
+        let header = read_at(file, offset, 16)?;
+        if header.len() < 8 {
+            return Ok(None);
+        }
+        let (size, header_size) = match read_u32_be(&header[0..4]) {
+            // The box goes on until the end of the file
+            0 => (len - offset, 8),
+            // The size doesn't fit and comes after the type as a 64 bit number
+            1 if header.len() == 16 => {
+                let mut size = [0; 8];
+                size.copy_from_slice(&header[8..16]);
+                (u64::from_be_bytes(size), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_size {
+            return Ok(None);
+        }
+        if &header[4..8] == box_type {
+            return Ok(Some((offset + header_size, (offset + size).min(len))));
+        }
+        offset += size;
+    }
+}
+
+// Payloads of the Ogg pages after the header pages, the Vorbis comments among them. Header pages
+// are at granule position 0, or at -1 when a packet too big for one page goes on to the next page.
+// The audio starts at the first page with a real granule position. The page headers are left out:
+// they have a sequence number and a checksum, which change on every page when the comments grow by
+// a page.
+fn ogg_payloads(file: &mut File, mut offset: u64, len: u64) -> Result<Vec<(u64, u64)>, Error> {
+    let mut payloads = vec![];
+    let mut headers = true;
+    loop {
+        let header = read_at(file, offset, 27)?;
+        if header.len() < 27 || &header[0..4] != b"OggS" {
+            return Ok(payloads);
+        }
+        let segments = read_at(file, offset + 27, header[26] as u64)?;
+        let start = offset + 27 + header[26] as u64;
+        let end = start + segments.iter().map(|size| *size as u64).sum::<u64>();
+
+        let mut granule = [0; 8];
+        granule.copy_from_slice(&header[6..14]);
+        headers = headers && matches!(u64::from_le_bytes(granule), 0 | u64::MAX);
+        if !headers && start < len {
+            payloads.push((start, end.min(len)));
+        }
+        offset = end;
+    }
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// ID3v2 sizes use 7 bits of each byte
+fn syncsafe(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |size, byte| (size << 7) | (*byte & 0x7f) as u64)
+}
+
+// Reads up to len bytes at offset of the audio data, as if the ranges were one piece
+fn read_ranges(
+    file: &mut File,
+    ranges: &[(u64, u64)],
+    mut offset: u64,
+    mut len: u64,
+) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::new();
+    for (start, end) in ranges {
+        let size = end - start;
+        if offset >= size {
+            offset -= size;
+            continue;
+        }
+        let part = (size - offset).min(len);
+        buffer.extend(read_at(file, start + offset, part)?);
+        offset = 0;
+        len -= part;
+        if len == 0 {
+            break;
+        }
+    }
+    Ok(buffer)
+}
+
+// Reads up to len bytes at offset, less if the file ends before that
+fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.by_ref().take(len).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
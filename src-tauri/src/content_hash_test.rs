@@ -0,0 +1,140 @@
+use std::fs;
+
+use lofty::{
+    config::WriteOptions,
+    file::TaggedFileExt,
+    tag::{Accessor, ItemKey, TagExt},
+};
+
+use crate::{content_hash::content_hash, test_utils::get_test_audio_copy};
+
+#[test]
+fn ignores_tags() {
+    let dir = get_test_audio_copy();
+    let path = format!("{}/sample1.mp3", dir);
+    let before = content_hash(&path).unwrap();
+    assert!(before.is_some());
+
+    let mut file = lofty::read_from_path(&path).unwrap();
+    let tag = file.primary_tag_mut().unwrap();
+    tag.set_title("A much longer title than the one the file had before".into());
+    tag.insert_text(ItemKey::AlbumArtist, "Someone else".into());
+    tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+    assert_eq!(content_hash(&path).unwrap(), before);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ignores_tags_in_containers() {
+    let dir = get_test_audio_copy();
+
+    // The tags of these are in chunks or boxes next to the audio data
+    for name in ["sample7.m4a", "sample9.wav", "sample10.aiff"] {
+        let path = format!("{}/{}", dir, name);
+        let before = content_hash(&path).unwrap();
+        assert!(before.is_some(), "{}", name);
+
+        let mut file = lofty::read_from_path(&path).unwrap();
+        let tag = file.first_tag_mut().unwrap();
+        tag.set_title("A much longer title than the one the file had before".into());
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+        assert_eq!(content_hash(&path).unwrap(), before, "{}", name);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ignores_ogg_page_headers() {
+    let dir = get_test_audio_copy();
+
+    // A comment too long for one page moves the audio to later pages, which changes their
+    // sequence numbers and checksums
+    for name in ["sample5.ogg", "sample6.opus"] {
+        let path = format!("{}/{}", dir, name);
+        let before = content_hash(&path).unwrap();
+        assert!(before.is_some(), "{}", name);
+
+        let mut file = lofty::read_from_path(&path).unwrap();
+        let tag = file.primary_tag_mut().unwrap();
+        tag.set_comment("A comment that goes on and on. ".repeat(5000));
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 100_000, "{}", name);
+
+        assert_eq!(content_hash(&path).unwrap(), before, "{}", name);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// Sets the granule position of the pages on which no packet ends to -1, like libogg does. Lofty
+// leaves them at 0.
+fn mark_continued_pages(path: &str) {
+    let mut data = fs::read(path).unwrap();
+    let mut offset = 0;
+    while offset + 27 <= data.len() && &data[offset..offset + 4] == b"OggS" {
+        let segments = data[offset + 26] as usize;
+        let lacing = &data[offset + 27..offset + 27 + segments];
+        let size: usize = lacing.iter().map(|size| *size as usize).sum();
+        if lacing.iter().all(|size| *size == 255) {
+            data[offset + 6..offset + 14].copy_from_slice(&u64::MAX.to_le_bytes());
+        }
+        offset += 27 + segments + size;
+    }
+    fs::write(path, data).unwrap();
+}
+
+#[test]
+fn ignores_ogg_comments_over_many_pages() {
+    let dir = get_test_audio_copy();
+
+    for name in ["sample5.ogg", "sample6.opus"] {
+        let path = format!("{}/{}", dir, name);
+        let before = content_hash(&path).unwrap();
+
+        let mut hashes = vec![];
+        for comment in ["A long comment. ", "An even longer comment. "] {
+            let mut file = lofty::read_from_path(&path).unwrap();
+            let tag = file.primary_tag_mut().unwrap();
+            tag.set_comment(comment.repeat(20000));
+            tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+            // Reading the file again after this would fail on the checksums
+            let copy = format!("{}/copy-{}", dir, name);
+            fs::copy(&path, &copy).unwrap();
+            mark_continued_pages(&copy);
+            hashes.push(content_hash(&copy).unwrap());
+        }
+
+        assert_eq!(hashes, vec![before.clone(), before], "{}", name);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn depends_on_audio_data() {
+    let dir = get_test_audio_copy();
+    let path = format!("{}/sample1.mp3", dir);
+    let before = content_hash(&path).unwrap();
+
+    // Flip a bit in the last byte, which is audio data since the file has no ID3v1 tag
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    fs::write(&path, data).unwrap();
+
+    assert_ne!(content_hash(&path).unwrap(), before);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn no_audio_data() {
+    // The FLAC samples only have metadata blocks
+    assert_eq!(content_hash("test_audio/sample3.flac").unwrap(), None);
+    assert!(content_hash("test_audio/does_not_exist.flac").is_err());
+}
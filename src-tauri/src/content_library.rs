@@ -1,6 +1,19 @@
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use sqlite::State;
+
 use crate::{
-    database::ConnectionWrapper,
-    models::{base_metadata::Song, Quality},
+    database::{self, ConnectionWrapper},
+    error::Error,
+    models::{base_metadata::Song, user_generated::Directory, Quality},
+    param::Order,
+    pool::Pool,
 };
 
 pub fn has_file(file_path: &str, db: &ConnectionWrapper) -> bool {
@@ -15,6 +28,295 @@ pub fn has_file(file_path: &str, db: &ConnectionWrapper) -> bool {
         genre: None,
         artist: None,
         album: None,
+        missing: false,
+        audio: Default::default(),
     })
     .unwrap_or(false)
 }
+
+// What the scanner saw of a file, a file whose state hasn't changed since doesn't need to be
+// read again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileState {
+    // Modification time as a unix timestamp in seconds
+    pub mtime: i64,
+    pub size: i64,
+}
+
+impl FileState {
+    pub fn from_metadata(metadata: &Metadata) -> FileState {
+        FileState {
+            mtime: metadata.modified().map(unix_time).unwrap_or(0),
+            size: metadata.len() as i64,
+        }
+    }
+}
+
+// A song already in the library, state is None if it was added before file states were stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnownFile {
+    pub song_id: i64,
+    pub state: Option<FileState>,
+    pub hashed: bool,
+}
+
+pub fn unix_time(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+pub fn now() -> i64 {
+    unix_time(SystemTime::now())
+}
+
+// Songs whose file is inside dir, keyed by file path
+pub fn get_known_files(
+    db: &ConnectionWrapper,
+    dir: &str,
+) -> Result<HashMap<String, KnownFile>, Error> {
+    let query = "SELECT song_id, file_path, file_mtime, file_size,
+    content_hash IS NOT NULL AS hashed
+    FROM song
+    WHERE substr(file_path, 1, length(:dir)) = :dir";
+    let mut statement = db.conn.prepare(query)?;
+    statement.bind((":dir", dir))?;
+
+    let mut files = HashMap::new();
+    while let Ok(State::Row) = statement.next() {
+        let mtime = statement.read::<Option<i64>, _>("file_mtime")?;
+        let size = statement.read::<Option<i64>, _>("file_size")?;
+        files.insert(
+            statement.read::<String, _>("file_path")?,
+            KnownFile {
+                song_id: statement.read::<i64, _>("song_id")?,
+                state: match (mtime, size) {
+                    (Some(mtime), Some(size)) => Some(FileState { mtime, size }),
+                    _ => None,
+                },
+                hashed: statement.read::<i64, _>("hashed")? != 0,
+            },
+        );
+    }
+    Ok(files)
+}
+
+// Stores the state of the file of a song as seen by the scan at scanned
+pub fn set_file_state(
+    db: &ConnectionWrapper,
+    song_id: i64,
+    state: FileState,
+    scanned: i64,
+) -> Result<(), Error> {
+    let query = "UPDATE song
+    SET file_mtime = :mtime, file_size = :size, last_scanned = :scanned, missing = 0
+    WHERE song_id = :song_id";
+    let mut statement = db.conn.prepare(query)?;
+    statement.bind((":mtime", state.mtime))?;
+    statement.bind((":size", state.size))?;
+    statement.bind((":scanned", scanned))?;
+    statement.bind((":song_id", song_id))?;
+    database::execute_statement(&mut statement)
+}
+
+// Records that a scan found the file of a song unchanged
+pub fn mark_scanned(db: &ConnectionWrapper, song_id: i64, scanned: i64) -> Result<(), Error> {
+    let query = "UPDATE song SET last_scanned = :scanned, missing = 0 WHERE song_id = :song_id";
+    let mut statement = db.conn.prepare(query)?;
+    statement.bind((":scanned", scanned))?;
+    statement.bind((":song_id", song_id))?;
+    database::execute_statement(&mut statement)
+}
+
+pub fn set_content_hash(
+    db: &ConnectionWrapper,
+    song_id: i64,
+    content_hash: Option<&str>,
+) -> Result<(), Error> {
+    database::update_field(db, "song", "content_hash", content_hash, "song_id", song_id)
+}
+
+// Finds the song a new file at file_path was before it was moved or renamed: a song with the same
+// content whose file doesn't exist anymore. Identical copies of a recording share the content, so
+// a song with the same file name wins.
+pub fn find_moved_song(
+    db: &ConnectionWrapper,
+    content_hash: &str,
+    file_path: &str,
+) -> Result<Option<i64>, Error> {
+    let query = "SELECT song_id, file_path FROM song WHERE content_hash = :content_hash";
+    let mut statement = db.conn.prepare(query)?;
+    statement.bind((":content_hash", content_hash))?;
+
+    let mut candidates = vec![];
+    while let Ok(State::Row) = statement.next() {
+        let old_path = statement.read::<String, _>("file_path")?;
+        if !Path::new(&old_path).exists() {
+            candidates.push((statement.read::<i64, _>("song_id")?, old_path));
+        }
+    }
+
+    let file_name = Path::new(file_path).file_name();
+    let same_name = candidates
+        .iter()
+        .find(|(_, old_path)| Path::new(old_path).file_name() == file_name);
+
+    Ok(same_name
+        .or(candidates.first())
+        .map(|(song_id, _)| *song_id))
+}
+
+// Points a song to the new location of its file
+pub fn move_song(db: &ConnectionWrapper, song_id: i64, file_path: &str) -> Result<(), Error> {
+    database::update_field(db, "song", "file_path", file_path, "song_id", song_id)
+}
+
+// What a clean up removed from the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CleanReport {
+    pub songs_removed: usize,
+    // Songs that are gone but kept as placeholders because they are in a playlist
+    pub songs_missing: usize,
+    pub albums_removed: usize,
+    pub artists_removed: usize,
+}
+
+struct LibraryFile {
+    song_id: i64,
+    file_path: String,
+    missing: bool,
+}
+
+// Songs whose files are gone, along with the path that was looked up, or came back. See
+// find_missing_files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MissingFiles {
+    gone: Vec<(i64, String)>,
+    back: Vec<i64>,
+}
+
+// Removes the songs whose file doesn't exist anymore or isn't inside any of the library
+// directories, and then the albums and artists left without songs. Songs in a playlist are flagged
+// missing instead so that the playlist can show a placeholder, the flag is cleared if the file
+// comes back. Songs in a directory that doesn't exist at all are left alone, an unmounted drive is
+// more likely than a deleted library.
+pub fn clean_library(db: &ConnectionWrapper) -> Result<CleanReport, Error> {
+    let missing = find_missing_files(db)?;
+    remove_missing_files(db, &missing)
+}
+
+// Like clean_library, but only holds on to the writer of the pool while removing the songs.
+// Looking at every file can take long on a big library or a network drive.
+pub fn clean_with_pool(pool: &Pool) -> Result<CleanReport, Error> {
+    let missing = find_missing_files(&pool.read()?)?;
+    remove_missing_files(&pool.write()?, &missing)
+}
+
+// Looks up the file of every song, without changing anything
+pub fn find_missing_files(db: &ConnectionWrapper) -> Result<MissingFiles, Error> {
+    let directories: Vec<PathBuf> = db
+        .get_all::<Directory>(Order::Default)?
+        .into_iter()
+        .map(|directory| PathBuf::from(directory.path))
+        .collect();
+
+    let mut missing = MissingFiles::default();
+    for file in get_library_files(db)? {
+        let path = Path::new(&file.file_path);
+        let gone = match directories.iter().find(|dir| path.starts_with(dir)) {
+            Some(dir) if !dir.exists() => continue,
+            Some(_) => !path.exists(),
+            None => true,
+        };
+
+        if gone {
+            missing.gone.push((file.song_id, file.file_path));
+        } else if file.missing {
+            missing.back.push(file.song_id);
+        }
+    }
+    Ok(missing)
+}
+
+// Removes or flags the songs that were found missing. A scan can have moved a song or its file
+// can have come back after the files were looked up, those songs are left alone. Whether a song
+// is in a playlist is checked again for the same reason.
+pub fn remove_missing_files(
+    db: &ConnectionWrapper,
+    missing: &MissingFiles,
+) -> Result<CleanReport, Error> {
+    db.in_transaction(|db| {
+        let mut report = CleanReport::default();
+
+        for song_id in &missing.back {
+            set_missing(db, *song_id, false)?;
+        }
+
+        for (song_id, file_path) in &missing.gone {
+            if Path::new(file_path).exists() {
+                continue;
+            }
+            let mut statement = db.conn.prepare(
+                "SELECT missing,
+                EXISTS (SELECT 1 FROM playlist_song WHERE song_id = :song_id) AS in_playlist
+                FROM song WHERE song_id = :song_id AND file_path = :file_path",
+            )?;
+            statement.bind((":song_id", *song_id))?;
+            statement.bind((":file_path", file_path.as_str()))?;
+            // Removed or moved in the meantime
+            let Ok(State::Row) = statement.next() else {
+                continue;
+            };
+            let flagged = statement.read::<i64, _>("missing")? != 0;
+            let in_playlist = statement.read::<i64, _>("in_playlist")? != 0;
+
+            if in_playlist {
+                if !flagged {
+                    set_missing(db, *song_id, true)?;
+                    report.songs_missing += 1;
+                }
+            } else {
+                let mut statement = db.conn.prepare("DELETE FROM song WHERE song_id = ?")?;
+                statement.bind((1, *song_id))?;
+                database::execute_statement(&mut statement)?;
+                report.songs_removed += 1;
+            }
+        }
+
+        db.conn.execute(
+            "DELETE FROM album WHERE album_id NOT IN (
+                SELECT album_id FROM song WHERE album_id IS NOT NULL
+            )",
+        )?;
+        report.albums_removed = db.conn.change_count();
+
+        db.conn.execute(
+            "DELETE FROM artist
+            WHERE artist_id NOT IN (SELECT artist_id FROM song WHERE artist_id IS NOT NULL)
+            AND artist_id NOT IN (SELECT artist_id FROM album WHERE artist_id IS NOT NULL)",
+        )?;
+        report.artists_removed = db.conn.change_count();
+
+        Ok(report)
+    })
+}
+
+fn get_library_files(db: &ConnectionWrapper) -> Result<Vec<LibraryFile>, Error> {
+    let query = "SELECT song_id, file_path, missing FROM song";
+    let mut statement = db.conn.prepare(query)?;
+
+    let mut files = vec![];
+    while let Ok(State::Row) = statement.next() {
+        files.push(LibraryFile {
+            song_id: statement.read::<i64, _>("song_id")?,
+            file_path: statement.read::<String, _>("file_path")?,
+            missing: statement.read::<i64, _>("missing")? != 0,
+        });
+    }
+    Ok(files)
+}
+
+fn set_missing(db: &ConnectionWrapper, song_id: i64, missing: bool) -> Result<(), Error> {
+    database::update_field(db, "song", "missing", missing as i64, "song_id", song_id)
+}
@@ -0,0 +1,195 @@
+use std::{fs, path::Path};
+
+use crate::{
+    config::CoverConfig,
+    content_library::{
+        clean_library, find_missing_files, move_song, remove_missing_files, CleanReport,
+    },
+    content_scanner::scan_for_new_content,
+    database::ConnectionWrapper,
+    models::{
+        base_metadata::{Album, Artist, Song, SongField},
+        user_generated::{Directory, Playlist, PlaylistSong},
+    },
+    param::{asc, eq, Order},
+    test_utils::{get_image_cache, get_mock_db, get_test_audio_copy},
+};
+
+// A library of the test audio files in a directory of their own, along with the directory of
+// its covers
+fn get_scanned_library() -> (ConnectionWrapper, String, String) {
+    let dir = get_test_audio_copy();
+    let images = get_image_cache();
+    let db = get_mock_db();
+    db.insert(&mut Directory {
+        directory_id: None,
+        path: dir.clone(),
+    })
+    .unwrap();
+    scan_for_new_content(&dir, &db, &images, &CoverConfig::default()).unwrap();
+    (db, dir, images)
+}
+
+fn get_song(db: &ConnectionWrapper, path: &str) -> Option<Song> {
+    db.get_by::<Song>(eq(SongField::FilePath, path), Order::Default)
+        .unwrap()
+        .pop()
+}
+
+fn add_to_playlist(db: &ConnectionWrapper, song: &Song) -> i64 {
+    let mut playlist = Playlist {
+        playlist_id: None,
+        name: "Playlist".into(),
+        desc: "".into(),
+        cover_path: None,
+        created: None,
+        tags: vec![],
+    };
+    db.insert_full(&mut playlist).unwrap();
+    let playlist_id = playlist.playlist_id.unwrap();
+
+    db.insert(&mut PlaylistSong {
+        playlist_song_id: None,
+        song_id: song.song_id.unwrap(),
+        playlist_id,
+        ordering: 0,
+        added: None,
+    })
+    .unwrap();
+    playlist_id
+}
+
+#[test]
+fn nothing_to_clean() {
+    let (db, dir, images) = get_scanned_library();
+
+    assert_eq!(clean_library(&db).unwrap(), CleanReport::default());
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 4);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn removes_deleted_files() {
+    let (db, dir, images) = get_scanned_library();
+    let kept = format!("{}/sample3.flac", dir);
+    let playlist_id = add_to_playlist(&db, &get_song(&db, &kept).unwrap());
+
+    // The other song of its album is still there
+    fs::remove_file(format!("{}/sample1.mp3", dir)).unwrap();
+    // The only song of its album and in a playlist
+    fs::remove_file(&kept).unwrap();
+
+    let report = clean_library(&db).unwrap();
+    assert_eq!(
+        report,
+        CleanReport {
+            songs_removed: 1,
+            songs_missing: 1,
+            albums_removed: 0,
+            artists_removed: 0,
+        }
+    );
+    assert!(get_song(&db, &format!("{}/sample1.mp3", dir)).is_none());
+
+    // The playlist keeps a placeholder with what is known of the song
+    let playlist_songs = db
+        .get_by::<Song>(
+            eq(SongField::PlaylistId, playlist_id),
+            asc(SongField::PlaylistOrdering),
+        )
+        .unwrap();
+    assert_eq!(playlist_songs.len(), 1);
+    assert!(playlist_songs[0].missing);
+    assert_eq!(playlist_songs[0].name, "flac track 1");
+    assert!(playlist_songs[0].album.is_some());
+
+    // Cleaning again changes nothing, the flag is cleared once the file is back
+    assert_eq!(clean_library(&db).unwrap(), CleanReport::default());
+    fs::copy("test_audio/sample3.flac", &kept).unwrap();
+    assert_eq!(clean_library(&db).unwrap(), CleanReport::default());
+    assert!(!get_song(&db, &kept).unwrap().missing);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn checks_playlists_again_when_removing() {
+    let (db, dir, images) = get_scanned_library();
+    let path = format!("{}/sample3.flac", dir);
+    fs::remove_file(&path).unwrap();
+
+    // Added to a playlist after the files were looked up
+    let missing = find_missing_files(&db).unwrap();
+    add_to_playlist(&db, &get_song(&db, &path).unwrap());
+
+    let report = remove_missing_files(&db, &missing).unwrap();
+    assert_eq!(report.songs_removed, 0);
+    assert_eq!(report.songs_missing, 1);
+    assert!(get_song(&db, &path).unwrap().missing);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn keeps_songs_found_again_before_removing() {
+    let (db, dir, images) = get_scanned_library();
+    let moved = format!("{}/sample3.flac", dir);
+    let restored = format!("{}/sample1.mp3", dir);
+    fs::rename(&moved, format!("{}/renamed.flac", dir)).unwrap();
+    fs::remove_file(&restored).unwrap();
+
+    // A scan moves one song and the other file comes back after the files were looked up
+    let missing = find_missing_files(&db).unwrap();
+    let song_id = get_song(&db, &moved).unwrap().song_id.unwrap();
+    move_song(&db, song_id, &format!("{}/renamed.flac", dir)).unwrap();
+    fs::copy("test_audio/sample1.mp3", &restored).unwrap();
+
+    let report = remove_missing_files(&db, &missing).unwrap();
+    assert_eq!(report, CleanReport::default());
+    assert!(get_song(&db, &format!("{}/renamed.flac", dir)).is_some());
+    assert!(get_song(&db, &restored).is_some());
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn removes_songs_of_deleted_directories() {
+    let (db, dir, images) = get_scanned_library();
+    db.conn.execute("DELETE FROM directory").unwrap();
+
+    let report = clean_library(&db).unwrap();
+    assert_eq!(
+        report,
+        CleanReport {
+            songs_removed: 4,
+            songs_missing: 0,
+            albums_removed: 3,
+            artists_removed: 2,
+        }
+    );
+    assert!(db.get_all::<Song>(Order::Default).unwrap().is_empty());
+    assert!(db.get_all::<Album>(Order::Default).unwrap().is_empty());
+    assert!(db.get_all::<Artist>(Order::Default).unwrap().is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn skips_unavailable_directories() {
+    let (db, dir, images) = get_scanned_library();
+
+    // Looks the same as an unmounted drive
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!Path::new(&dir).exists());
+
+    assert_eq!(clean_library(&db).unwrap(), CleanReport::default());
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 4);
+
+    fs::remove_dir_all(images).unwrap();
+}
@@ -1,145 +1,622 @@
-use audiotags::{Picture, Tag};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+    thread,
+};
+
+use lofty::{
+    file::{AudioFile, TaggedFileExt},
+    picture::PictureType,
+    tag::{Accessor, ItemKey, Tag, TagType},
+};
+use serde::Serialize;
 use walkdir::WalkDir;
 
 use crate::{
-    content_library,
-    database::ConnectionWrapper,
+    audio_format::{audio_properties, detect, read_audio_file},
+    config::{CoverConfig, CoverSource},
+    content_hash::content_hash,
+    content_library::{self, FileState, KnownFile},
+    database::{update_song, ConnectionWrapper},
+    error::Error,
     fs_utils::mime_type_to_extension,
-    images::save_cover,
-    models::{
-        base_metadata::{Album, Artist, Song},
-        err, Quality,
-    },
-    utils::IntoOption,
+    images::{find_folder_cover, save_cover},
+    models::base_metadata::{Album, Artist, Song},
+    pool::Pool,
 };
 
-pub struct MetadataResult<'a> {
-    pub song: Song,
-    pub album_cover: Option<Picture<'a>>,
+// How many files are saved per transaction while scanning
+pub const SCAN_CHUNK_SIZE: usize = 500;
+
+// How many files are scanned between progress reports
+pub const PROGRESS_INTERVAL: usize = 25;
+
+// Paths of the original, small and tiny versions of a saved cover
+type CoverPaths = (Option<String>, Option<String>, Option<String>);
+
+// How many files of each kind a scan went through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ScanReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    // Files found at a new path that were matched to their existing song by content
+    pub moved: usize,
+    // Files and directories that couldn't be scanned
+    pub errors: usize,
+    // Whether the scan was stopped before it went through every file
+    pub cancelled: bool,
+}
+
+impl ScanReport {
+    pub fn add(&mut self, other: ScanReport) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+        self.moved += other.moved;
+        self.errors += other.errors;
+        self.cancelled |= other.cancelled;
+    }
+}
+
+// How far along a running scan is
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScanProgress {
+    // The directory being scanned
+    pub directory: String,
+    pub processed: usize,
+    // Audio files found in all the directories of the scan
+    pub total: usize,
+    // Songs that weren't in the library before
+    pub added: usize,
+    pub errors: usize,
+}
+
+// Stops a running scan, it is checked between files. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Everything a scan needs besides the files and the database
+pub struct ScanOptions<'a> {
+    pub image_cache_dir: &'a str,
+    pub covers: &'a CoverConfig,
+    // How many files are read at the same time, see default_threads
+    pub threads: usize,
+    pub cancel: &'a CancelToken,
+    // Called when a directory is started, every PROGRESS_INTERVAL files and when the scan is done
+    pub on_progress: &'a dyn Fn(&ScanProgress),
+}
+
+// A scan in progress over one or more directories
+struct Scan<'a> {
+    options: &'a ScanOptions<'a>,
+    scanned: i64,
+    report: ScanReport,
+    directory: String,
+    processed: usize,
+    total: usize,
+}
+
+impl<'a> Scan<'a> {
+    fn new(options: &'a ScanOptions<'a>, total: usize) -> Scan<'a> {
+        Scan {
+            options,
+            scanned: content_library::now(),
+            report: ScanReport::default(),
+            directory: String::new(),
+            processed: 0,
+            total,
+        }
+    }
+
+    fn start_directory(&mut self, dir: &str) {
+        self.directory = dir.into();
+        self.report_progress();
+    }
+
+    fn file_done(&mut self) {
+        self.processed += 1;
+        if self.processed % PROGRESS_INTERVAL == 0 {
+            self.report_progress();
+        }
+    }
+
+    fn report_progress(&self) {
+        (self.options.on_progress)(&ScanProgress {
+            directory: self.directory.clone(),
+            processed: self.processed,
+            total: self.total,
+            added: self.report.added,
+            errors: self.report.errors,
+        });
+    }
+
+    fn finish(mut self) -> ScanReport {
+        self.report.cancelled = self.options.cancel.is_cancelled();
+        self.report_progress();
+        self.report
+    }
+}
+
+// An audio file found by a scan and the song it already is in the library, if any
+struct FoundFile {
+    path: String,
+    state: FileState,
+    known: Option<KnownFile>,
 }
 
 // Scans a given directory and commits music metadata to database
+//
+// Only new files and files whose modification time or size changed since the last scan are read,
+// changed ones are updated in place. New files that have the same content as a song whose file
+// is gone are taken to be that file moved. Songs are committed in chunks of SCAN_CHUNK_SIZE. Files
+// that can't be read are skipped and counted as errors. If saving fails the chunk in progress is
+// rolled back, everything before it stays.
+//
+// Files are read on a thread per core, see prepare_chunk. The result is the same as reading them
+// one by one.
 pub fn scan_for_new_content(
     dir: &str,
     db: &ConnectionWrapper,
     image_cache_dir: &str,
-) -> Result<(), sqlite::Error> {
-    // Loop over files in a directory recursively
-    for entry in WalkDir::new(dir).into_iter() {
-        let Ok(entry) = entry else { continue; };
-        let path = entry.path().to_string_lossy();
-
-        // Skip over non-audio files and already existing ones
-        if !is_audio(&path) || content_library::has_file(&path, db) {
-            continue;
+    covers: &CoverConfig,
+) -> Result<ScanReport, Error> {
+    let options = ScanOptions {
+        image_cache_dir,
+        covers,
+        threads: default_threads(),
+        cancel: &CancelToken::default(),
+        on_progress: &|_| {},
+    };
+    let files = find_audio_files(dir, db, options.cancel)?;
+
+    let mut scan = Scan::new(&options, files.len());
+    scan.start_directory(dir);
+    for chunk in files.chunks(SCAN_CHUNK_SIZE) {
+        let prepared = prepare_chunk(chunk, db, &options)?;
+        save_chunk(chunk, prepared, db, &mut scan)?;
+    }
+    Ok(scan.finish())
+}
+
+// Scans every directory like scan_for_new_content, reporting progress and stopping when cancelled.
+// Only holds on to the writer of the pool while saving a chunk so that other writes don't have to
+// wait for the whole scan. A directory that fails doesn't stop the others.
+pub fn scan_with_pool(
+    dirs: &[String],
+    pool: &Pool,
+    options: &ScanOptions,
+) -> Result<ScanReport, Error> {
+    // Every directory is walked first so that progress has a total
+    let mut found = vec![];
+    let mut errors = 0;
+    for dir in dirs {
+        match find_audio_files(dir, &pool.read()?, options.cancel) {
+            Ok(files) => found.push((dir, files)),
+            Err(err) => {
+                println!("Error in scanning {}: {}", dir, err);
+                errors += 1;
+            }
         }
+    }
 
-        parse_and_save_metadata(&path, db, image_cache_dir)?;
+    let total = found.iter().map(|(_, files)| files.len()).sum();
+    let mut scan = Scan::new(options, total);
+    scan.report.errors = errors;
+
+    for (dir, files) in found {
+        scan.start_directory(dir);
+        for chunk in files.chunks(SCAN_CHUNK_SIZE) {
+            if options.cancel.is_cancelled() {
+                break;
+            }
+            // Whatever the failed chunk counted was rolled back
+            let before = scan.report;
+            let saved = pool
+                .read()
+                .and_then(|db| prepare_chunk(chunk, &db, options))
+                .and_then(|prepared| save_chunk(chunk, prepared, &pool.write()?, &mut scan));
+            if let Err(err) = saved {
+                println!("Error in scanning {}: {}", dir, err);
+                scan.report = before;
+                scan.report.errors += 1;
+                break;
+            }
+        }
     }
+    Ok(scan.finish())
+}
 
-    Ok(())
+// As many threads as there are cores
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1)
 }
 
-fn is_audio(file_path: &str) -> bool {
-    let audio_file_extensions = [".flac", ".mp3"];
+// Loops over files in a directory recursively, pairing them up with the songs already in the
+// library
+fn find_audio_files(
+    dir: &str,
+    db: &ConnectionWrapper,
+    cancel: &CancelToken,
+) -> Result<Vec<FoundFile>, Error> {
+    let mut known = content_library::get_known_files(db, dir)?;
+
+    Ok(WalkDir::new(dir)
+        .into_iter()
+        .take_while(|_| !cancel.is_cancelled())
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let path = entry.path().to_string_lossy().to_string();
+            // Files already in the library are known to be audio
+            let known = known.remove(&path);
+            if known.is_none() && !is_audio(&path) {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            Some(FoundFile {
+                known,
+                state: FileState::from_metadata(&metadata),
+                path,
+            })
+        })
+        .collect())
+}
+
+// A file as read by a worker thread, without touching the database
+enum ReadFile {
+    // Nothing to read if the file hasn't changed since the last scan. Songs from before content
+    // hashes existed get one the next time around.
+    Unchanged(KnownFile, Option<String>),
+    // The metadata and content hash of a new or changed file
+    Changed(Song, Option<String>),
+}
+
+// The work on a chunk that can be done in parallel, before anything is written
+struct PreparedChunk {
+    // One for every file of the chunk, None for the ones left unread when the scan was cancelled
+    reads: Vec<Option<Result<ReadFile, Error>>>,
+    // Saved covers of the albums that aren't in the library yet, by album name
+    covers: HashMap<String, CoverPaths>,
+}
+
+// Reads the files of a chunk and saves the covers of their new albums on worker threads. Resizing
+// covers is the slowest part of a scan by far.
+//
+// The cover of a new album comes from the first file of the album in the chunk, just like when
+// the files are saved one by one. Albums are told apart by their name when looking for existing
+// ones, see save_metadata.
+fn prepare_chunk(
+    files: &[FoundFile],
+    db: &ConnectionWrapper,
+    options: &ScanOptions,
+) -> Result<PreparedChunk, Error> {
+    let reads = parallel_map(files, options.threads, options.cancel, read_file);
 
-    for ext in audio_file_extensions {
-        if file_path.ends_with(ext) {
-            return true;
+    let mut seen = HashSet::new();
+    let mut new_albums = vec![];
+    for (file, read) in files.iter().zip(&reads) {
+        let Some(Ok(ReadFile::Changed(song, _))) = read else {
+            continue;
+        };
+        let Some(album) = &song.album else { continue };
+        if seen.insert(album.name.clone()) && !db.exists(&mut album.clone())? {
+            new_albums.push((album.name.clone(), file.path.as_str()));
         }
     }
 
-    false
+    let (covers, image_cache_dir) = (options.covers, options.image_cache_dir);
+    let saved = parallel_map(&new_albums, options.threads, options.cancel, |(_, path)| {
+        save_album_cover(path, covers, image_cache_dir)
+    });
+    let covers = new_albums
+        .into_iter()
+        .zip(saved)
+        .filter_map(|((name, _), cover)| Some((name, cover??)))
+        .collect();
+
+    Ok(PreparedChunk { reads, covers })
 }
 
-// Reads the metadata tags of an audio file and saves that metadata
-fn parse_and_save_metadata(
-    file_path: &str,
+// Calls f for every item on up to threads threads, returning the results in the order of the
+// items. Items that weren't started before the scan was cancelled have no result.
+fn parallel_map<T, R, F>(items: &[T], threads: usize, cancel: &CancelToken, f: F) -> Vec<Option<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, items.len().max(1)) {
+            let (sender, next, f) = (sender.clone(), &next, &f);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= items.len() || cancel.is_cancelled() {
+                    return;
+                }
+                if sender.send((index, f(&items[index]))).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(sender);
+
+        for (index, result) in receiver {
+            results[index] = Some(result);
+        }
+    });
+    results
+}
+
+// Saves every file of the chunk in one transaction, each file in a savepoint of its own so that a
+// file that can't be read doesn't take the others with it
+fn save_chunk(
+    files: &[FoundFile],
+    prepared: PreparedChunk,
     db: &ConnectionWrapper,
-    image_cache_dir: &str,
-) -> Result<(), sqlite::Error> {
+    scan: &mut Scan,
+) -> Result<(), Error> {
+    let PreparedChunk { reads, mut covers } = prepared;
+
+    let transaction = db.transaction()?;
+    for (file, read) in files.iter().zip(reads) {
+        if scan.options.cancel.is_cancelled() {
+            break;
+        }
+        let Some(read) = read else { break };
+
+        let savepoint = transaction.savepoint("file")?;
+        match read.and_then(|read| save_file(file, read, db, &mut covers, scan.scanned)) {
+            Ok(report) => {
+                savepoint.commit()?;
+                scan.report.add(report);
+            }
+            Err(err @ (Error::Tag(_) | Error::Io(_) | Error::Image(_))) => {
+                savepoint.rollback()?;
+                println!("Error in scanning {}: {}", file.path, err);
+                scan.report.errors += 1;
+            }
+            Err(err) => return Err(err),
+        }
+        scan.file_done();
+    }
+    transaction.commit()
+}
+
+// Reads what's needed of a file, see ReadFile
+fn read_file(file: &FoundFile) -> Result<ReadFile, Error> {
+    Ok(match file.known {
+        Some(known) if known.state == Some(file.state) => {
+            let hash = if known.hashed {
+                None
+            } else {
+                content_hash(&file.path)?
+            };
+            ReadFile::Unchanged(known, hash)
+        }
+        _ => ReadFile::Changed(read_metadata(&file.path)?, content_hash(&file.path)?),
+    })
+}
+
+// Saves one file, returning what kind of file it was
+fn save_file(
+    file: &FoundFile,
+    read: ReadFile,
+    db: &ConnectionWrapper,
+    covers: &mut HashMap<String, CoverPaths>,
+    scanned: i64,
+) -> Result<ScanReport, Error> {
+    let mut report = ScanReport::default();
+    let (mut song, hash) = match read {
+        ReadFile::Unchanged(known, hash) => {
+            content_library::mark_scanned(db, known.song_id, scanned)?;
+            if !known.hashed {
+                content_library::set_content_hash(db, known.song_id, hash.as_deref())?;
+            }
+            report.unchanged += 1;
+            return Ok(report);
+        }
+        ReadFile::Changed(song, hash) => (song, hash),
+    };
+
+    song.song_id = match file.known {
+        Some(known) => {
+            report.updated += 1;
+            Some(known.song_id)
+        }
+        // A file at a new path can be one that was moved or renamed, it keeps its song so that
+        // playlists still have it
+        None => {
+            let moved = match &hash {
+                Some(hash) => content_library::find_moved_song(db, hash, &file.path)?,
+                None => None,
+            };
+            match moved {
+                Some(song_id) => {
+                    content_library::move_song(db, song_id, &file.path)?;
+                    report.moved += 1;
+                }
+                None => report.added += 1,
+            }
+            moved
+        }
+    };
+
+    let song_id = save_metadata(&mut song, db, covers)?;
+    content_library::set_file_state(db, song_id, file.state, scanned)?;
+    content_library::set_content_hash(db, song_id, hash.as_deref())?;
+    Ok(report)
+}
+
+// Audio files are told apart by their content, not by their extension
+fn is_audio(file_path: &str) -> bool {
+    matches!(detect(file_path), Ok(Some(_)))
+}
+
+// Reads the metadata tags of an audio file into a song that isn't saved yet
+fn read_metadata(file_path: &str) -> Result<Song, Error> {
     // Get metadata tags
-    let Ok(tag) = Tag::new().read_from_path(file_path) else {
-        return err("Could not get audio file metadata");
+    let (file, format) = match read_audio_file(file_path) {
+        Ok(file) => file,
+        Err(error) => {
+            return Err(Error::Tag(format!(
+                "Could not get audio file metadata of {}: {}",
+                file_path,
+                error.message()
+            )))
+        }
     };
+    // Files without tags are saved with what can be told from the file itself
+    let no_tag = Tag::new(TagType::Id3v2);
+    let tag = file
+        .primary_tag()
+        .or_else(|| file.first_tag())
+        .unwrap_or(&no_tag);
 
     // Convert the tag objects into our database model objects
-    let artist_name = tag.artist();
-    let artist = if let Some(name) = artist_name {
-        Some(Artist {
-            artist_id: None,
-            name: name.into(),
-            artist_image_path: None,
-        })
-    } else {
-        None
-    };
+    let artist = tag.artist().map(|name| Artist {
+        artist_id: None,
+        name: name.into(),
+        artist_image_path: None,
+    });
 
     // Use album artist, if that doesn't exist use the song artist instead
-    let album_artist_name = tag.album_artist();
-    let album_artist = if let Some(name) = album_artist_name {
-        Some(Artist {
+    let album_artist = match tag.get_string(&ItemKey::AlbumArtist) {
+        Some(name) => Some(Artist {
             artist_id: None,
             name: name.into(),
             artist_image_path: None,
-        })
-    } else {
-        artist.clone()
+        }),
+        None => artist.clone(),
     };
 
-    let album_name = tag.album_title();
-    let album = if let Some(name) = album_name {
-        Some(Album {
-            album_id: None,
-            name: name.into(),
-            cover_path: None,
-            cover_path_small: None,
-            cover_path_tiny: None,
-            year: tag.year().option_into(),
-            total_tracks: tag.total_tracks().option_into(),
-            total_discs: tag.total_discs().option_into(),
-            artist: album_artist,
-        })
-    } else {
-        None
-    };
-    let mut song = Song {
+    let album = tag.album().map(|name| Album {
+        album_id: None,
+        name: name.into(),
+        cover_path: None,
+        cover_path_small: None,
+        cover_path_tiny: None,
+        year: tag.year().map(i64::from),
+        total_tracks: tag.track_total().map(i64::from),
+        total_discs: tag.disk_total().map(i64::from),
+        artist: album_artist,
+    });
+    Ok(Song {
         song_id: None,
-        name: get_str(tag.title()),
-        track: tag.track_number(),
-        duration_s: tag.duration(),
-        quality: if file_path.ends_with(".flac") {
-            Quality::Lossless
-        } else {
-            Quality::Lossy
-        },
-        genre: tag.genre().option_into(),
+        name: get_str(tag.title().as_deref()),
+        track: tag.track().map(|track| track as u16),
+        duration_s: Some(file.properties().duration().as_secs_f64()),
+        quality: format.quality,
+        audio: audio_properties(&file, format),
+        genre: tag.genre().map(String::from),
         artist,
         album,
-        disc: tag.disc_number(),
+        disc: tag.disk().map(|disc| disc as u16),
         file_path: file_path.into(),
-    };
+        missing: false,
+    })
+}
 
+// Saves a song, updating it if it has an id. New albums get their cover from covers, the ones
+// saved by prepare_chunk. Returns the id of the saved song.
+fn save_metadata(
+    song: &mut Song,
+    db: &ConnectionWrapper,
+    covers: &mut HashMap<String, CoverPaths>,
+) -> Result<i64, Error> {
     // Save cover art into app data directory
     if let Some(album) = &mut song.album {
         if !db.exists(album)? {
-            if let Some(image) = tag.album_cover() {
+            if let Some(cover) = covers.remove(&album.name) {
                 (
                     album.cover_path,
                     album.cover_path_small,
                     album.cover_path_tiny,
-                ) = save_cover(
-                    image.data,
-                    mime_type_to_extension(image.mime_type),
-                    image_cache_dir,
-                );
+                ) = cover;
             }
         }
     }
 
     // We're done! :3
-    db.insert_full(&mut song)?;
-    Ok(())
+    match song.song_id {
+        Some(_) => update_song(db, song)?,
+        None => db.insert_full(song)?,
+    }
+    song.song_id.ok_or(Error::Database(format!(
+        "Could not save {}",
+        song.file_path
+    )))
+}
+
+// Saves the cover of the album of an audio file, from its tags or the image files next to it
+// whichever the config prefers
+fn save_album_cover(
+    file_path: &str,
+    covers: &CoverConfig,
+    image_cache_dir: &str,
+) -> Option<CoverPaths> {
+    let embedded = || save_embedded_cover(file_path, image_cache_dir);
+    let folder = || save_folder_cover(file_path, &covers.folder_names, image_cache_dir);
+    match covers.prefer {
+        CoverSource::Embedded => embedded().or_else(folder),
+        CoverSource::Folder => folder().or_else(embedded),
+    }
+}
+
+// Saves the front cover in the tags, or any picture if there's no front cover
+fn save_embedded_cover(file_path: &str, image_cache_dir: &str) -> Option<CoverPaths> {
+    let (file, _) = read_audio_file(file_path).ok()?;
+    let tag = file.primary_tag().or_else(|| file.first_tag())?;
+    let image = tag
+        .get_picture_type(PictureType::CoverFront)
+        .or_else(|| tag.pictures().first())?;
+    let cover = save_cover(
+        image.data(),
+        mime_type_to_extension(image.mime_type()),
+        image_cache_dir,
+    );
+    cover.0.is_some().then_some(cover)
+}
+
+// Saves a cover image found next to the audio file
+fn save_folder_cover(
+    file_path: &str,
+    names: &[String],
+    image_cache_dir: &str,
+) -> Option<CoverPaths> {
+    let path = find_folder_cover(Path::new(file_path).parent()?, names)?;
+    let extension = path.extension()?.to_str()?;
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(err) => {
+            println!("Could not read cover {}: {}", path.to_string_lossy(), err);
+            return None;
+        }
+    };
+    let cover = save_cover(&data, extension, image_cache_dir);
+    cover.0.is_some().then_some(cover)
 }
 
 // "Flatten" a string option into a string
@@ -1,13 +1,73 @@
+use std::{
+    cell::RefCell,
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use lofty::{
+    config::WriteOptions,
+    file::TaggedFileExt,
+    picture::{MimeType, Picture, PictureType},
+    tag::{Accessor, TagExt},
+};
+
 use crate::{
-    content_scanner::scan_for_new_content,
+    config::{CoverConfig, CoverSource},
+    content_scanner::{
+        scan_for_new_content, scan_with_pool, CancelToken, ScanOptions, ScanProgress, ScanReport,
+    },
+    database::ConnectionWrapper,
     models::{
-        base_metadata::{Album, Artist, Song},
-        Quality,
+        base_metadata::{Album, Artist, Song, SongField},
+        user_generated::{Playlist, PlaylistSong},
+        AudioProperties, Codec, Quality,
     },
-    param::Order,
-    test_utils::get_mock_db,
+    param::{eq, Order},
+    pool::Pool,
+    test_utils::{get_image_cache, get_mock_db, get_test_audio_copy},
 };
 
+fn scan(dir: &str, db: &ConnectionWrapper, image_cache_dir: &str) -> ScanReport {
+    scan_for_new_content(dir, db, image_cache_dir, &CoverConfig::default()).unwrap()
+}
+
+fn get_song(db: &ConnectionWrapper, path: &str) -> Option<Song> {
+    db.get_by::<Song>(eq(SongField::FilePath, path), Order::Default)
+        .unwrap()
+        .pop()
+}
+
+// Writes an image of the given width and returns its contents
+fn write_image(path: &str, width: u32) -> Vec<u8> {
+    image::RgbImage::new(width, 1).save(path).unwrap();
+    fs::read(path).unwrap()
+}
+
+fn album_cover(db: &ConnectionWrapper, path: &str) -> Option<Vec<u8>> {
+    let album = get_song(db, path)?.album?;
+    Some(fs::read(album.cover_path?).unwrap())
+}
+
+// Ids depend on the order the files are found in, durations and bitrates are estimated for some
+// formats
+fn comparable(mut song: Song) -> Song {
+    song.song_id = None;
+    song.audio.bitrate_kbps = None;
+    song.duration_s = song
+        .duration_s
+        .map(|duration| (duration * 10.0).round() / 10.0);
+    if let Some(artist) = &mut song.artist {
+        artist.artist_id = None;
+    }
+    if let Some(album) = &mut song.album {
+        album.album_id = None;
+        if let Some(artist) = &mut album.artist {
+            artist.artist_id = None;
+        }
+    }
+    song
+}
+
 #[test]
 fn works() {
     let expected_songs = vec![
@@ -17,7 +77,7 @@ fn works() {
             file_path: "test_audio/sample1.mp3".into(),
             track: Some(1),
             disc: None,
-            duration_s: None,
+            duration_s: Some(0.0),
             quality: Quality::Lossy,
             genre: Some("Nothing".into()),
             artist: Some(Artist {
@@ -34,10 +94,20 @@ fn works() {
                     artist_image_path: None,
                 }),
                 cover_path: None,
+                cover_path_small: None,
+                cover_path_tiny: None,
                 year: Some(2024),
                 total_tracks: None,
                 total_discs: None,
             }),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Mp3),
+                bitrate_kbps: None,
+                sample_rate_hz: Some(44100),
+                bit_depth: None,
+                channels: Some(2),
+            },
         },
         Song {
             song_id: Some(2),
@@ -62,10 +132,20 @@ fn works() {
                     artist_image_path: None,
                 }),
                 cover_path: None,
+                cover_path_small: None,
+                cover_path_tiny: None,
                 year: Some(1990),
                 total_tracks: None,
                 total_discs: None,
             }),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Flac),
+                bitrate_kbps: None,
+                sample_rate_hz: Some(44100),
+                bit_depth: Some(16),
+                channels: Some(2),
+            },
         },
         Song {
             song_id: Some(3),
@@ -73,7 +153,7 @@ fn works() {
             file_path: "test_audio/sample2.mp3".into(),
             track: Some(2),
             disc: None,
-            duration_s: None,
+            duration_s: Some(0.0),
             quality: Quality::Lossy,
             genre: Some("Nothing".into()),
             artist: Some(Artist {
@@ -90,10 +170,20 @@ fn works() {
                     artist_image_path: None,
                 }),
                 cover_path: None,
+                cover_path_small: None,
+                cover_path_tiny: None,
                 year: Some(2024),
                 total_tracks: None,
                 total_discs: None,
             }),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Mp3),
+                bitrate_kbps: None,
+                sample_rate_hz: Some(44100),
+                bit_depth: None,
+                channels: Some(2),
+            },
         },
         Song {
             song_id: Some(4),
@@ -118,19 +208,549 @@ fn works() {
                     artist_image_path: None,
                 }),
                 cover_path: None,
+                cover_path_small: None,
+                cover_path_tiny: None,
                 year: Some(1980),
                 total_tracks: None,
                 total_discs: None,
             }),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Flac),
+                bitrate_kbps: None,
+                sample_rate_hz: Some(44100),
+                bit_depth: Some(16),
+                channels: Some(2),
+            },
         },
     ];
 
+    let images = get_image_cache();
+    let db = get_mock_db();
+    scan("test_audio/", &db, &images);
+
+    for expected in expected_songs {
+        let song = get_song(&db, &expected.file_path).unwrap();
+        assert_eq!(comparable(song), comparable(expected));
+    }
+
+    // Songs with the same artist or album share them
+    let mp3_songs = ["test_audio/sample1.mp3", "test_audio/sample2.mp3"]
+        .map(|path| get_song(&db, path).unwrap());
+    assert_eq!(mp3_songs[0].album, mp3_songs[1].album);
+    assert_eq!(db.get_all::<Artist>(Order::Default).unwrap().len(), 6);
+    assert_eq!(db.get_all::<Album>(Order::Default).unwrap().len(), 7);
+
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn reads_every_format() {
+    use Quality::{Lossless, Lossy};
+
+    let images = get_image_cache();
+    let db = get_mock_db();
+    scan("test_audio/", &db, &images);
+
+    let expected = [
+        ("sample5.ogg", "vorbis track", Lossy, "ogg artist", 1),
+        ("sample6.opus", "opus track", Lossy, "ogg artist", 2),
+        ("sample7.m4a", "aac track", Lossy, "mp4 artist", 1),
+        ("sample8.m4a", "alac track", Lossless, "mp4 artist", 2),
+        ("sample9.wav", "wav track", Lossless, "pcm artist", 1),
+        ("sample10.aiff", "aiff track", Lossless, "pcm artist", 2),
+        ("sample11.wv", "wv track", Lossless, "wv artist", 1),
+    ];
+    for (file, name, quality, artist, track) in expected {
+        let song = get_song(&db, &format!("test_audio/{}", file)).unwrap();
+        assert_eq!(song.name, name);
+        assert_eq!(song.quality, quality, "{}", file);
+        assert_eq!(song.artist.unwrap().name, artist);
+        assert_eq!(song.track, Some(track), "{}", file);
+        assert_eq!(song.genre, Some("Nothing".into()), "{}", file);
+        assert!(song.album.unwrap().year.is_some(), "{}", file);
+    }
+
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn reads_audio_properties() {
+    let images = get_image_cache();
     let db = get_mock_db();
-    scan_for_new_content("test_audio/", &db, None).unwrap();
+    scan("test_audio/", &db, &images);
 
-    let db_songs = db.get_all::<Song>(Order::Default).unwrap();
-    for target in db_songs {
-        println!("{:?}", target);
-        assert!(expected_songs.contains(&target))
+    // The WAV and AIFF samples are 1000 frames long, the others 0.1 seconds
+    let frames = 1000.0 / 44100.0;
+    let expected = [
+        ("sample5.ogg", Codec::Vorbis, 44100, None, 0.1),
+        ("sample6.opus", Codec::Opus, 48000, None, 0.1),
+        ("sample7.m4a", Codec::Aac, 44100, None, 0.1),
+        ("sample8.m4a", Codec::Alac, 44100, Some(16), 0.1),
+        ("sample9.wav", Codec::Pcm, 44100, Some(16), frames),
+        ("sample10.aiff", Codec::Pcm, 44100, Some(16), frames),
+        ("sample11.wv", Codec::WavPack, 44100, Some(16), 0.1),
+    ];
+    for (file, codec, sample_rate, bit_depth, duration) in expected {
+        let song = get_song(&db, &format!("test_audio/{}", file)).unwrap();
+        assert_eq!(song.audio.codec, Some(codec), "{}", file);
+        assert_eq!(song.audio.sample_rate_hz, Some(sample_rate), "{}", file);
+        assert_eq!(song.audio.bit_depth, bit_depth, "{}", file);
+        assert_eq!(song.audio.channels, Some(2), "{}", file);
+        // Read from the stream, not from the tags
+        let difference = song.duration_s.unwrap() - duration;
+        assert!(difference.abs() < 0.002, "{}", file);
     }
+
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn detects_formats_by_content() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+
+    // Wrong, missing and upper case extensions don't matter
+    fs::rename(
+        format!("{}/sample7.m4a", dir),
+        format!("{}/sample7.mp3", dir),
+    )
+    .unwrap();
+    fs::rename(format!("{}/sample9.wav", dir), format!("{}/sample9", dir)).unwrap();
+    fs::rename(
+        format!("{}/sample3.flac", dir),
+        format!("{}/sample3.FLAC", dir),
+    )
+    .unwrap();
+    // Neither do they for files that aren't audio
+    fs::write(format!("{}/notes.mp3", dir), "Not audio").unwrap();
+    fs::write(format!("{}/cover.jpg", dir), [0xff, 0xd8, 0xff, 0xdb]).unwrap();
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(report.added, 11);
+
+    let song = get_song(&db, &format!("{}/sample7.mp3", dir)).unwrap();
+    assert_eq!(song.name, "aac track");
+    assert_eq!(song.quality, Quality::Lossy);
+    let song = get_song(&db, &format!("{}/sample9", dir)).unwrap();
+    assert_eq!(song.name, "wav track");
+    assert_eq!(song.quality, Quality::Lossless);
+    let song = get_song(&db, &format!("{}/sample3.FLAC", dir)).unwrap();
+    assert_eq!(song.quality, Quality::Lossless);
+    assert!(get_song(&db, &format!("{}/notes.mp3", dir)).is_none());
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn rescan_skips_unchanged_files() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(
+        report,
+        ScanReport {
+            added: 11,
+            updated: 0,
+            unchanged: 0,
+            moved: 0,
+            errors: 0,
+            cancelled: false,
+        }
+    );
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(
+        report,
+        ScanReport {
+            added: 0,
+            updated: 0,
+            unchanged: 11,
+            moved: 0,
+            errors: 0,
+            cancelled: false,
+        }
+    );
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn rescan_updates_changed_files() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+    scan(&dir, &db, &images);
+
+    let path = format!("{}/sample3.flac", dir);
+    let before = db
+        .get_by::<Song>(eq(SongField::FilePath, path.clone()), Order::Default)
+        .unwrap();
+
+    let mut file = lofty::read_from_path(&path).unwrap();
+    let tag = file.primary_tag_mut().unwrap();
+    tag.set_title("Retagged".into());
+    tag.set_year(2001);
+    tag.save_to_path(&path, WriteOptions::default()).unwrap();
+    // Make sure the change is visible even if it happened within the same second as the scan
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(
+        report,
+        ScanReport {
+            added: 0,
+            updated: 1,
+            unchanged: 10,
+            moved: 0,
+            errors: 0,
+            cancelled: false,
+        }
+    );
+
+    // Updated in place, the song keeps its id
+    let after = db
+        .get_by::<Song>(eq(SongField::FilePath, path), Order::Default)
+        .unwrap();
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].song_id, before[0].song_id);
+    assert_eq!(after[0].name, "Retagged");
+    assert_eq!(after[0].album.as_ref().unwrap().year, Some(2001));
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn rescan_recognizes_moved_files() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+    scan(&dir, &db, &images);
+
+    let old_path = format!("{}/sample1.mp3", dir);
+    let song_id = get_song(&db, &old_path).unwrap().song_id.unwrap();
+    let mut playlist = Playlist {
+        playlist_id: None,
+        name: "Playlist".into(),
+        desc: "".into(),
+        cover_path: None,
+        created: None,
+        tags: vec![],
+    };
+    db.insert_full(&mut playlist).unwrap();
+    db.insert(&mut PlaylistSong {
+        playlist_song_id: None,
+        song_id,
+        playlist_id: playlist.playlist_id.unwrap(),
+        ordering: 0,
+        added: None,
+    })
+    .unwrap();
+
+    fs::create_dir(format!("{}/moved", dir)).unwrap();
+    let new_path = format!("{}/moved/renamed.mp3", dir);
+    fs::rename(&old_path, &new_path).unwrap();
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(
+        report,
+        ScanReport {
+            added: 0,
+            updated: 0,
+            unchanged: 10,
+            moved: 1,
+            errors: 0,
+            cancelled: false,
+        }
+    );
+
+    assert!(get_song(&db, &old_path).is_none());
+    assert_eq!(get_song(&db, &new_path).unwrap().song_id, Some(song_id));
+    let playlist_songs = db
+        .get_by::<Song>(
+            eq(SongField::PlaylistId, playlist.playlist_id.unwrap()),
+            Order::Default,
+        )
+        .unwrap();
+    assert_eq!(playlist_songs.len(), 1);
+    assert_eq!(playlist_songs[0].file_path, new_path);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn moved_copies_keep_their_own_songs() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+    scan(&dir, &db, &images);
+
+    // Both files have the same audio data, the file names tell them apart
+    fs::create_dir(format!("{}/moved", dir)).unwrap();
+    let mut song_ids = vec![];
+    for name in ["sample1.mp3", "sample2.mp3"] {
+        let old_path = format!("{}/{}", dir, name);
+        song_ids.push(get_song(&db, &old_path).unwrap().song_id);
+        fs::rename(&old_path, format!("{}/moved/{}", dir, name)).unwrap();
+    }
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(report.moved, 2);
+    assert_eq!(report.added, 0);
+
+    for (name, song_id) in ["sample1.mp3", "sample2.mp3"].iter().zip(song_ids) {
+        let song = get_song(&db, &format!("{}/moved/{}", dir, name)).unwrap();
+        assert_eq!(song.song_id, song_id);
+    }
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn uses_folder_covers() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+
+    // Names earlier in the list win, whatever their case or format
+    write_image(&format!("{}/folder.png", dir), 2);
+    let cover = write_image(&format!("{}/Cover.PNG", dir), 3);
+    write_image(&format!("{}/back.png", dir), 4);
+
+    scan(&dir, &db, &images);
+    for album in db.get_all::<Album>(Order::Default).unwrap() {
+        let cover_path = album.cover_path.unwrap();
+        assert_eq!(fs::read(cover_path).unwrap(), cover);
+        assert!(album.cover_path_small.is_some());
+        assert!(album.cover_path_tiny.is_some());
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn prefers_cover_source() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let path = format!("{}/sample3.flac", dir);
+    let embedded = write_image(&format!("{}/embedded.png", dir), 2);
+    let folder = write_image(&format!("{}/front.png", dir), 3);
+
+    let mut file = lofty::read_from_path(&path).unwrap();
+    let tag = file.primary_tag_mut().unwrap();
+    tag.push_picture(Picture::new_unchecked(
+        PictureType::CoverFront,
+        Some(MimeType::Png),
+        None,
+        embedded.clone(),
+    ));
+    tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+    let db = get_mock_db();
+    scan(&dir, &db, &images);
+    assert_eq!(album_cover(&db, &path), Some(embedded));
+    // Albums without embedded art still get the one in their folder
+    let mp3_path = format!("{}/sample1.mp3", dir);
+    assert_eq!(album_cover(&db, &mp3_path), Some(folder.clone()));
+
+    let db = get_mock_db();
+    let covers = CoverConfig {
+        prefer: CoverSource::Folder,
+        ..Default::default()
+    };
+    scan_for_new_content(&dir, &db, &images, &covers).unwrap();
+    assert_eq!(album_cover(&db, &path), Some(folder));
+
+    // Nothing to fall back to without matching images
+    let db = get_mock_db();
+    let covers = CoverConfig {
+        folder_names: vec!["albumart*".into()],
+        prefer: CoverSource::Folder,
+    };
+    scan_for_new_content(&dir, &db, &images, &covers).unwrap();
+    assert!(album_cover(&db, &mp3_path).is_none());
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn skips_unreadable_files() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    let db = get_mock_db();
+
+    // Looks like a FLAC file but ends before its first metadata block
+    fs::write(format!("{}/broken.flac", dir), b"fLaC\0\0").unwrap();
+
+    let report = scan(&dir, &db, &images);
+    assert_eq!(report.added, 11);
+    assert_eq!(report.errors, 1);
+    assert!(get_song(&db, &format!("{}/broken.flac", dir)).is_none());
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn reports_progress() {
+    let images = get_image_cache();
+    let dirs = [get_test_audio_copy(), get_test_audio_copy()];
+    let pool = Pool::single(get_mock_db());
+    let progress = RefCell::new(vec![]);
+    let options = ScanOptions {
+        image_cache_dir: &images,
+        covers: &CoverConfig::default(),
+        threads: 4,
+        cancel: &CancelToken::default(),
+        on_progress: &|p: &ScanProgress| progress.borrow_mut().push(p.clone()),
+    };
+
+    let report = scan_with_pool(&dirs, &pool, &options).unwrap();
+    assert_eq!(report.added, 22);
+    assert!(!report.cancelled);
+
+    // Every directory is counted before the first one is scanned
+    let progress = progress.into_inner();
+    assert_eq!(
+        progress.first(),
+        Some(&ScanProgress {
+            directory: dirs[0].clone(),
+            processed: 0,
+            total: 22,
+            added: 0,
+            errors: 0,
+        })
+    );
+    assert_eq!(
+        progress.last(),
+        Some(&ScanProgress {
+            directory: dirs[1].clone(),
+            processed: 22,
+            total: 22,
+            added: 22,
+            errors: 0,
+        })
+    );
+    assert!(progress
+        .windows(2)
+        .all(|w| w[0].processed <= w[1].processed));
+
+    for dir in dirs {
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn cancels_between_files() {
+    let images = get_image_cache();
+    let dirs = [get_test_audio_copy(), get_test_audio_copy()];
+    let pool = Pool::single(get_mock_db());
+    let cancel = CancelToken::default();
+    let second = dirs[1].clone();
+    let options = ScanOptions {
+        image_cache_dir: &images,
+        covers: &CoverConfig::default(),
+        threads: 1,
+        cancel: &cancel,
+        // Stop as soon as the second directory starts
+        on_progress: &|p: &ScanProgress| {
+            if p.directory == second {
+                cancel.cancel();
+            }
+        },
+    };
+
+    let report = scan_with_pool(&dirs, &pool, &options).unwrap();
+    assert!(report.cancelled);
+    assert_eq!(report.added, 11);
+    let db = pool.read().unwrap();
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+    drop(db);
+
+    // A cancelled token stops the scan before it starts
+    let report = scan_with_pool(&dirs, &pool, &options).unwrap();
+    assert!(report.cancelled);
+    assert_eq!(report.added + report.unchanged, 0);
+
+    for dir in dirs {
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fs::remove_dir_all(images).unwrap();
+}
+
+#[test]
+fn parallel_scans_match_sequential_ones() {
+    let images = get_image_cache();
+    let dir = get_test_audio_copy();
+    write_image(&format!("{}/cover.png", dir), 2);
+
+    let scan_with_threads = |threads| {
+        let pool = Pool::single(get_mock_db());
+        let options = ScanOptions {
+            image_cache_dir: &images,
+            covers: &CoverConfig::default(),
+            threads,
+            cancel: &CancelToken::default(),
+            on_progress: &|_| {},
+        };
+        scan_with_pool(&[dir.clone()], &pool, &options).unwrap();
+
+        let db = pool.read().unwrap();
+        // Songs hold their album along with its cover paths
+        let songs: Vec<(Option<i64>, String, Option<i64>)> = db
+            .get_all::<Song>(Order::Default)
+            .unwrap()
+            .into_iter()
+            .map(|song| {
+                (
+                    song.song_id,
+                    song.file_path,
+                    song.album.and_then(|a| a.album_id),
+                )
+            })
+            .collect();
+        let artists = db.get_all::<Artist>(Order::Default).unwrap();
+        // Every scan saves its own copy of the covers, compare what's in them
+        let albums: Vec<(Album, Option<Vec<u8>>)> = db
+            .get_all::<Album>(Order::Default)
+            .unwrap()
+            .into_iter()
+            .map(|mut album| {
+                let cover = album.cover_path.take().map(|path| fs::read(path).unwrap());
+                album.cover_path_small = None;
+                album.cover_path_tiny = None;
+                (album, cover)
+            })
+            .collect();
+        (songs, artists, albums)
+    };
+
+    let sequential = scan_with_threads(1);
+    assert_eq!(sequential.0.len(), 11);
+    assert_eq!(scan_with_threads(8), sequential);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(images).unwrap();
 }
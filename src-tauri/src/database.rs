@@ -1,8 +1,16 @@
+use std::path::Path;
+
 use sqlite::{BindableWithIndex, Connection, State};
 
 use crate::{
-    models::{user_generated::Playlist, Retrieve, Store, StoreFull},
-    param::{Condition, Order},
+    error::Error,
+    migrations,
+    models::{
+        base_metadata::{bind_audio_properties, Song},
+        user_generated::Playlist,
+        Paged, Retrieve, Store, StoreFull,
+    },
+    param::{Condition, Order, Page},
 };
 
 pub struct ConnectionWrapper {
@@ -10,122 +18,164 @@ pub struct ConnectionWrapper {
 }
 
 impl ConnectionWrapper {
-    pub fn create_schema(&self) -> Result<(), sqlite::Error> {
-        let query = "
-        CREATE TABLE artist (
-            artist_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE
-        );
-
-        CREATE TABLE album (
-            album_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            artist_id INTEGER,
-            cover_path TEXT,
-            cover_path_small TEXT,
-            cover_path_tiny TEXT,
-            year INTEGER,
-            total_tracks INTEGER,
-            total_discs INTEGER,
-            UNIQUE (artist_id, name)
-        );
-
-        CREATE TABLE song (
-            song_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            file_path TEXT NOT NULL UNIQUE,
-            track INTEGER,
-            disc INTEGER,
-            duration_s FLOATING,
-            quality INTEGER NOT NULL,
-            genre TEXT,
-            artist_id INTEGER,
-            album_id INTEGER
-        );
-
-        CREATE TABLE playlist (
-            playlist_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            desc TEXT NOT NULL,
-            cover_path TEXT,
-            cover_path_small TEXT,
-            cover_path_tiny TEXT,
-            created TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        );
-
-        CREATE TABLE playlist_song (
-            playlist_song_id INTEGER PRIMARY KEY,
-            song_id INTEGER NOT NULL,
-            playlist_id INTEGER NOT NULL,
-            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            ordering INTEGER DEFAULT 0
-        );
-
-        CREATE TABLE tag (
-            tag_id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE
-        );
-
-        CREATE TABLE playlist_tag (
-            playlist_tag_id INTEGER PRIMARY KEY,
-            playlist_id INTEGER NOT NULL,
-            tag_id INTEGER NOT NULL,
-            added TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            UNIQUE (playlist_id, tag_id)
-        );
-
-        CREATE TABLE album_tag (
-            album_tag_id INTEGER PRIMARY KEY,
-            album_id INTEGER NOT NULL,
-            tag_id INTEGER NOT NULL,
-            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            UNIQUE (album_id, tag_id)
-        );
-
-        CREATE TABLE directory (
-            directory_id INTEGER PRIMARY KEY,
-            path TEXT NOT NULL UNIQUE
-        );
-
-        ";
-
-        self.conn.execute(query)
-    }
-
-    pub fn insert(&self, item: &mut impl Store) -> Result<(), sqlite::Error> {
+    // Opens the database at path, or an in-memory one with ":memory:"
+    pub fn open(path: impl AsRef<Path>) -> Result<ConnectionWrapper, Error> {
+        let conn = sqlite::open(path)?;
+        // Foreign keys are off by default and have to be enabled for every connection
+        conn.execute("PRAGMA foreign_keys = ON")?;
+        Ok(ConnectionWrapper { conn })
+    }
+
+    // Creates or upgrades the database schema to the version this build expects
+    pub fn migrate(&self) -> Result<(), Error> {
+        migrations::migrate(&self.conn)
+    }
+
+    pub fn schema_version(&self) -> Result<i64, Error> {
+        migrations::get_version(&self.conn)
+    }
+
+    pub fn insert(&self, item: &mut impl Store) -> Result<(), Error> {
         item.insert(&self.conn)
     }
 
-    pub fn insert_full(&self, item: &mut impl StoreFull) -> Result<(), sqlite::Error> {
+    pub fn insert_full(&self, item: &mut impl StoreFull) -> Result<(), Error> {
         item.insert_full(&self.conn)
     }
 
-    pub fn exists(&self, item: &mut impl Store) -> Result<bool, sqlite::Error> {
+    pub fn exists(&self, item: &mut impl Store) -> Result<bool, Error> {
         item.exists(&self.conn)
     }
 
-    pub fn delete(&self, item: &mut impl Store) -> Result<(), sqlite::Error> {
+    pub fn delete(&self, item: &mut impl Store) -> Result<(), Error> {
         item.delete(&self.conn)
     }
 
-    pub fn get_all<T: Retrieve>(&self, order: Order) -> Result<Vec<T>, sqlite::Error> {
+    pub fn get_all<T: Retrieve>(&self, order: Order<T::Field>) -> Result<Vec<T>, Error> {
         T::get_all(&self.conn, order)
     }
 
     pub fn get_by<T: Retrieve>(
         &self,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<T>, sqlite::Error> {
+        condition: Condition<T::Field>,
+        order: Order<T::Field>,
+    ) -> Result<Vec<T>, Error> {
         T::get_by(&self.conn, condition, order)
     }
 
-    pub fn last_id(&self) -> Result<i64, sqlite::Error> {
+    pub fn get_page<T: Retrieve>(
+        &self,
+        condition: Condition<T::Field>,
+        order: Order<T::Field>,
+        page: Page,
+    ) -> Result<Paged<T>, Error> {
+        // In one transaction so that the total is counted from the same snapshot as the items,
+        // even if a scan writes in between
+        let (items, total) = self.in_transaction(|db| {
+            let total = T::count(&db.conn, &condition)?;
+            let items = T::get_page(&db.conn, condition, order, Some(page))?;
+            Ok((items, total))
+        })?;
+
+        Ok(Paged {
+            items,
+            total,
+            limit: page.limit,
+            offset: page.offset,
+        })
+    }
+
+    pub fn count<T: Retrieve>(&self, condition: &Condition<T::Field>) -> Result<i64, Error> {
+        T::count(&self.conn, condition)
+    }
+
+    pub fn last_id(&self) -> Result<i64, Error> {
         last_id(&self.conn)
     }
+
+    // Starts a transaction that is rolled back unless committed
+    pub fn transaction(&self) -> Result<Transaction<'_>, Error> {
+        self.conn.execute("BEGIN TRANSACTION")?;
+        Ok(Transaction {
+            conn: &self.conn,
+            savepoint: None,
+            finished: false,
+        })
+    }
+
+    // Runs f in a transaction, committing if it succeeds and rolling back if it fails
+    pub fn in_transaction<T>(
+        &self,
+        f: impl FnOnce(&ConnectionWrapper) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let transaction = self.transaction()?;
+        let result = f(self)?;
+        transaction.commit()?;
+        Ok(result)
+    }
+}
+
+// A transaction or a savepoint inside one. Dropping it without committing rolls the changes back,
+// so returning early with ? never leaves partial writes behind.
+pub struct Transaction<'a> {
+    conn: &'a Connection,
+    savepoint: Option<String>,
+    finished: bool,
+}
+
+impl<'a> Transaction<'a> {
+    // Starts a nested transaction, which can be rolled back without affecting this one. It
+    // borrows this transaction so that it can't outlive it.
+    pub fn savepoint(&self, name: &str) -> Result<Transaction<'_>, Error> {
+        let name = format!("\"{}\"", name.replace('"', "\"\""));
+        self.conn.execute(format!("SAVEPOINT {}", name))?;
+        Ok(Transaction {
+            conn: self.conn,
+            savepoint: Some(name),
+            finished: false,
+        })
+    }
+
+    // If committing fails, for example when the database is busy, the transaction is still open.
+    // It's rolled back when self is dropped so that the connection can start new ones.
+    pub fn commit(mut self) -> Result<(), Error> {
+        match &self.savepoint {
+            Some(name) => self.conn.execute(format!("RELEASE {}", name))?,
+            None => self.conn.execute("COMMIT")?,
+        }
+        self.finished = true;
+        Ok(())
+    }
+
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.finished = true;
+        self.undo()
+    }
+
+    fn undo(&self) -> Result<(), Error> {
+        match &self.savepoint {
+            // Rolling back to a savepoint keeps it open, it still has to be released
+            Some(name) => self
+                .conn
+                .execute(format!("ROLLBACK TO {name}; RELEASE {name}", name = name))?,
+            None => self.conn.execute("ROLLBACK")?,
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Err(error) = self.undo() {
+            println!("Error in rolling back transaction: {}", error);
+        }
+    }
 }
 
-pub fn last_id(conn: &sqlite::Connection) -> Result<i64, sqlite::Error> {
+pub fn last_id(conn: &sqlite::Connection) -> Result<i64, Error> {
     let query = "SELECT LAST_INSERT_ROWID()";
 
     let mut statement = conn.prepare(query)?;
@@ -133,13 +183,12 @@ pub fn last_id(conn: &sqlite::Connection) -> Result<i64, sqlite::Error> {
     if let Ok(State::Row) = statement.next() {
         return Ok(statement.read::<i64, _>(0)?);
     }
-    Err(sqlite::Error {
-        code: None,
-        message: Some("Error in retrieving last insert row id".into()),
-    })
+    Err(Error::Database(
+        "Error in retrieving last insert row id".into(),
+    ))
 }
 
-pub fn execute_statement(statement: &mut sqlite::Statement) -> Result<(), sqlite::Error> {
+pub fn execute_statement(statement: &mut sqlite::Statement) -> Result<(), Error> {
     loop {
         let result = statement.next();
         if let Ok(res) = result {
@@ -147,7 +196,7 @@ pub fn execute_statement(statement: &mut sqlite::Statement) -> Result<(), sqlite
                 break;
             }
         } else if let Err(err) = result {
-            return Err(err);
+            return Err(err.into());
         }
     }
     Ok(())
@@ -161,7 +210,7 @@ pub fn update_field<T>(
     field_value: T,
     id_name: &str,
     id_value: i64,
-) -> Result<(), sqlite::Error>
+) -> Result<(), Error>
 where
     T: Sized + BindableWithIndex,
 {
@@ -178,7 +227,7 @@ where
     Ok(())
 }
 
-pub fn get_ordering_offset(db: &ConnectionWrapper, playlist_id: i64) -> Result<i64, sqlite::Error> {
+pub fn get_ordering_offset(db: &ConnectionWrapper, playlist_id: i64) -> Result<i64, Error> {
     let query = "SELECT MAX(ordering) AS res FROM playlist_song WHERE playlist_id = :playlist_id";
     let mut statement = db.conn.prepare(query)?;
     statement.bind((":playlist_id", playlist_id))?;
@@ -190,7 +239,7 @@ pub fn get_ordering_offset(db: &ConnectionWrapper, playlist_id: i64) -> Result<i
     Ok(0)
 }
 
-pub fn update_playlist(db: &ConnectionWrapper, playlist: Playlist) -> Result<(), sqlite::Error> {
+pub fn update_playlist(db: &ConnectionWrapper, playlist: Playlist) -> Result<(), Error> {
     let query = "UPDATE playlist SET name = :name, desc = :desc WHERE playlist_id = :id";
     let mut statement = db.conn.prepare(query)?;
 
@@ -202,6 +251,56 @@ pub fn update_playlist(db: &ConnectionWrapper, playlist: Playlist) -> Result<(),
     Ok(())
 }
 
+// Updates an existing song in place with new metadata, keeping its id so that playlists still
+// refer to it. The album and artist are looked up or created like on insert, and the album picks
+// up the year and totals of the song if it has them.
+pub fn update_song(db: &ConnectionWrapper, song: &mut Song) -> Result<(), Error> {
+    let Some(song_id) = song.song_id else {
+        return Err(Error::Validation("Song has no id".into()));
+    };
+
+    if let Some(artist) = &mut song.artist {
+        artist.insert(&db.conn)?;
+    }
+
+    if let Some(album) = &mut song.album {
+        album.insert_full(&db.conn)?;
+
+        let query = "UPDATE album SET
+        year = coalesce(:year, year),
+        total_tracks = coalesce(:total_tracks, total_tracks),
+        total_discs = coalesce(:total_discs, total_discs)
+        WHERE album_id = :album_id";
+        let mut statement = db.conn.prepare(query)?;
+        statement.bind((":year", album.year))?;
+        statement.bind((":total_tracks", album.total_tracks))?;
+        statement.bind((":total_discs", album.total_discs))?;
+        statement.bind((":album_id", album.album_id))?;
+        execute_statement(&mut statement)?;
+    }
+
+    let query = "UPDATE song SET
+    name = :name, track = :track, disc = :disc, duration_s = :duration_s, quality = :quality,
+    codec = :codec, bitrate_kbps = :bitrate_kbps, sample_rate_hz = :sample_rate_hz,
+    bit_depth = :bit_depth, channels = :channels,
+    genre = :genre, artist_id = :artist_id, album_id = :album_id
+    WHERE song_id = :song_id";
+    let mut statement = db.conn.prepare(query)?;
+    statement.bind((":name", &song.name[..]))?;
+    statement.bind((":track", song.track.map(i64::from)))?;
+    statement.bind((":disc", song.disc.map(i64::from)))?;
+    statement.bind((":duration_s", song.duration_s))?;
+    statement.bind((":quality", song.quality as i64))?;
+    bind_audio_properties(&mut statement, &song.audio)?;
+    statement.bind((":genre", song.genre.as_deref()))?;
+    statement.bind((":artist_id", song.artist.as_ref().and_then(|a| a.artist_id)))?;
+    statement.bind((":album_id", song.album.as_ref().and_then(|a| a.album_id)))?;
+    statement.bind((":song_id", song_id))?;
+    execute_statement(&mut statement)?;
+
+    Ok(())
+}
+
 pub fn update_cover(
     db: &ConnectionWrapper,
     id: i64,
@@ -209,7 +308,7 @@ pub fn update_cover(
     cover_path: String,
     cover_path_small: String,
     cover_path_tiny: String,
-) -> Result<(), sqlite::Error> {
+) -> Result<(), Error> {
     let query = format!(
         "UPDATE {table} 
     SET cover_path = :cover_path, 
@@ -0,0 +1,399 @@
+use std::{env, fs};
+
+use sqlite::State;
+
+use crate::{
+    database::ConnectionWrapper,
+    error::Error,
+    fs_utils::get_unique_path,
+    migrations::{current_version, get_pragma, has_column},
+    models::{
+        base_metadata::{Album, Artist, Song, SongField},
+        user_generated::{Playlist, PlaylistSong, Tag},
+        Quality, Store, StoreFull,
+    },
+    param::{eq, Order},
+    test_utils::get_mock_db,
+};
+
+// Copies the bundled sample database so the tests can modify it freely
+fn sample_db_copy() -> (ConnectionWrapper, String) {
+    let path = get_unique_path(&env::temp_dir().to_string_lossy(), "db").unwrap();
+    fs::copy("sample_data.db", &path).expect("Copy sample database");
+    let db = ConnectionWrapper::open(&path).expect("Connection failed");
+    (db, path)
+}
+
+fn sample_song(name: &str, album: &str, artist: &str) -> Song {
+    let artist = Artist {
+        artist_id: None,
+        name: artist.into(),
+        artist_image_path: None,
+    };
+    Song {
+        song_id: None,
+        name: name.into(),
+        file_path: format!("/music/{}/{}.mp3", album, name),
+        track: None,
+        disc: None,
+        duration_s: None,
+        quality: Quality::Lossy,
+        genre: None,
+        artist: Some(artist.clone()),
+        album: Some(Album {
+            album_id: None,
+            name: album.into(),
+            artist: Some(artist),
+            cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
+            year: None,
+            total_tracks: None,
+            total_discs: None,
+        }),
+        missing: false,
+        audio: Default::default(),
+    }
+}
+
+fn count_rows(db: &ConnectionWrapper, table: &str) -> i64 {
+    let mut statement = db
+        .conn
+        .prepare(format!("SELECT COUNT(*) FROM {}", table))
+        .unwrap();
+    statement.next().unwrap();
+    statement.read::<i64, _>(0).unwrap()
+}
+
+// The details of EXPLAIN QUERY PLAN, one line per table access
+fn query_plan(db: &ConnectionWrapper, query: &str) -> Vec<String> {
+    let mut statement = db
+        .conn
+        .prepare(format!("EXPLAIN QUERY PLAN {}", query))
+        .unwrap();
+
+    let mut plan = vec![];
+    while let Ok(State::Row) = statement.next() {
+        plan.push(statement.read::<String, _>("detail").unwrap());
+    }
+    plan
+}
+
+fn assert_no_scans(plan: &[String]) {
+    for step in plan {
+        assert!(!step.starts_with("SCAN"), "Full table scan: {:?}", plan);
+    }
+}
+
+#[test]
+fn fresh_database_is_current() {
+    let db = get_mock_db();
+    assert_eq!(db.schema_version().unwrap(), current_version());
+}
+
+#[test]
+fn migrate_twice() {
+    let db = get_mock_db();
+    db.migrate().expect("Second migration");
+    assert_eq!(db.schema_version().unwrap(), current_version());
+}
+
+#[test]
+fn upgrade_sample_database() {
+    let (db, path) = sample_db_copy();
+    assert_eq!(db.schema_version().unwrap(), 0);
+    assert!(!has_column(&db.conn, "album", "cover_path_small").unwrap());
+
+    let songs_before = db.get_all::<Song>(Order::Default);
+    // The old schema is missing album columns that the song query needs
+    assert!(songs_before.is_err());
+
+    db.migrate().expect("Migration");
+
+    assert_eq!(db.schema_version().unwrap(), current_version());
+    assert!(has_column(&db.conn, "album", "cover_path_small").unwrap());
+    assert!(has_column(&db.conn, "album", "cover_path_tiny").unwrap());
+
+    let songs = db.get_all::<Song>(Order::Default).unwrap();
+    let albums = db.get_all::<Album>(Order::Default).unwrap();
+    assert_eq!(songs.len(), 2578);
+    assert_eq!(albums.len(), 188);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn refuse_newer_database() {
+    let db = get_mock_db();
+    db.conn
+        .execute(format!("PRAGMA user_version = {}", current_version() + 1))
+        .unwrap();
+
+    assert!(db.migrate().is_err());
+    assert_eq!(db.schema_version().unwrap(), current_version() + 1);
+}
+
+#[test]
+fn foreign_keys_enabled() {
+    let db = get_mock_db();
+    assert_eq!(get_pragma(&db.conn, "foreign_keys").unwrap(), 1);
+
+    // Migrating turns them off temporarily
+    db.migrate().unwrap();
+    assert_eq!(get_pragma(&db.conn, "foreign_keys").unwrap(), 1);
+}
+
+#[test]
+fn reject_dangling_references() {
+    let db = get_mock_db();
+
+    let mut song = sample_song("Song", "Album", "Artist");
+    song.album.as_mut().unwrap().album_id = Some(999);
+    assert!(db.insert(&mut song).is_err());
+
+    let mut playlist_song = PlaylistSong {
+        playlist_song_id: None,
+        song_id: 999,
+        playlist_id: 999,
+        ordering: 0,
+        added: None,
+    };
+    assert!(db.insert(&mut playlist_song).is_err());
+}
+
+#[test]
+fn delete_artist_keeps_albums_and_songs() {
+    let db = get_mock_db();
+    let mut song = sample_song("Song", "Album", "Artist");
+    db.insert_full(&mut song).unwrap();
+
+    db.delete(song.artist.as_mut().unwrap()).unwrap();
+
+    let songs = db.get_all::<Song>(Order::Default).unwrap();
+    assert_eq!(songs.len(), 1);
+    assert_eq!(songs[0].artist, None);
+
+    let albums = db.get_all::<Album>(Order::Default).unwrap();
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0].artist, None);
+}
+
+#[test]
+fn delete_album_keeps_songs() {
+    let db = get_mock_db();
+    let mut song = sample_song("Song", "Album", "Artist");
+    db.insert_full(&mut song).unwrap();
+
+    db.delete(song.album.as_mut().unwrap()).unwrap();
+
+    let songs = db.get_all::<Song>(Order::Default).unwrap();
+    assert_eq!(songs.len(), 1);
+    assert_eq!(songs[0].album, None);
+    assert!(songs[0].artist.is_some());
+}
+
+#[test]
+fn delete_cascades_to_playlists() {
+    let db = get_mock_db();
+
+    let mut songs = vec![
+        sample_song("First", "Album", "Artist"),
+        sample_song("Second", "Album", "Artist"),
+    ];
+    for song in songs.iter_mut() {
+        db.insert_full(song).unwrap();
+    }
+
+    let mut playlist = Playlist {
+        playlist_id: None,
+        name: "Playlist".into(),
+        desc: "".into(),
+        cover_path: None,
+        created: None,
+        tags: vec!["tag".into(), "other tag".into()],
+    };
+    db.insert(&mut playlist).unwrap();
+
+    for song in songs.iter() {
+        let mut playlist_song = PlaylistSong {
+            playlist_song_id: None,
+            song_id: song.song_id.unwrap(),
+            playlist_id: playlist.playlist_id.unwrap(),
+            ordering: 0,
+            added: None,
+        };
+        db.insert(&mut playlist_song).unwrap();
+    }
+    assert_eq!(count_rows(&db, "playlist_song"), 2);
+    assert_eq!(count_rows(&db, "playlist_tag"), 2);
+
+    db.delete(&mut songs[0]).unwrap();
+    assert_eq!(count_rows(&db, "playlist_song"), 1);
+
+    let mut tag = db
+        .get_all::<Tag>(Order::Default)
+        .unwrap()
+        .into_iter()
+        .find(|t| t.name == "tag")
+        .unwrap();
+    db.delete(&mut tag).unwrap();
+    assert_eq!(count_rows(&db, "playlist_tag"), 1);
+
+    db.delete(&mut playlist).unwrap();
+    assert_eq!(count_rows(&db, "playlist_song"), 0);
+    assert_eq!(count_rows(&db, "playlist_tag"), 0);
+    assert_eq!(count_rows(&db, "song"), 1);
+}
+
+#[test]
+fn upgrade_clears_dangling_references() {
+    let (db, path) = sample_db_copy();
+    db.conn
+        .execute("UPDATE song SET artist_id = 99999, album_id = 99999 WHERE song_id = 1")
+        .unwrap();
+
+    db.migrate().expect("Migration");
+
+    let songs = db
+        .get_by::<Song>(eq(SongField::SongId, 1i64), Order::Default)
+        .unwrap();
+    assert_eq!(songs[0].artist, None);
+    assert_eq!(songs[0].album, None);
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 2578);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn joins_use_indexes() {
+    let db = get_mock_db();
+
+    let plan = query_plan(
+        &db,
+        "SELECT song.song_id FROM playlist_song
+        JOIN song ON song.song_id = playlist_song.song_id
+        WHERE playlist_song.playlist_id = 1
+        ORDER BY playlist_song.ordering",
+    );
+    assert_no_scans(&plan);
+    assert!(plan.iter().any(|p| p.contains("playlist_song_playlist_id")));
+
+    let plan = query_plan(
+        &db,
+        "SELECT song.song_id FROM song
+        LEFT JOIN playlist_song ON playlist_song.song_id = song.song_id
+        WHERE song.album_id = 1",
+    );
+    assert_no_scans(&plan);
+    assert!(plan.iter().any(|p| p.contains("song_album_id")));
+    assert!(plan.iter().any(|p| p.contains("playlist_song_song_id")));
+
+    let plan = query_plan(&db, "SELECT album_id FROM album WHERE artist_id = 1");
+    assert_eq!(plan.len(), 1);
+    // The index of the UNIQUE (artist_id, name) constraint
+    assert!(plan[0].starts_with("SEARCH") && plan[0].contains("INDEX"));
+
+    let plan = query_plan(&db, "SELECT song_id FROM song WHERE artist_id = 1");
+    assert!(plan[0].contains("song_artist_id"));
+
+    let plan = query_plan(
+        &db,
+        "SELECT playlist_id FROM playlist_tag
+        JOIN tag ON tag.tag_id = playlist_tag.tag_id
+        WHERE tag.name = 'chill'",
+    );
+    assert_no_scans(&plan);
+
+    let plan = query_plan(&db, "SELECT album_id FROM album_tag WHERE tag_id = 1");
+    assert!(plan[0].contains("album_tag_tag_id"));
+}
+
+#[test]
+fn transaction_commit_and_rollback() {
+    let db = get_mock_db();
+
+    let transaction = db.transaction().unwrap();
+    db.insert_full(&mut sample_song("First", "Album", "Artist"))
+        .unwrap();
+    transaction.commit().unwrap();
+    assert_eq!(count_rows(&db, "song"), 1);
+
+    let transaction = db.transaction().unwrap();
+    db.insert_full(&mut sample_song("Second", "Album", "Artist"))
+        .unwrap();
+    transaction.rollback().unwrap();
+    assert_eq!(count_rows(&db, "song"), 1);
+
+    // Dropping rolls back as well
+    {
+        let _transaction = db.transaction().unwrap();
+        db.insert_full(&mut sample_song("Third", "Other album", "Other artist"))
+            .unwrap();
+    }
+    assert_eq!(count_rows(&db, "song"), 1);
+    assert_eq!(count_rows(&db, "album"), 1);
+    assert_eq!(count_rows(&db, "artist"), 1);
+
+    let result: Result<(), Error> = db.in_transaction(|db| {
+        db.insert_full(&mut sample_song("Fourth", "Album", "Artist"))?;
+        Err(Error::Internal("Failed halfway".into()))
+    });
+    assert!(result.is_err());
+    assert_eq!(count_rows(&db, "song"), 1);
+}
+
+#[test]
+fn failed_commit_rolls_back() {
+    let db = get_mock_db();
+
+    // Deferred foreign keys are only checked on commit, a song on an album that doesn't exist
+    // makes it fail
+    let transaction = db.transaction().unwrap();
+    db.conn.execute("PRAGMA defer_foreign_keys = ON").unwrap();
+    db.conn
+        .execute(
+            "INSERT INTO song (name, file_path, quality, album_id)
+            VALUES ('Orphan', '/music/orphan.flac', 0, 1000)",
+        )
+        .unwrap();
+    assert!(transaction.commit().is_err());
+    assert_eq!(count_rows(&db, "song"), 0);
+
+    // The connection isn't stuck in the failed transaction
+    db.in_transaction(|db| db.insert_full(&mut sample_song("Song", "Album", "Artist")))
+        .unwrap();
+    assert_eq!(count_rows(&db, "song"), 1);
+}
+
+#[test]
+fn savepoints() {
+    let db = get_mock_db();
+
+    let transaction = db.transaction().unwrap();
+    db.insert_full(&mut sample_song("First", "Album", "Artist"))
+        .unwrap();
+
+    let savepoint = transaction.savepoint("song").unwrap();
+    db.insert_full(&mut sample_song("Second", "Album", "Artist"))
+        .unwrap();
+    savepoint.rollback().unwrap();
+
+    let savepoint = transaction.savepoint("song").unwrap();
+    let nested = savepoint.savepoint("nested").unwrap();
+    db.insert_full(&mut sample_song("Third", "Album", "Artist"))
+        .unwrap();
+    nested.commit().unwrap();
+    savepoint.commit().unwrap();
+
+    transaction.commit().unwrap();
+
+    let names: Vec<String> = db
+        .get_all::<Song>(Order::Default)
+        .unwrap()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(names.len(), 2);
+    assert!(names.contains(&"First".to_string()));
+    assert!(names.contains(&"Third".to_string()));
+}
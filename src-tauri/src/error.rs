@@ -0,0 +1,117 @@
+use std::fmt;
+
+use serde::Serialize;
+
+// Errors of the whole backend. Commands return these to the frontend serialized as
+// { "kind": "database", "message": "..." } so that it can tell the kinds apart.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum Error {
+    Database(String),
+    // Reading the metadata tags of an audio file
+    Tag(String),
+    Io(String),
+    Image(String),
+    Playback(String),
+    // Invalid input, such as an object that can't be stored or a malformed filter
+    Validation(String),
+    NotFound(String),
+    Config(String),
+    // Something else is already running that this can't run alongside, such as a second scan
+    Busy(String),
+    // Failures of the application itself, such as a poisoned lock or a crashed task
+    Internal(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Database(_) => "database",
+            Error::Tag(_) => "tag",
+            Error::Io(_) => "io",
+            Error::Image(_) => "image",
+            Error::Playback(_) => "playback",
+            Error::Validation(_) => "validation",
+            Error::NotFound(_) => "not_found",
+            Error::Config(_) => "config",
+            Error::Busy(_) => "busy",
+            Error::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Database(message)
+            | Error::Tag(message)
+            | Error::Io(message)
+            | Error::Image(message)
+            | Error::Playback(message)
+            | Error::Validation(message)
+            | Error::NotFound(message)
+            | Error::Config(message)
+            | Error::Busy(message)
+            | Error::Internal(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {}", self.kind(), self.message())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlite::Error> for Error {
+    fn from(error: sqlite::Error) -> Self {
+        match error.message {
+            Some(message) => Error::Database(message),
+            None => Error::Database(error.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Error::Image(error.to_string())
+    }
+}
+
+impl From<lofty::error::LoftyError> for Error {
+    fn from(error: lofty::error::LoftyError) -> Self {
+        Error::Tag(error.to_string())
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(error: notify::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Validation(error.to_string())
+    }
+}
+
+impl From<tauri::Error> for Error {
+    fn from(error: tauri::Error) -> Self {
+        Error::Internal(error.to_string())
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for Error {
+    fn from(error: std::sync::PoisonError<T>) -> Self {
+        Error::Internal(error.to_string())
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{
+    error::Error,
+    models::{user_generated::Directory, Store},
+    param::from_json,
+    test_utils::get_mock_db,
+};
+
+#[test]
+fn serializes_kind_and_message() {
+    let error = Error::NotFound("No song with id 1".into());
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        serde_json::json!({ "kind": "not_found", "message": "No song with id 1" })
+    );
+    assert_eq!(error.to_string(), "not_found error: No song with id 1");
+}
+
+#[test]
+fn converts_library_errors() {
+    let db = get_mock_db();
+    let mut directory = Directory {
+        directory_id: None,
+        path: "/music".into(),
+    };
+    directory.insert(&db.conn).unwrap();
+
+    // The path is unique
+    directory.directory_id = None;
+    let error = directory.insert(&db.conn).unwrap_err();
+    assert_eq!(error.kind(), "database");
+    assert!(!error.message().is_empty());
+
+    let error = from_json(&serde_json::json!([1, 2])).unwrap_err();
+    assert_eq!(error.kind(), "validation");
+}
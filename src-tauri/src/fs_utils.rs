@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use audiotags::MimeType;
+use lofty::picture::MimeType;
 use rand::{distributions::Alphanumeric, Rng};
 
 fn random_string(len: usize) -> String {
@@ -24,7 +24,9 @@ pub fn get_unique_path(base: &str, extension: &str) -> Result<String, String> {
 
         let candidate = path.join(format!("{}.{}", random_string(filename_len), extension));
 
-        let Ok(exists) = candidate.try_exists() else { return Err("Failed to check if path exists in get_unique_path".into()); };
+        let Ok(exists) = candidate.try_exists() else {
+            return Err("Failed to check if path exists in get_unique_path".into());
+        };
 
         if exists {
             continue;
@@ -37,12 +39,13 @@ pub fn get_unique_path(base: &str, extension: &str) -> Result<String, String> {
     }
 }
 
-pub fn mime_type_to_extension<'a>(mime_type: MimeType) -> &'a str {
+pub fn mime_type_to_extension<'a>(mime_type: Option<&MimeType>) -> &'a str {
     match mime_type {
-        MimeType::Png => "png",
-        MimeType::Jpeg => "jpg",
-        MimeType::Tiff => "tiff",
-        MimeType::Bmp => "bpm",
-        MimeType::Gif => "gif",
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Tiff) => "tiff",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Gif) => "gif",
+        // Most embedded covers without a known type are JPEGs
+        _ => "jpg",
     }
 }
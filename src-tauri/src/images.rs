@@ -1,12 +1,16 @@
 use std::{
     fs::{self, File},
     io::Write,
+    path::{Path, PathBuf},
 };
 
 use image::{imageops::FilterType, load_from_memory_with_format, ImageFormat};
 
 use crate::fs_utils::get_unique_path;
 
+// Image files that save_cover can read
+pub const COVER_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "bmp"];
+
 // Saves image in different sizes and returns their paths in a tuple
 pub fn save_cover<'a>(
     data: &'a [u8],
@@ -23,22 +27,34 @@ pub fn save_cover<'a>(
     };
 
     // Create paths that do not exist already
-    let Ok(path) = get_unique_path(image_cache_dir, extension) else { return (None, None, None) };
-    let Ok(path_small) = get_unique_path(image_cache_dir, extension) else { return (None, None, None) };
-    let Ok(path_tiny) = get_unique_path(image_cache_dir, extension) else { return (None, None, None) };
+    let Ok(path) = get_unique_path(image_cache_dir, extension) else {
+        return (None, None, None);
+    };
+    let Ok(path_small) = get_unique_path(image_cache_dir, extension) else {
+        return (None, None, None);
+    };
+    let Ok(path_tiny) = get_unique_path(image_cache_dir, extension) else {
+        return (None, None, None);
+    };
 
     // Save downscaled versions
-    let Ok(img) = load_from_memory_with_format(data, format) else { return (None, None, None) };
+    let Ok(img) = load_from_memory_with_format(data, format) else {
+        return (None, None, None);
+    };
     let small = img.resize(256, 256, FilterType::Triangle);
     let tiny = img.resize(128, 128, FilterType::Triangle);
 
-    let Ok(mut output) = File::create(path_small.clone()) else { return (None, None, None) };
+    let Ok(mut output) = File::create(path_small.clone()) else {
+        return (None, None, None);
+    };
     if let Err(err) = small.write_to(&mut output, ImageFormat::Png) {
         println!("Error in save_cover (small), {}", err);
         return (None, None, None);
     }
 
-    let Ok(mut output) = File::create(path_tiny.clone()) else { return (None, None, None) };
+    let Ok(mut output) = File::create(path_tiny.clone()) else {
+        return (None, None, None);
+    };
     if let Err(err) = tiny.write_to(&mut output, ImageFormat::Png) {
         println!("Error in save_cover (original), {}", err);
         return (None, None, None);
@@ -46,7 +62,9 @@ pub fn save_cover<'a>(
 
     // Save original version
     let file = fs::OpenOptions::new().create(true).write(true).open(&path);
-    let Ok(mut file) = file else { return (None, None, None) };
+    let Ok(mut file) = file else {
+        return (None, None, None);
+    };
     if let Err(err) = file.write_all(data) {
         println!("Error in save_cover (original), {}", err);
         return (None, None, None);
@@ -54,3 +72,39 @@ pub fn save_cover<'a>(
 
     (Some(path), Some(path_small), Some(path_tiny))
 }
+
+// Finds the album cover among the image files in dir. Names are matched against the file names
+// without their extension in order, see CoverConfig.
+pub fn find_folder_cover(dir: &Path, names: &[String]) -> Option<PathBuf> {
+    let mut images: Vec<(String, PathBuf)> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let extension = path.extension()?.to_str()?.to_lowercase();
+            if !COVER_EXTENSIONS.contains(&&extension[..]) {
+                return None;
+            }
+            let stem = path.file_stem()?.to_str()?.to_lowercase();
+            Some((stem, path))
+        })
+        .collect();
+    // The same name can match more than one file, pick the same one every time
+    images.sort();
+
+    names.iter().find_map(|name| {
+        let name = name.to_lowercase();
+        images
+            .iter()
+            .find(|(stem, _)| matches_name(stem, &name))
+            .map(|(_, path)| path.clone())
+    })
+}
+
+fn matches_name(stem: &str, name: &str) -> bool {
+    match name.strip_suffix('*') {
+        Some(prefix) => stem.starts_with(prefix),
+        None => stem == name,
+    }
+}
@@ -0,0 +1,71 @@
+use std::{env, fs, path::Path};
+
+use crate::{fs_utils::get_unique_path, images::find_folder_cover};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn get_dir_with(files: &[&str]) -> String {
+    let temp_dir = env::temp_dir().to_string_lossy().to_string();
+    let dir = get_unique_path(&temp_dir, "covers").unwrap();
+    fs::create_dir(&dir).unwrap();
+    for file in files {
+        fs::write(Path::new(&dir).join(file), []).unwrap();
+    }
+    dir
+}
+
+fn found(dir: &str, list: &[&str]) -> Option<String> {
+    let path = find_folder_cover(Path::new(dir), &names(list))?;
+    Some(path.file_name()?.to_string_lossy().to_string())
+}
+
+#[test]
+fn finds_covers_in_order() {
+    let dir = get_dir_with(&["Folder.JPG", "cover.png", "back.jpg", "song.flac"]);
+
+    assert_eq!(found(&dir, &["cover", "folder"]), Some("cover.png".into()));
+    assert_eq!(found(&dir, &["folder", "cover"]), Some("Folder.JPG".into()));
+    assert_eq!(found(&dir, &["front"]), None);
+    assert_eq!(found(&dir, &[]), None);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn matches_names() {
+    let dir = get_dir_with(&[
+        "AlbumArt_{1234}_Large.jpg",
+        "AlbumArtSmall.jpg",
+        "front.txt",
+        "front.jpeg.flac",
+    ]);
+
+    // Several files match, the first by name is picked every time
+    assert_eq!(
+        found(&dir, &["albumart*"]),
+        Some("AlbumArt_{1234}_Large.jpg".into())
+    );
+    assert_eq!(
+        found(&dir, &["albumarts*"]),
+        Some("AlbumArtSmall.jpg".into())
+    );
+    // Whole names only without a wildcard, and only images
+    assert_eq!(found(&dir, &["albumart"]), None);
+    assert_eq!(found(&dir, &["front"]), None);
+    assert_eq!(found(&dir, &["front.jpeg"]), None);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ignores_directories() {
+    let dir = get_dir_with(&[]);
+    fs::create_dir(format!("{}/cover.jpg", dir)).unwrap();
+
+    assert_eq!(found(&dir, &["cover"]), None);
+    assert_eq!(found("/nonexistent/directory", &["cover"]), None);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,15 +1,46 @@
+pub mod audio_format;
 pub mod audio_playback;
+pub mod config;
+pub mod content_hash;
 pub mod content_library;
 pub mod content_scanner;
 pub mod database;
+pub mod error;
 pub mod fs_utils;
 pub mod images;
+pub mod library_watcher;
+pub mod migrations;
 pub mod models;
 pub mod param;
+pub mod playback_observer;
+pub mod pool;
+pub mod search;
 pub mod test_utils;
 pub mod utils;
 
+#[cfg(test)]
+mod audio_playback_test;
+#[cfg(test)]
+mod config_test;
+#[cfg(test)]
+mod content_hash_test;
+#[cfg(test)]
+mod content_library_test;
 #[cfg(test)]
 mod content_scanner_test;
 #[cfg(test)]
+mod database_test;
+#[cfg(test)]
+mod error_test;
+#[cfg(test)]
+mod images_test;
+#[cfg(test)]
+mod library_watcher_test;
+#[cfg(test)]
 mod models_test;
+#[cfg(test)]
+mod playback_observer_test;
+#[cfg(test)]
+mod pool_test;
+#[cfg(test)]
+mod search_test;
@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+
+use crate::{
+    content_library::{clean_with_pool, CleanReport},
+    content_scanner::{scan_with_pool, ScanOptions, ScanReport},
+    error::Error,
+    pool::Pool,
+};
+
+// How long the library directories have to be quiet before the changes are applied. Copying an
+// album sends events for every file, they are applied together once the copy is done.
+pub const DEBOUNCE: Duration = Duration::from_millis(1500);
+
+// What applying a batch of changes did to the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LibraryChanges {
+    pub scan: ScanReport,
+    pub clean: CleanReport,
+}
+
+// Collects changed paths until there have been no new ones for delay
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    pending: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Debouncer {
+        Debouncer {
+            delay,
+            pending: BTreeSet::new(),
+            last_change: None,
+        }
+    }
+
+    // Every change pushes the deadline back, a file being copied changes until it's done
+    pub fn add(&mut self, paths: impl IntoIterator<Item = PathBuf>, now: Instant) {
+        self.pending.extend(paths);
+        if !self.pending.is_empty() {
+            self.last_change = Some(now);
+        }
+    }
+
+    // How long to wait for the pending changes to settle, None if there are none
+    pub fn wait_time(&self, now: Instant) -> Option<Duration> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let last_change = self.last_change?;
+        Some(self.delay.saturating_sub(now.duration_since(last_change)))
+    }
+
+    // The pending changes once they have settled
+    pub fn take_ready(&mut self, now: Instant) -> Option<Vec<PathBuf>> {
+        if self.wait_time(now)? > Duration::ZERO {
+            return None;
+        }
+        self.last_change = None;
+        Some(std::mem::take(&mut self.pending).into_iter().collect())
+    }
+}
+
+// Leaves out the paths inside other paths, scanning a new directory covers its files. Scanning
+// them twice in one go would add them twice.
+pub fn outermost(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = paths.to_vec();
+    // Sorted, a directory comes right before the paths inside it
+    paths.sort();
+    paths.dedup();
+
+    let mut result: Vec<PathBuf> = vec![];
+    for path in paths {
+        if !result.last().is_some_and(|parent| path.starts_with(parent)) {
+            result.push(path);
+        }
+    }
+    result
+}
+
+// Watches the library directories in the background (inotify on Linux) and calls on_change with
+// the changed paths once they have settled. If on_change returns false the paths are kept and
+// handed over again after another delay. Watching stops when this is dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    pub fn start<F>(dirs: &[String], delay: Duration, on_change: F) -> Result<LibraryWatcher, Error>
+    where
+        F: FnMut(&[PathBuf]) -> bool + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        thread::spawn(move || run(receiver, delay, on_change));
+
+        for dir in dirs {
+            // A directory on a drive that isn't mounted doesn't keep the others from being watched
+            if let Err(err) = watcher.watch(Path::new(dir), RecursiveMode::Recursive) {
+                println!("Could not watch {}: {}", dir, err);
+            }
+        }
+        Ok(LibraryWatcher { _watcher: watcher })
+    }
+}
+
+// The thread of a watcher, ends when the watcher is dropped
+fn run<F>(receiver: Receiver<notify::Result<Event>>, delay: Duration, mut on_change: F)
+where
+    F: FnMut(&[PathBuf]) -> bool,
+{
+    let mut debouncer = Debouncer::new(delay);
+    loop {
+        let received = match debouncer.wait_time(Instant::now()) {
+            Some(timeout) => receiver.recv_timeout(timeout),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(Ok(event)) if is_change(&event.kind) => debouncer.add(event.paths, Instant::now()),
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(err)) => println!("Error in watching the library: {}", err),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if let Some(paths) = debouncer.take_ready(Instant::now()) {
+            if !on_change(&paths) {
+                debouncer.add(paths, Instant::now());
+            }
+        }
+    }
+}
+
+// Files being read don't change the library
+fn is_change(kind: &EventKind) -> bool {
+    !matches!(kind, EventKind::Access(_))
+}
+
+// Brings the library up to date with the changed paths: new and changed files are scanned like in
+// a scan, songs whose files are gone are cleaned up. Renamed files are matched to their songs by
+// content before anything is cleaned up.
+pub fn apply_changes(
+    paths: &[PathBuf],
+    pool: &Pool,
+    options: &ScanOptions,
+) -> Result<LibraryChanges, Error> {
+    let (existing, removed): (Vec<PathBuf>, Vec<PathBuf>) =
+        outermost(paths).into_iter().partition(|path| path.exists());
+    let existing: Vec<String> = existing
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    let mut changes = LibraryChanges::default();
+    if !existing.is_empty() {
+        changes.scan = scan_with_pool(&existing, pool, options)?;
+    }
+    if !removed.is_empty() {
+        changes.clean = clean_with_pool(pool)?;
+    }
+    Ok(changes)
+}
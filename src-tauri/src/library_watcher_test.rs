@@ -0,0 +1,158 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::mpsc::channel,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::CoverConfig,
+    content_scanner::{scan_for_new_content, CancelToken, ScanOptions},
+    fs_utils::get_unique_path,
+    library_watcher::{apply_changes, outermost, Debouncer, LibraryWatcher},
+    models::base_metadata::{Song, SongField},
+    param::{eq, Order},
+    pool::Pool,
+    test_utils::{get_image_cache, get_mock_db, get_test_audio_copy},
+};
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn debounces_changes() {
+    let start = Instant::now();
+    let after = |ms: u64| start + Duration::from_millis(ms);
+    let mut debouncer = Debouncer::new(Duration::from_secs(1));
+    assert_eq!(debouncer.wait_time(start), None);
+    assert_eq!(debouncer.take_ready(start), None);
+
+    debouncer.add(paths(&["/music/a.flac"]), start);
+    assert_eq!(debouncer.wait_time(start), Some(Duration::from_secs(1)));
+    assert_eq!(debouncer.take_ready(after(500)), None);
+
+    // A file that keeps changing keeps pushing the deadline back
+    debouncer.add(paths(&["/music/a.flac"]), after(800));
+    assert_eq!(debouncer.take_ready(after(1200)), None);
+    assert_eq!(
+        debouncer.take_ready(after(1800)),
+        Some(paths(&["/music/a.flac"]))
+    );
+    assert_eq!(debouncer.wait_time(after(1800)), None);
+}
+
+#[test]
+fn leaves_out_nested_paths() {
+    let changed = paths(&[
+        "/music/New album/1.flac",
+        "/music/b.flac",
+        "/music/New album",
+        "/music/New album 2/1.flac",
+        "/music/New album/2.flac",
+        "/music/b.flac",
+    ]);
+
+    assert_eq!(
+        outermost(&changed),
+        paths(&[
+            "/music/New album",
+            "/music/New album 2/1.flac",
+            "/music/b.flac",
+        ])
+    );
+}
+
+#[test]
+fn applies_changes() {
+    let dir = get_test_audio_copy();
+    let image_cache_dir = get_image_cache();
+    let covers = CoverConfig::default();
+    let pool = Pool::single(get_mock_db());
+    scan_for_new_content(&dir, &pool.write().unwrap(), &image_cache_dir, &covers).unwrap();
+
+    let get_song = |path: &str| {
+        pool.read()
+            .unwrap()
+            .get_by::<Song>(eq(SongField::FilePath, path), Order::Default)
+            .unwrap()
+            .pop()
+    };
+    let renamed_id = get_song(&format!("{}/sample5.ogg", dir)).unwrap().song_id;
+
+    // A new directory, a renamed file and a deleted one
+    fs::create_dir(format!("{}/new", dir)).unwrap();
+    fs::copy(
+        format!("{}/sample9.wav", dir),
+        format!("{}/new/copy.wav", dir),
+    )
+    .unwrap();
+    fs::rename(
+        format!("{}/sample5.ogg", dir),
+        format!("{}/renamed.ogg", dir),
+    )
+    .unwrap();
+    fs::remove_file(format!("{}/sample6.opus", dir)).unwrap();
+
+    let changed = [
+        "new",
+        "new/copy.wav",
+        "sample5.ogg",
+        "renamed.ogg",
+        "sample6.opus",
+    ]
+    .map(|name| PathBuf::from(format!("{}/{}", dir, name)));
+    let options = ScanOptions {
+        image_cache_dir: &image_cache_dir,
+        covers: &covers,
+        threads: 2,
+        cancel: &CancelToken::default(),
+        on_progress: &|_| {},
+    };
+    let changes = apply_changes(&changed, &pool, &options).unwrap();
+
+    assert_eq!(changes.scan.added, 1);
+    assert_eq!(changes.scan.moved, 1);
+    assert_eq!(changes.clean.songs_removed, 1);
+    assert!(get_song(&format!("{}/new/copy.wav", dir)).is_some());
+    assert_eq!(
+        get_song(&format!("{}/renamed.ogg", dir)).unwrap().song_id,
+        renamed_id
+    );
+    assert!(get_song(&format!("{}/sample6.opus", dir)).is_none());
+    let db = pool.read().unwrap();
+    assert_eq!(db.get_all::<Song>(Order::Default).unwrap().len(), 11);
+    drop(db);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(image_cache_dir).unwrap();
+}
+
+#[test]
+fn watches_directories() {
+    let temp_dir = env::temp_dir().to_string_lossy().to_string();
+    let dir = get_unique_path(&temp_dir, "music").unwrap();
+    fs::create_dir(&dir).unwrap();
+
+    let (sender, receiver) = channel();
+    let mut calls = 0;
+    // Turns the first batch down, like when a scan is running
+    let watcher = LibraryWatcher::start(&[dir.clone()], Duration::from_millis(100), move |paths| {
+        calls += 1;
+        let _ = sender.send(paths.to_vec());
+        calls > 1
+    })
+    .unwrap();
+
+    let path = PathBuf::from(format!("{}/new.flac", dir));
+    fs::write(&path, "Not audio").unwrap();
+
+    let timeout = Duration::from_secs(10);
+    let first = receiver.recv_timeout(timeout).unwrap();
+    assert!(first.contains(&path));
+    let second = receiver.recv_timeout(timeout).unwrap();
+    assert!(second.contains(&path));
+
+    drop(watcher);
+    fs::remove_dir_all(dir).unwrap();
+}
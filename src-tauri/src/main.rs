@@ -2,108 +2,226 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::{
-    fs,
-    io::{Read, Write},
+    env, fs,
+    io::{ErrorKind, Read, Write},
     os::unix::net::UnixListener,
-    sync::Mutex,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     thread,
 };
 
 use musicbase::{
-    audio_playback::{play_file, start_mpv_process},
-    content_scanner::scan_for_new_content,
+    audio_playback::{
+        start_mpv_process, Mpv, PlayMode, PlayQueue, Player, PlayerState, RepeatMode, SeekMode,
+        ShuffleMode, MPV_SOCKET,
+    },
+    config::{Config, CoverConfig, Profile},
+    content_library::{clean_with_pool, CleanReport},
+    content_scanner::{
+        default_threads, scan_with_pool, CancelToken, ScanOptions, ScanProgress, ScanReport,
+    },
     database::{get_ordering_offset, update_cover, update_playlist, ConnectionWrapper},
+    error::Error,
     images::save_cover,
+    library_watcher::{apply_changes, LibraryChanges, LibraryWatcher, DEBOUNCE},
     models::{
-        base_metadata::{Album, Artist, Song},
-        user_generated::{Directory, Playlist, PlaylistSong, Tag},
-        Retrieve, Store, StoreFull,
+        base_metadata::{Album, AlbumField, Artist, ArtistField, Song, SongField},
+        user_generated::{Directory, Playlist, PlaylistField, PlaylistSong, Tag},
+        Paged, Retrieve, Store,
     },
-    param::{self, eq, Order},
+    param::{asc, eq, is_in, Condition, Filter, Order, Page, SortKey},
+    playback_observer::{PlaybackObserver, PlayerEvent},
+    pool::{Pool, SharedPool, DEFAULT_READERS},
+    search::{self, SearchResults},
 };
-use tauri::{api::dialog, AppHandle, Manager, State};
-
-fn vec_result<T>(res: Result<Vec<T>, sqlite::Error>) -> Vec<T> {
-    match res {
-        Ok(a) => a,
-        Err(error) => {
-            println!("Error: {}", error.message.unwrap_or("".into()));
-            vec![]
-        }
-    }
+use tauri::{api::dialog, async_runtime, AppHandle, Manager, State};
+
+fn get_by<T: Retrieve>(
+    db: &ConnectionWrapper,
+    field: T::Field,
+    value: impl Into<sqlite::Value>,
+    order: Order<T::Field>,
+) -> Result<Vec<T>, Error> {
+    db.get_by::<T>(eq(field, value), order)
 }
 
-fn get_all<T: Retrieve>(db: &ConnectionWrapper) -> Vec<T> {
-    vec_result(db.get_all::<T>(Order::Default))
+fn get_one_by<T: Retrieve>(
+    db: &ConnectionWrapper,
+    field: T::Field,
+    value: impl Into<sqlite::Value>,
+) -> Result<Option<T>, Error> {
+    let result = get_by::<T>(db, field, value, Order::Default)?;
+    Ok(result.into_iter().next())
 }
 
-fn get_by<T: Retrieve>(db: &ConnectionWrapper, field: &str, value: &str, order: Order) -> Vec<T> {
-    vec_result(db.get_by::<T>(eq(field, value), order))
+fn get_filtered<T: Retrieve>(
+    db: &ConnectionWrapper,
+    filter: Filter<T::Field>,
+) -> Result<Vec<T>, Error>
+where
+    T::Field: Copy,
+{
+    db.get_by::<T>(filter.condition()?, filter.order())
+}
+
+fn get_page<T: Retrieve>(
+    db: &ConnectionWrapper,
+    page: Page,
+    sort: Option<Vec<SortKey<T::Field>>>,
+) -> Result<Paged<T>, Error> {
+    let order = match sort {
+        Some(keys) => keys.into(),
+        None => Order::Default,
+    };
+
+    db.get_page::<T>(Condition::None, order, page)
 }
 
-fn get_one_by<T: Retrieve + Clone>(db: &ConnectionWrapper, field: &str, value: &str) -> Option<T> {
-    let result = get_by::<T>(db, field, value, Order::Default);
-    if result.len() == 0 {
-        None
-    } else {
-        Some(result[0].clone())
+// Runs f on a pooled reader. Queries run on the blocking thread pool so that a long one never holds
+// up the other commands or the UI.
+async fn read<T, F>(app_handle: &AppHandle, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&ConnectionWrapper) -> Result<T, Error> + Send + 'static,
+{
+    let pool = app_handle.state::<SharedPool>().get();
+    async_runtime::spawn_blocking(move || {
+        let db = pool.read()?;
+        f(&db)
+    })
+    .await?
+}
+
+// Like read but with the writer, which only one command or scan can hold at a time
+async fn write<T, F>(app_handle: &AppHandle, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&ConnectionWrapper) -> Result<T, Error> + Send + 'static,
+{
+    let pool = app_handle.state::<SharedPool>().get();
+    async_runtime::spawn_blocking(move || {
+        let db = pool.write()?;
+        f(&db)
+    })
+    .await?
+}
+
+// Like read but with the player, commands wait for mpv to answer
+async fn playback<T, F>(app_handle: &AppHandle, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&Player) -> Result<T, Error> + Send + 'static,
+{
+    let player = app_handle.state::<Arc<Player>>().inner().clone();
+    async_runtime::spawn_blocking(move || f(&player)).await?
+}
+
+// Runs a change to the play queue and tells the frontend about it with a queue_changed event
+async fn change_queue<F>(app_handle: &AppHandle, f: F) -> Result<PlayQueue, Error>
+where
+    F: FnOnce(&Player) -> Result<PlayQueue, Error> + Send + 'static,
+{
+    let queue = playback(app_handle, f).await?;
+    if let Err(err) = app_handle.emit_all("queue_changed", queue.clone()) {
+        println!("Error in sending the queue: {}", err);
     }
+    Ok(queue)
 }
 
-fn insert<T: Store>(db: &ConnectionWrapper, obj: &mut T) -> Result<(), sqlite::Error> {
-    match db.insert(obj) {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            println!("Error in main::insert, {}", err);
-            return Err(err);
-        }
+// Tells the frontend what mpv reported, see playback_observer
+fn on_player_event(app_handle: &AppHandle, event: PlayerEvent) {
+    if let Err(err) = send_player_event(app_handle, event) {
+        println!("Error in sending the player state: {}", err);
     }
 }
 
-fn insert_full<T: StoreFull>(db: &ConnectionWrapper, obj: &mut T) -> Result<(), sqlite::Error> {
-    match db.insert_full(obj) {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            println!("Error in main::insert_full, {}", err);
-            return Err(err);
+fn send_player_event(app_handle: &AppHandle, event: PlayerEvent) -> Result<(), Error> {
+    match event {
+        PlayerEvent::State(playback) => {
+            let state = app_handle.state::<Arc<Player>>().set_playback(playback)?;
+            app_handle.emit_all("player_state", state)?;
+        }
+        PlayerEvent::TrackChanged(path) => {
+            let song = playing_song(app_handle, path)?;
+            app_handle.emit_all("track_changed", song)?;
         }
+        PlayerEvent::PlaybackEnded => app_handle.emit_all("playback_ended", ())?,
     }
+    Ok(())
+}
+
+// The song of the file mpv started playing, None for files that aren't in the library
+fn playing_song(app_handle: &AppHandle, path: String) -> Result<Option<Song>, Error> {
+    let pool = app_handle.state::<SharedPool>().get();
+    let db = pool.read()?;
+    get_one_by::<Song>(&db, SongField::FilePath, path)
+}
+
+fn emit_player_state(app_handle: &AppHandle, state: PlayerState) {
+    if let Err(err) = app_handle.emit_all("player_state", state) {
+        println!("Error in sending the player state: {}", err);
+    }
+}
+
+// Reads the image cache directory of the active profile
+fn image_cache_dir(app_handle: &AppHandle) -> Result<String, Error> {
+    let config = app_handle.state::<Mutex<Config>>();
+    let config = config.lock()?;
+    match config.image_cache_dir().into_os_string().into_string() {
+        Ok(image_cache_dir) => Ok(image_cache_dir),
+        Err(_) => Err(Error::Config("Image cache path is not valid UTF-8".into())),
+    }
+}
+
+// Where scans take covers from and how many files they read at the same time
+fn scan_settings(app_handle: &AppHandle) -> Result<(CoverConfig, usize), Error> {
+    let config = app_handle.state::<Mutex<Config>>();
+    let config = config.lock()?;
+    let threads = config.scan_threads.unwrap_or_else(default_threads);
+    Ok((config.covers.clone(), threads))
 }
 
 #[tauri::command]
-fn get_all_albums(db: State<'_, Mutex<ConnectionWrapper>>) -> Vec<Album> {
-    get_all::<Album>(&db.lock().unwrap())
+async fn get_all_albums(app_handle: AppHandle) -> Result<Vec<Album>, Error> {
+    read(&app_handle, |db| db.get_all::<Album>(Order::Default)).await
 }
 
 #[tauri::command]
-fn get_all_artists(db: State<'_, Mutex<ConnectionWrapper>>) -> Vec<Artist> {
-    get_all::<Artist>(&db.lock().unwrap())
+async fn get_all_artists(app_handle: AppHandle) -> Result<Vec<Artist>, Error> {
+    read(&app_handle, |db| db.get_all::<Artist>(Order::Default)).await
 }
 
 #[tauri::command]
-fn get_all_playlists(db: State<'_, Mutex<ConnectionWrapper>>) -> Vec<Playlist> {
-    get_all::<Playlist>(&db.lock().unwrap())
+async fn get_all_playlists(app_handle: AppHandle) -> Result<Vec<Playlist>, Error> {
+    read(&app_handle, |db| db.get_all::<Playlist>(Order::Default)).await
 }
 
 #[tauri::command]
-fn get_all_tags(db: State<'_, Mutex<ConnectionWrapper>>) -> Vec<Tag> {
-    get_all::<Tag>(&db.lock().unwrap())
+async fn get_all_tags(app_handle: AppHandle) -> Result<Vec<Tag>, Error> {
+    read(&app_handle, |db| db.get_all::<Tag>(Order::Default)).await
 }
 
 #[tauri::command]
-fn get_all_directories(db: State<'_, Mutex<ConnectionWrapper>>) -> Vec<Directory> {
-    get_all::<Directory>(&db.lock().unwrap())
+async fn get_all_directories(app_handle: AppHandle) -> Result<Vec<Directory>, Error> {
+    read(&app_handle, |db| db.get_all::<Directory>(Order::Default)).await
 }
 
+// Returns false if the user cancelled the picker
 #[tauri::command]
-async fn select_directory() -> bool {
+async fn select_directory(app_handle: AppHandle) -> Result<bool, Error> {
     // Directory picker
     let path = dialog::blocking::FileDialogBuilder::new()
         .set_title("Select music directory")
         .pick_folder();
 
-    let Some(path) = path else { return false; };
-    let Ok(path) = path.into_os_string().into_string() else { return false; };
+    let Some(path) = path else {
+        return Ok(false);
+    };
+    let Ok(path) = path.into_os_string().into_string() else {
+        return Err(Error::Validation(
+            "Directory path is not valid UTF-8".into(),
+        ));
+    };
 
     let mut directory = Directory {
         directory_id: None,
@@ -111,29 +229,17 @@ async fn select_directory() -> bool {
     };
 
     // Insert into db
-    let db = get_db();
-    let res = db.insert(&mut directory);
-    if let Err(_) = res {
-        return false;
-    }
-
-    true
+    write(&app_handle, move |db| db.insert(&mut directory)).await?;
+    restart_watcher(&app_handle)?;
+    Ok(true)
 }
 
 //  TODO: Some kind of image scaling / optimization
 //  For example don't load the full res images for gridviews etc.
 #[tauri::command]
-async fn select_cover(
-    id: i64,
-    playlist: bool,
-    db: State<'_, Mutex<ConnectionWrapper>>,
-    app_handle: AppHandle,
-) -> Result<(), ()> {
-    let Ok(db) = db.lock() else { return Err(()) };
-
-    // Get application data directory
-    let Some(data_dir) = app_handle.path_resolver().app_data_dir() else { return Err(()) };
-    let Some(data_dir) = data_dir.to_str() else { return Err(()) };
+async fn select_cover(id: i64, playlist: bool, app_handle: AppHandle) -> Result<(), Error> {
+    // Get the image cache directory of the current profile
+    let image_cache_dir = image_cache_dir(&app_handle)?;
 
     // File picker
     let path = dialog::blocking::FileDialogBuilder::new()
@@ -146,119 +252,387 @@ async fn select_cover(
         .set_title("Select cover")
         .pick_file();
 
-    let Some(path) = path else { return Err(()); };
+    // Cancelled
+    let Some(path) = path else {
+        return Ok(());
+    };
 
-    let Some(extension) = path.extension() else { return Err(()) };
-    let Ok(extension) = extension.to_os_string().into_string() else { return Err(()) };
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+        return Err(Error::Validation("Cover has no file extension".into()));
+    };
+    let extension = extension.to_string();
 
     // Read image file
-    let Ok(image_data) = fs::read(path) else { return Err(()) };
+    let image_data = fs::read(&path)?;
 
     // Save image
     let (cover_path, cover_path_small, cover_path_tiny) =
-        save_cover(&image_data, &extension, data_dir);
-    let Some(cover_path) = cover_path else { return Err(()) };
-    let Some(cover_path_small) = cover_path_small else { return Err(()) };
-    let Some(cover_path_tiny) = cover_path_tiny else { return Err(()) };
+        save_cover(&image_data, &extension, &image_cache_dir);
+    let (Some(cover_path), Some(cover_path_small), Some(cover_path_tiny)) =
+        (cover_path, cover_path_small, cover_path_tiny)
+    else {
+        return Err(Error::Image(format!(
+            "Could not save cover {}",
+            path.to_string_lossy()
+        )));
+    };
 
     // Insert into db
-    if let Err(err) = update_cover(
-        &db,
-        id,
-        playlist,
-        cover_path,
-        cover_path_small,
-        cover_path_tiny,
-    ) {
-        println!("Error in select_cover, {}", err);
-    }
-
-    Ok(())
+    write(&app_handle, move |db| {
+        update_cover(
+            db,
+            id,
+            playlist,
+            cover_path,
+            cover_path_small,
+            cover_path_tiny,
+        )
+    })
+    .await
 }
 
 #[tauri::command]
-fn delete_directory(directory_id: i64, db: State<'_, Mutex<ConnectionWrapper>>) {
+async fn delete_directory(directory_id: i64, app_handle: AppHandle) -> Result<(), Error> {
     let dir = Directory {
         directory_id: Some(directory_id),
         path: "".into(),
     };
-    let res = dir.delete(&db.lock().unwrap().conn);
-    if let Err(err) = res {
-        println!("{}", err);
+    write(&app_handle, move |db| dir.delete(&db.conn)).await?;
+    // The songs of the directory go with it
+    clean_up(&app_handle).await?;
+    restart_watcher(&app_handle)
+}
+
+// Removes songs whose files are gone, see content_library::clean_library
+#[tauri::command]
+async fn clean(app_handle: AppHandle) -> Result<CleanReport, Error> {
+    clean_up(&app_handle).await
+}
+
+// Like write, but the writer is only taken once the missing files have been looked up
+async fn clean_up(app_handle: &AppHandle) -> Result<CleanReport, Error> {
+    let pool = app_handle.state::<SharedPool>().get();
+    async_runtime::spawn_blocking(move || clean_with_pool(&pool)).await?
+}
+
+// Songs in the order of the ids, all of them have to exist
+async fn get_songs(app_handle: &AppHandle, song_ids: Vec<i64>) -> Result<Vec<Song>, Error> {
+    let ids = song_ids.clone();
+    let found = read(app_handle, move |db| {
+        db.get_by::<Song>(is_in(SongField::SongId, ids), Order::Default)
+    })
+    .await?;
+
+    let mut songs = vec![];
+    for id in song_ids {
+        match found.iter().find(|song| song.song_id == Some(id)) {
+            Some(song) => songs.push(song.clone()),
+            None => return Err(Error::NotFound(format!("No song with id {}", id))),
+        }
     }
+    Ok(songs)
 }
 
+async fn get_song(app_handle: &AppHandle, song_id: i64) -> Result<Song, Error> {
+    let song = read(app_handle, move |db| {
+        get_one_by::<Song>(db, SongField::SongId, song_id)
+    })
+    .await?;
+    match song {
+        Some(song) => Ok(song),
+        None => Err(Error::NotFound(format!("No song with id {}", song_id))),
+    }
+}
+
+// Adds the song to the queue right away, next or after everything else
 #[tauri::command]
-fn play_song(db: State<'_, Mutex<ConnectionWrapper>>, song_id: i64, queue: bool) {
-    let song = get_one_by::<Song>(
-        &db.lock().unwrap(),
-        "song.song_id",
-        &song_id.to_string()[..],
-    );
-    let Some(song) = song else { return; };
-    play_file(&song.file_path[..], queue);
+async fn play_song(
+    app_handle: AppHandle,
+    song_id: i64,
+    mode: PlayMode,
+) -> Result<PlayQueue, Error> {
+    let song = get_song(&app_handle, song_id).await?;
+    change_queue(&app_handle, move |player| player.add(vec![song], mode)).await
 }
 
+// Like play_song but with many songs, which stay in the given order
 #[tauri::command]
-fn get_artist_albums(db: State<'_, Mutex<ConnectionWrapper>>, artist_id: i64) -> Vec<Album> {
-    get_by(
-        &db.lock().unwrap(),
-        "album.artist_id",
-        &artist_id.to_string()[..],
-        Order::Default,
-    )
+async fn queue_songs(
+    app_handle: AppHandle,
+    song_ids: Vec<i64>,
+    mode: PlayMode,
+) -> Result<PlayQueue, Error> {
+    let songs = get_songs(&app_handle, song_ids).await?;
+    change_queue(&app_handle, move |player| player.add(songs, mode)).await
 }
 
+// Plays the song in place of the current one, the rest of the queue stays
 #[tauri::command]
-fn get_album(db: State<'_, Mutex<ConnectionWrapper>>, album_id: i64) -> Option<Album> {
-    get_one_by::<Album>(
-        &db.lock().unwrap(),
-        "album.album_id",
-        &album_id.to_string()[..],
-    )
+async fn replace_song(app_handle: AppHandle, song_id: i64) -> Result<PlayQueue, Error> {
+    let song = get_song(&app_handle, song_id).await?;
+    change_queue(&app_handle, move |player| player.replace_current(song)).await
 }
 
+// Replaces the queue with the songs, in the given order, and plays from the one at index
 #[tauri::command]
-fn get_album_songs(db: State<'_, Mutex<ConnectionWrapper>>, album_id: i64) -> Vec<Song> {
+async fn play_songs(
+    app_handle: AppHandle,
+    song_ids: Vec<i64>,
+    index: usize,
+) -> Result<PlayQueue, Error> {
+    let songs = get_songs(&app_handle, song_ids).await?;
+    change_queue(&app_handle, move |player| player.replace(songs, index)).await
+}
+
+// Replaces the queue with the songs of the album and plays from the one at index
+#[tauri::command]
+async fn play_album(
+    app_handle: AppHandle,
+    album_id: i64,
+    index: usize,
+) -> Result<PlayQueue, Error> {
+    let songs = read(&app_handle, move |db| album_songs(db, album_id)).await?;
+    change_queue(&app_handle, move |player| player.replace(songs, index)).await
+}
+
+// Like play_album but with the songs of a playlist
+#[tauri::command]
+async fn play_playlist(
+    app_handle: AppHandle,
+    playlist_id: i64,
+    index: usize,
+) -> Result<PlayQueue, Error> {
+    let songs = read(&app_handle, move |db| playlist_songs(db, playlist_id)).await?;
+    change_queue(&app_handle, move |player| player.replace(songs, index)).await
+}
+
+#[tauri::command]
+async fn get_queue(app_handle: AppHandle) -> Result<PlayQueue, Error> {
+    playback(&app_handle, |player| player.queue()).await
+}
+
+// Inserts the songs before the one at index, index can be the length of the queue to add them last
+#[tauri::command]
+async fn insert_into_queue(
+    app_handle: AppHandle,
+    song_ids: Vec<i64>,
+    index: usize,
+) -> Result<PlayQueue, Error> {
+    let songs = get_songs(&app_handle, song_ids).await?;
+    change_queue(&app_handle, move |player| player.insert(index, songs)).await
+}
+
+#[tauri::command]
+async fn move_in_queue(app_handle: AppHandle, from: usize, to: usize) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, move |player| player.move_song(from, to)).await
+}
+
+#[tauri::command]
+async fn remove_from_queue(app_handle: AppHandle, index: usize) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, move |player| player.remove(index)).await
+}
+
+#[tauri::command]
+async fn clear_queue(app_handle: AppHandle) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, |player| player.clear()).await
+}
+
+// Plays the song at index of the queue
+#[tauri::command]
+async fn jump_in_queue(app_handle: AppHandle, index: usize) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, move |player| player.jump(index)).await
+}
+
+#[tauri::command]
+async fn next_song(app_handle: AppHandle) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, |player| player.next()).await
+}
+
+#[tauri::command]
+async fn previous_song(app_handle: AppHandle) -> Result<PlayQueue, Error> {
+    change_queue(&app_handle, |player| player.previous()).await
+}
+
+#[tauri::command]
+async fn get_player_state(app_handle: AppHandle) -> Result<PlayerState, Error> {
+    playback(&app_handle, |player| player.state()).await
+}
+
+// Shuffles the songs that haven't been played yet, or puts them back in order
+#[tauri::command]
+async fn set_shuffle(app_handle: AppHandle, mode: ShuffleMode) -> Result<PlayQueue, Error> {
+    let queue = change_queue(&app_handle, move |player| player.set_shuffle(mode)).await?;
+    let state = playback(&app_handle, |player| player.state()).await?;
+    emit_player_state(&app_handle, state);
+    Ok(queue)
+}
+
+#[tauri::command]
+async fn set_repeat(app_handle: AppHandle, mode: RepeatMode) -> Result<PlayerState, Error> {
+    let state = playback(&app_handle, move |player| player.set_repeat(mode)).await?;
+    emit_player_state(&app_handle, state.clone());
+    Ok(state)
+}
+
+// mpv reports the change back in a player_state event
+#[tauri::command]
+async fn set_paused(app_handle: AppHandle, paused: bool) -> Result<(), Error> {
+    playback(&app_handle, move |player| match paused {
+        true => player.mpv().pause(),
+        false => player.mpv().resume(),
+    })
+    .await
+}
+
+// Seconds from the start of the song that is playing
+#[tauri::command]
+async fn seek(app_handle: AppHandle, seconds: f64) -> Result<(), Error> {
+    playback(&app_handle, move |player| {
+        player.mpv().seek(seconds, SeekMode::Absolute)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_artist_albums(app_handle: AppHandle, artist_id: i64) -> Result<Vec<Album>, Error> {
+    read(&app_handle, move |db| {
+        get_by(db, AlbumField::ArtistId, artist_id, Order::Default)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_album(app_handle: AppHandle, album_id: i64) -> Result<Option<Album>, Error> {
+    read(&app_handle, move |db| {
+        get_one_by::<Album>(db, AlbumField::AlbumId, album_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_album_songs(app_handle: AppHandle, album_id: i64) -> Result<Vec<Song>, Error> {
+    read(&app_handle, move |db| album_songs(db, album_id)).await
+}
+
+// In the order of the discs and tracks
+fn album_songs(db: &ConnectionWrapper, album_id: i64) -> Result<Vec<Song>, Error> {
     get_by::<Song>(
-        &db.lock().unwrap(),
-        "album.album_id",
-        &album_id.to_string()[..],
-        param::asc("song.disc, song.track"),
+        db,
+        SongField::AlbumId,
+        album_id,
+        asc(SongField::Disc).then(asc(SongField::Track)),
     )
 }
 
 #[tauri::command]
-fn get_playlist(db: State<'_, Mutex<ConnectionWrapper>>, playlist_id: i64) -> Option<Playlist> {
-    get_one_by::<Playlist>(
-        &db.lock().unwrap(),
-        "playlist.playlist_id",
-        &playlist_id.to_string()[..],
-    )
+async fn get_playlist(app_handle: AppHandle, playlist_id: i64) -> Result<Option<Playlist>, Error> {
+    read(&app_handle, move |db| {
+        get_one_by::<Playlist>(db, PlaylistField::PlaylistId, playlist_id)
+    })
+    .await
 }
 
 #[tauri::command]
-fn get_playlist_songs(db: State<'_, Mutex<ConnectionWrapper>>, playlist_id: i64) -> Vec<Song> {
+async fn get_playlist_songs(app_handle: AppHandle, playlist_id: i64) -> Result<Vec<Song>, Error> {
+    read(&app_handle, move |db| playlist_songs(db, playlist_id)).await
+}
+
+fn playlist_songs(db: &ConnectionWrapper, playlist_id: i64) -> Result<Vec<Song>, Error> {
     get_by::<Song>(
-        &db.lock().unwrap(),
-        "playlist_song.playlist_id",
-        &playlist_id.to_string()[..],
-        param::asc("playlist_song.ordering"),
+        db,
+        SongField::PlaylistId,
+        playlist_id,
+        asc(SongField::PlaylistOrdering),
     )
 }
 
 #[tauri::command]
-fn create_tag(name: String, db: State<'_, Mutex<ConnectionWrapper>>) -> Option<Tag> {
+async fn get_songs_by(
+    app_handle: AppHandle,
+    filter: Filter<SongField>,
+) -> Result<Vec<Song>, Error> {
+    read(&app_handle, move |db| get_filtered(db, filter)).await
+}
+
+#[tauri::command]
+async fn get_albums_by(
+    app_handle: AppHandle,
+    filter: Filter<AlbumField>,
+) -> Result<Vec<Album>, Error> {
+    read(&app_handle, move |db| get_filtered(db, filter)).await
+}
+
+#[tauri::command]
+async fn get_artists_by(
+    app_handle: AppHandle,
+    filter: Filter<ArtistField>,
+) -> Result<Vec<Artist>, Error> {
+    read(&app_handle, move |db| get_filtered(db, filter)).await
+}
+
+#[tauri::command]
+async fn get_playlists_by(
+    app_handle: AppHandle,
+    filter: Filter<PlaylistField>,
+) -> Result<Vec<Playlist>, Error> {
+    read(&app_handle, move |db| get_filtered(db, filter)).await
+}
+
+// Paged versions of the get_all commands for the left panel, sort is a list of sort keys such as
+// [{ field: "year", direction: "desc", nulls: "last" }, { field: "name" }]
+#[tauri::command]
+async fn get_albums_page(
+    app_handle: AppHandle,
+    page: Page,
+    sort: Option<Vec<SortKey<AlbumField>>>,
+) -> Result<Paged<Album>, Error> {
+    read(&app_handle, move |db| get_page(db, page, sort)).await
+}
+
+#[tauri::command]
+async fn get_artists_page(
+    app_handle: AppHandle,
+    page: Page,
+    sort: Option<Vec<SortKey<ArtistField>>>,
+) -> Result<Paged<Artist>, Error> {
+    read(&app_handle, move |db| get_page(db, page, sort)).await
+}
+
+#[tauri::command]
+async fn get_songs_page(
+    app_handle: AppHandle,
+    page: Page,
+    sort: Option<Vec<SortKey<SongField>>>,
+) -> Result<Paged<Song>, Error> {
+    read(&app_handle, move |db| get_page(db, page, sort)).await
+}
+
+// Full-text search over the whole library, results are grouped by kind and best matches come first
+#[tauri::command]
+async fn search(
+    app_handle: AppHandle,
+    query: String,
+    limit: Option<i64>,
+) -> Result<SearchResults, Error> {
+    let limit = limit.unwrap_or(search::DEFAULT_LIMIT);
+    read(&app_handle, move |db| {
+        search::search(&db.conn, &query, limit)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn create_tag(name: String, app_handle: AppHandle) -> Result<Tag, Error> {
     let mut tag = Tag { tag_id: None, name };
-    let result = insert(&db.lock().unwrap(), &mut tag);
-    if let Ok(_) = result {
-        return Some(tag);
-    };
-    None
+    write(&app_handle, move |db| {
+        db.insert(&mut tag)?;
+        Ok(tag)
+    })
+    .await
 }
 
 #[tauri::command]
-fn create_playlist(name: String, db: State<'_, Mutex<ConnectionWrapper>>) -> Option<Playlist> {
+async fn create_playlist(name: String, app_handle: AppHandle) -> Result<Playlist, Error> {
     let mut playlist = Playlist {
         playlist_id: None,
         name,
@@ -267,110 +641,297 @@ fn create_playlist(name: String, db: State<'_, Mutex<ConnectionWrapper>>) -> Opt
         created: None,
         tags: Vec::new(),
     };
-    let result = insert_full(&db.lock().unwrap(), &mut playlist);
-    if let Ok(_) = result {
-        return Some(playlist);
-    };
-    None
+    write(&app_handle, move |db| {
+        db.insert_full(&mut playlist)?;
+        Ok(playlist)
+    })
+    .await
 }
 
+// Appends the songs to the end of the playlist, either all of them or none
 #[tauri::command]
-fn add_songs_to_playlist(
+async fn add_songs_to_playlist(
     song_ids: Vec<i64>,
     playlist_id: i64,
-    db: State<'_, Mutex<ConnectionWrapper>>,
-) -> Vec<PlaylistSong> {
-    let mut playlist_songs: Vec<PlaylistSong> = Vec::new();
-    let Ok(db) = db.lock() else { return playlist_songs };
-    let result = get_ordering_offset(&db, playlist_id);
-    let order_offset = match result {
-        Ok(v) => v,
-        Err(err) => {
-            println!(
-                "Error in main::add_songs_to_playlist, get_max_playlist_order: {}",
-                err
-            );
-            0
+    app_handle: AppHandle,
+) -> Result<Vec<PlaylistSong>, Error> {
+    write(&app_handle, move |db| {
+        db.in_transaction(|db| {
+            let order_offset = get_ordering_offset(db, playlist_id)?;
+            let mut playlist_songs: Vec<PlaylistSong> = Vec::new();
+
+            for (i, song_id) in song_ids.into_iter().enumerate() {
+                let mut playlist_song = PlaylistSong {
+                    playlist_song_id: None,
+                    added: None,
+                    song_id,
+                    playlist_id,
+                    ordering: order_offset + (i as i64),
+                };
+                db.insert(&mut playlist_song)?;
+                playlist_songs.push(playlist_song);
+            }
+            Ok(playlist_songs)
+        })
+    })
+    .await
+}
+
+// The scan running in the background, if any. Only one scan or update from the library watcher
+// runs at a time.
+pub struct ScanState {
+    running: Arc<Mutex<Option<(ScanKind, CancelToken)>>>,
+}
+
+// What claimed the library. Only scans the user started can be cancelled, an update from the
+// watcher would lose the changes it was applying.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanKind {
+    Scan,
+    WatcherUpdate,
+}
+
+impl ScanState {
+    // Claims the library, None if a scan or an update is running
+    fn start(&self, kind: ScanKind) -> Result<Option<ScanClaim>, Error> {
+        let mut running = self.running.lock()?;
+        if running.is_some() {
+            return Ok(None);
         }
-    };
+        let cancel = CancelToken::default();
+        *running = Some((kind, cancel.clone()));
+        Ok(Some(ScanClaim {
+            running: self.running.clone(),
+            cancel,
+        }))
+    }
+}
 
-    for (i, song_id) in song_ids.into_iter().enumerate() {
-        let mut playlist_song = PlaylistSong {
-            playlist_song_id: None,
-            added: None,
-            song_id,
-            playlist_id,
-            ordering: order_offset + (i as i64),
-        };
+// The library claimed by a scan. Dropping it lets the next scan start, so a scan that panics
+// doesn't keep the library claimed until restart.
+struct ScanClaim {
+    running: Arc<Mutex<Option<(ScanKind, CancelToken)>>>,
+    cancel: CancelToken,
+}
 
-        let result = insert::<PlaylistSong>(&db, &mut playlist_song);
-        if let Ok(_) = result {
-            playlist_songs.push(playlist_song);
-        };
+impl Drop for ScanClaim {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running.lock() {
+            *running = None;
+        }
     }
-    playlist_songs
 }
 
-//  TODO: use the tauri async commands instead of this crap
+// Starts scanning the library in the background. Progress is sent as scan_progress events and the
+// result as a scan_done event.
 #[tauri::command]
-fn scan(app_handle: AppHandle) {
-    thread::spawn(move || {
-        let db = get_db();
+async fn scan(app_handle: AppHandle) -> Result<(), Error> {
+    // Resolve the library now so that switching profiles mid-scan doesn't mix libraries
+    let pool = app_handle.state::<SharedPool>().get();
+    let image_cache_dir = image_cache_dir(&app_handle)?;
+    let (covers, threads) = scan_settings(&app_handle)?;
 
-        let Some(data_dir) = app_handle.path_resolver().app_data_dir() else { return; };
-        let Some(data_dir) = data_dir.to_str() else { return; };
+    let Some(claim) = app_handle.state::<ScanState>().start(ScanKind::Scan)? else {
+        return Err(Error::Busy("A scan is already running".into()));
+    };
 
-        let res = db.get_all::<Directory>(Order::Default);
-        let directories = match res {
-            Ok(v) => v,
-            Err(err) => {
-                println!("{}", err);
-                return;
+    let app = app_handle.clone();
+    async_runtime::spawn_blocking(move || {
+        let on_progress = |progress: &ScanProgress| {
+            if let Err(err) = app.emit_all("scan_progress", progress) {
+                println!("Error in command scan: {}", err);
             }
         };
 
-        for directory in directories {
-            println!("Scanning {}", directory.path);
-            if let Err(err) = scan_for_new_content(&directory.path, &db, data_dir) {
-                println!(
-                    "Error in command scan: {}",
-                    err.message.unwrap_or("".into())
-                );
+        let options = ScanOptions {
+            image_cache_dir: &image_cache_dir,
+            covers: &covers,
+            threads,
+            cancel: &claim.cancel,
+            on_progress: &on_progress,
+        };
+        let report = match scan_library(&pool, &options) {
+            Ok(report) => report,
+            Err(err) => {
+                println!("Error in command scan: {}", err);
+                ScanReport::default()
             }
-        }
+        };
 
-        app_handle
-            .emit_all::<Option<()>>("scan_done", None)
-            .unwrap();
+        // Done before telling the frontend so that it can start the next scan right away
+        drop(claim);
+        if let Err(err) = app.emit_all("scan_done", report) {
+            println!("Error in command scan: {}", err);
+        }
     });
+    Ok(())
 }
 
+// Stops the running scan after the file it is on, returns false if there's no scan running. Updates
+// from the library watcher are left to finish.
 #[tauri::command]
-fn edit_playlist(playlist: Playlist, db: State<'_, Mutex<ConnectionWrapper>>) {
-    let Ok(db) = db.lock() else { return };
-    if let Err(err) = update_playlist(&db, playlist) {
-        println!("Error in command edit_playlist, {}", err);
+async fn cancel_scan(state: State<'_, ScanState>) -> Result<bool, Error> {
+    let running = state.running.lock()?;
+    match running.as_ref() {
+        Some((ScanKind::Scan, cancel)) => {
+            cancel.cancel();
+            Ok(true)
+        }
+        Some((ScanKind::WatcherUpdate, _)) | None => Ok(false),
+    }
+}
+
+// Scans every directory of the library, then cleans up after the files that were deleted
+fn scan_library(pool: &Pool, options: &ScanOptions) -> Result<ScanReport, Error> {
+    let directories: Vec<String> = pool
+        .read()?
+        .get_all::<Directory>(Order::Default)?
+        .into_iter()
+        .map(|directory| directory.path)
+        .collect();
+
+    let report = scan_with_pool(&directories, pool, options)?;
+    println!(
+        "Scan done: {} added, {} updated, {} unchanged, {} errors",
+        report.added, report.updated, report.unchanged, report.errors
+    );
+    if report.cancelled {
+        println!("Scan cancelled");
+        return Ok(report);
+    }
+
+    // Files that were deleted since the last scan
+    match clean_with_pool(pool) {
+        Ok(cleaned) => println!(
+            "Clean done: {} songs removed, {} missing",
+            cleaned.songs_removed, cleaned.songs_missing
+        ),
+        Err(err) => println!("Error in cleaning the library: {}", err),
+    }
+    Ok(report)
+}
+
+// The library watcher of the active profile, None when watching is turned off
+pub struct WatcherState {
+    watcher: Mutex<Option<LibraryWatcher>>,
+}
+
+// Starts watching the directories of the active profile if watching is turned on, replacing the
+// watcher of the previous directories
+fn restart_watcher(app_handle: &AppHandle) -> Result<(), Error> {
+    let enabled = app_handle.state::<Mutex<Config>>().lock()?.watch_library;
+    let state = app_handle.state::<WatcherState>();
+    let mut watcher = state.watcher.lock()?;
+
+    // Dropping the watcher stops it
+    *watcher = None;
+    if !enabled {
+        return Ok(());
+    }
+
+    let directories: Vec<String> = app_handle
+        .state::<SharedPool>()
+        .get()
+        .read()?
+        .get_all::<Directory>(Order::Default)?
+        .into_iter()
+        .map(|directory| directory.path)
+        .collect();
+    let app = app_handle.clone();
+    *watcher = Some(LibraryWatcher::start(
+        &directories,
+        DEBOUNCE,
+        move |paths| on_library_change(&app, paths),
+    )?);
+    Ok(())
+}
+
+// Applies the changes the watcher saw and sends them as a library_changed event. Returns false if
+// a scan is running so that the watcher hands the changes over again later.
+fn on_library_change(app_handle: &AppHandle, paths: &[PathBuf]) -> bool {
+    let Ok(Some(claim)) = app_handle
+        .state::<ScanState>()
+        .start(ScanKind::WatcherUpdate)
+    else {
+        return false;
+    };
+    let changes = apply_library_changes(app_handle, paths, &claim.cancel);
+    drop(claim);
+
+    match changes {
+        Ok(changes) => {
+            if let Err(err) = app_handle.emit_all("library_changed", changes) {
+                println!("Error in watching the library: {}", err);
+            }
+        }
+        Err(err) => println!("Error in watching the library: {}", err),
+    }
+    true
+}
+
+fn apply_library_changes(
+    app_handle: &AppHandle,
+    paths: &[PathBuf],
+    cancel: &CancelToken,
+) -> Result<LibraryChanges, Error> {
+    let pool = app_handle.state::<SharedPool>().get();
+    let image_cache_dir = image_cache_dir(app_handle)?;
+    let (covers, threads) = scan_settings(app_handle)?;
+
+    let options = ScanOptions {
+        image_cache_dir: &image_cache_dir,
+        covers: &covers,
+        threads,
+        cancel,
+        on_progress: &|_| {},
     };
+    apply_changes(paths, &pool, &options)
+}
+
+#[tauri::command]
+async fn get_watch_library(config: State<'_, Mutex<Config>>) -> Result<bool, Error> {
+    Ok(config.lock()?.watch_library)
+}
+
+#[tauri::command]
+async fn set_watch_library(enabled: bool, app_handle: AppHandle) -> Result<(), Error> {
+    {
+        let config = app_handle.state::<Mutex<Config>>();
+        let mut config = config.lock()?;
+        config.watch_library = enabled;
+        config.save()?;
+    }
+    restart_watcher(&app_handle)
+}
+
+#[tauri::command]
+async fn edit_playlist(playlist: Playlist, app_handle: AppHandle) -> Result<(), Error> {
+    write(&app_handle, move |db| update_playlist(db, playlist)).await
 }
 
 // Initializes a Unix domain socket listener to be used by the musicbase web server
 // Basically allows us to send arbitrary tauri events to the frontend from an outside process
 #[tauri::command]
-fn init_ipc_socket(app_handle: AppHandle, state: State<Mutex<SocketListenerState>>) {
-    let Ok(mut state) = state.lock() else { return };
+async fn init_ipc_socket(app_handle: AppHandle) -> Result<(), Error> {
+    let state = app_handle.state::<Mutex<SocketListenerState>>();
+    let mut state = state.lock()?;
 
     if state.running {
-        return;
+        return Ok(());
     }
 
     let socket = "/tmp/musicbasetatularassocket";
-    if let Err(_) = fs::remove_file(&socket) {
-        return;
+    if let Err(err) = fs::remove_file(socket) {
+        // Left over from an earlier run unless it doesn't exist at all
+        if err.kind() != ErrorKind::NotFound {
+            return Err(err.into());
+        }
     };
-    let Ok(listener) = UnixListener::bind(socket) else { return };
+    let listener = UnixListener::bind(socket)?;
 
     // Simple protocol:
     // kind;payload
+    let app_handle = app_handle.clone();
     thread::spawn(move || loop {
         match listener.accept() {
             Ok((mut socket, _addr)) => {
@@ -396,8 +957,12 @@ fn init_ipc_socket(app_handle: AppHandle, state: State<Mutex<SocketListenerState
                 match kind {
                     // Ask for the application data directory
                     "datadir" => {
-                        let Some(data_dir) = app_handle.path_resolver().app_data_dir() else { continue };
-                        let Some(data_dir) = data_dir.to_str() else { continue };
+                        let Some(data_dir) = app_handle.path_resolver().app_data_dir() else {
+                            continue;
+                        };
+                        let Some(data_dir) = data_dir.to_str() else {
+                            continue;
+                        };
                         let _ = socket.write_all(data_dir.as_bytes());
                     }
                     // Just forward it as an tauri event to the frontend
@@ -410,12 +975,85 @@ fn init_ipc_socket(app_handle: AppHandle, state: State<Mutex<SocketListenerState
 
     // on success...
     state.running = true;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_profiles(app_handle: AppHandle) -> Result<Vec<Profile>, Error> {
+    let config = app_handle.state::<Mutex<Config>>();
+    let config = config.lock()?;
+    Ok(config.profiles.clone())
+}
+
+#[tauri::command]
+async fn get_active_profile(app_handle: AppHandle) -> Result<Profile, Error> {
+    let config = app_handle.state::<Mutex<Config>>();
+    let config = config.lock()?;
+    Ok(config.active())
+}
+
+#[tauri::command]
+async fn create_profile(name: String, config: State<'_, Mutex<Config>>) -> Result<Profile, Error> {
+    let mut config = config.lock()?;
+    let profile = config.add_profile(&name)?;
+    config.save()?;
+    Ok(profile)
+}
+
+// Swaps the library database under every command, the frontend should reload everything after
+// receiving the profile_changed event. Queries and scans already running finish on the old one.
+#[tauri::command]
+async fn switch_profile(
+    name: String,
+    app_handle: AppHandle,
+    pool: State<'_, SharedPool>,
+    config: State<'_, Mutex<Config>>,
+) -> Result<(), Error> {
+    let mut config = config.lock()?;
+
+    let mut new_config = config.clone();
+    new_config.switch_profile(&name)?;
+    new_config.ensure_dirs()?;
+    let new_pool = open_pool(&new_config.database_path())?;
+
+    pool.replace(new_pool);
+    *config = new_config;
+    config.save()?;
+
+    if let Err(err) = app_handle.emit_all("profile_changed", config.active()) {
+        println!("Error in command switch_profile: {}", err);
+    }
+
+    // The watcher reads the config
+    drop(config);
+    restart_watcher(&app_handle)
+}
+
+fn open_pool(path: &Path) -> Result<Pool, Error> {
+    match Pool::open(path, DEFAULT_READERS) {
+        Ok(pool) => Ok(pool),
+        Err(Error::Database(message)) => Err(Error::Database(format!(
+            "Could not open {}: {}",
+            path.to_string_lossy(),
+            message
+        ))),
+        Err(err) => Err(err),
+    }
 }
 
-fn get_db() -> ConnectionWrapper {
-    ConnectionWrapper {
-        conn: sqlite::open("/home/tatu/test.db").expect("Connection failed"),
+fn load_config(app: &tauri::App) -> Config {
+    let resolver = app.path_resolver();
+    let data_dir = resolver
+        .app_data_dir()
+        .expect("No application data directory");
+    let config_dir = resolver.app_config_dir().unwrap_or(data_dir.clone());
+
+    let mut config = Config::load(&config_dir.join("config.json"), &data_dir);
+    let args: Vec<String> = env::args().collect();
+    if let Err(err) = config.apply_overrides(&args, &env::vars().collect()) {
+        println!("{}", err);
     }
+    config
 }
 
 pub struct SocketListenerState {
@@ -423,14 +1061,17 @@ pub struct SocketListenerState {
 }
 
 fn main() {
-    let db = get_db();
-    let _ = db.create_schema();
-    start_mpv_process().unwrap();
+    if let Err(err) = start_mpv_process(MPV_SOCKET) {
+        println!("{}", err);
+    }
 
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             get_all_albums,
             scan,
+            cancel_scan,
+            get_watch_library,
+            set_watch_library,
             get_all_artists,
             get_all_playlists,
             get_all_tags,
@@ -439,6 +1080,24 @@ fn main() {
             get_all_directories,
             select_directory,
             play_song,
+            replace_song,
+            play_songs,
+            play_album,
+            play_playlist,
+            queue_songs,
+            get_queue,
+            insert_into_queue,
+            move_in_queue,
+            remove_from_queue,
+            clear_queue,
+            jump_in_queue,
+            next_song,
+            previous_song,
+            get_player_state,
+            set_shuffle,
+            set_repeat,
+            set_paused,
+            seek,
             get_artist_albums,
             create_playlist,
             get_playlist,
@@ -449,10 +1108,44 @@ fn main() {
             add_songs_to_playlist,
             edit_playlist,
             init_ipc_socket,
+            get_profiles,
+            get_active_profile,
+            create_profile,
+            switch_profile,
+            get_songs_by,
+            get_albums_by,
+            get_artists_by,
+            get_playlists_by,
+            get_albums_page,
+            get_artists_page,
+            get_songs_page,
+            search,
+            clean,
         ])
         .setup(|app| {
-            app.manage(Mutex::new(db));
+            let config = load_config(app);
+            config.ensure_dirs()?;
+            let pool = open_pool(&config.database_path())?;
+
+            app.manage(SharedPool::new(pool));
+            app.manage(ScanState {
+                running: Arc::new(Mutex::new(None)),
+            });
+            app.manage(WatcherState {
+                watcher: Mutex::new(None),
+            });
+            app.manage(Mutex::new(config));
+            app.manage(Arc::new(Player::new(Mpv::new(MPV_SOCKET))));
+
+            let app_handle = app.handle();
+            app.manage(PlaybackObserver::start(MPV_SOCKET, move |event| {
+                on_player_event(&app_handle, event)
+            }));
             app.manage(Mutex::new(SocketListenerState { running: false }));
+
+            if let Err(err) = restart_watcher(&app.handle()) {
+                println!("Error in watching the library: {}", err);
+            }
             Ok(())
         })
         .run(tauri::generate_context!())
@@ -0,0 +1,685 @@
+use sqlite::{Connection, State};
+
+use crate::error::Error;
+
+// A single schema upgrade step, applied in order of the version number
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> Result<(), Error>,
+}
+
+// All migrations, ordered by version. Never edit or reorder a migration that has already been
+// released, add a new one to the end instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "Album cover thumbnails",
+        up: album_cover_thumbnails,
+    },
+    Migration {
+        version: 3,
+        description: "Full-text search index",
+        up: search_index,
+    },
+    Migration {
+        version: 4,
+        description: "Foreign keys and indexes",
+        up: foreign_keys_and_indexes,
+    },
+    Migration {
+        version: 5,
+        description: "File state for incremental scans",
+        up: file_state,
+    },
+    Migration {
+        version: 6,
+        description: "Missing songs",
+        up: missing_songs,
+    },
+    Migration {
+        version: 7,
+        description: "Content hashes",
+        up: content_hashes,
+    },
+    Migration {
+        version: 8,
+        description: "Audio properties",
+        up: audio_properties,
+    },
+];
+
+// The schema version this build of the application expects
+pub fn current_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn get_version(conn: &Connection) -> Result<i64, Error> {
+    get_pragma(conn, "user_version")
+}
+
+// Reads an integer valued pragma
+pub fn get_pragma(conn: &Connection, name: &str) -> Result<i64, Error> {
+    let mut statement = conn.prepare(format!("PRAGMA {}", name))?;
+
+    if let Ok(State::Row) = statement.next() {
+        return Ok(statement.read::<i64, _>(0)?);
+    }
+
+    Ok(0)
+}
+
+fn set_version(conn: &Connection, version: i64) -> Result<(), Error> {
+    // Pragmas can't be parameterized, version is always an integer though
+    conn.execute(format!("PRAGMA user_version = {}", version))?;
+    Ok(())
+}
+
+// Brings the database schema up to date, applying every migration newer than the version stored
+// in the database. Each migration runs in its own transaction so a failing step leaves the
+// database at the previous version.
+pub fn migrate(conn: &Connection) -> Result<(), Error> {
+    let version = get_version(conn)?;
+
+    if version > current_version() {
+        return Err(Error::Database(format!(
+            "Database schema version {} is newer than the supported version {}",
+            version,
+            current_version()
+        )));
+    }
+
+    // Rebuilding a table means dropping the old one, which would cascade with foreign keys on.
+    // The pragma has no effect inside a transaction so it's toggled around all of them.
+    let foreign_keys = get_pragma(conn, "foreign_keys")? == 1;
+    conn.execute("PRAGMA foreign_keys = OFF")?;
+
+    let result = apply_migrations(conn, version);
+
+    if foreign_keys {
+        conn.execute("PRAGMA foreign_keys = ON")?;
+    }
+    result
+}
+
+fn apply_migrations(conn: &Connection, version: i64) -> Result<(), Error> {
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        conn.execute("BEGIN TRANSACTION")?;
+
+        let result = (migration.up)(conn)
+            .and_then(|_| check_foreign_keys(conn))
+            .and_then(|_| set_version(conn, migration.version));
+
+        if let Err(error) = result {
+            conn.execute("ROLLBACK")?;
+            println!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.description, error
+            );
+            return Err(error);
+        }
+
+        conn.execute("COMMIT")?;
+    }
+
+    Ok(())
+}
+
+// Foreign keys aren't enforced during migrations, so each one has to leave the data consistent
+fn check_foreign_keys(conn: &Connection) -> Result<(), Error> {
+    let mut statement = conn.prepare("PRAGMA foreign_key_check")?;
+
+    if let Ok(State::Row) = statement.next() {
+        let table = statement.read::<String, _>("table")?;
+        return Err(Error::Database(format!(
+            "Foreign key violation in table {}",
+            table
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, Error> {
+    let query = "SELECT COUNT(*) FROM pragma_table_info(:table) WHERE name = :column";
+    let mut statement = conn.prepare(query)?;
+    statement.bind((":table", table))?;
+    statement.bind((":column", column))?;
+
+    if let Ok(State::Row) = statement.next() {
+        return Ok(statement.read::<i64, _>(0)? > 0);
+    }
+
+    Ok(false)
+}
+
+// Adds a column unless it already exists, for columns that some older databases already have
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+    if has_column(conn, table, column)? {
+        return Ok(());
+    }
+    conn.execute(format!(
+        "ALTER TABLE {} ADD COLUMN {} {}",
+        table, column, definition
+    ))?;
+    Ok(())
+}
+
+// Version 1, the schema as it was before migrations existed. Uses IF NOT EXISTS so that databases
+// created by the old create_schema get adopted as-is.
+fn initial_schema(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS artist (
+            artist_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE
+        );
+
+        CREATE TABLE IF NOT EXISTS album (
+            album_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            artist_id INTEGER,
+            cover_path TEXT,
+            year INTEGER,
+            total_tracks INTEGER,
+            total_discs INTEGER,
+            UNIQUE (artist_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS song (
+            song_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            file_path TEXT NOT NULL UNIQUE,
+            track INTEGER,
+            disc INTEGER,
+            duration_s FLOATING,
+            quality INTEGER NOT NULL,
+            genre TEXT,
+            artist_id INTEGER,
+            album_id INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS playlist (
+            playlist_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            desc TEXT NOT NULL,
+            cover_path TEXT,
+            cover_path_small TEXT,
+            cover_path_tiny TEXT,
+            created TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS playlist_song (
+            playlist_song_id INTEGER PRIMARY KEY,
+            song_id INTEGER NOT NULL,
+            playlist_id INTEGER NOT NULL,
+            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            ordering INTEGER DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS tag (
+            tag_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE
+        );
+
+        CREATE TABLE IF NOT EXISTS playlist_tag (
+            playlist_tag_id INTEGER PRIMARY KEY,
+            playlist_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            added TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (playlist_id, tag_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS album_tag (
+            album_tag_id INTEGER PRIMARY KEY,
+            album_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (album_id, tag_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS directory (
+            directory_id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+// Version 2, resized cover images. Databases created by the later versions of create_schema
+// already have these columns.
+fn album_cover_thumbnails(conn: &Connection) -> Result<(), Error> {
+    add_column(conn, "album", "cover_path_small", "TEXT")?;
+    add_column(conn, "album", "cover_path_tiny", "TEXT")?;
+    Ok(())
+}
+
+// Version 3, full-text search over songs, albums, artists and playlists. The index is kept in sync
+// by triggers, see search.rs for the rowid scheme.
+fn search_index(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "
+        CREATE VIRTUAL TABLE search_index USING fts5(
+            name,
+            extra,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        -- Songs, kind 0
+        CREATE TRIGGER song_search_insert AFTER INSERT ON song BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.song_id * 4,
+                new.name,
+                coalesce((SELECT name FROM artist WHERE artist_id = new.artist_id), '') || ' ' ||
+                coalesce((SELECT name FROM album WHERE album_id = new.album_id), '') || ' ' ||
+                coalesce(new.genre, '')
+            );
+        END;
+
+        CREATE TRIGGER song_search_delete AFTER DELETE ON song BEGIN
+            DELETE FROM search_index WHERE rowid = old.song_id * 4;
+        END;
+
+        CREATE TRIGGER song_search_update AFTER UPDATE ON song BEGIN
+            DELETE FROM search_index WHERE rowid = old.song_id * 4;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.song_id * 4,
+                new.name,
+                coalesce((SELECT name FROM artist WHERE artist_id = new.artist_id), '') || ' ' ||
+                coalesce((SELECT name FROM album WHERE album_id = new.album_id), '') || ' ' ||
+                coalesce(new.genre, '')
+            );
+        END;
+
+        -- Albums, kind 1
+        CREATE TRIGGER album_search_insert AFTER INSERT ON album BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.album_id * 4 + 1,
+                new.name,
+                (SELECT name FROM artist WHERE artist_id = new.artist_id)
+            );
+        END;
+
+        CREATE TRIGGER album_search_delete AFTER DELETE ON album BEGIN
+            DELETE FROM search_index WHERE rowid = old.album_id * 4 + 1;
+        END;
+
+        CREATE TRIGGER album_search_update AFTER UPDATE ON album BEGIN
+            DELETE FROM search_index WHERE rowid = old.album_id * 4 + 1;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.album_id * 4 + 1,
+                new.name,
+                (SELECT name FROM artist WHERE artist_id = new.artist_id)
+            );
+        END;
+
+        -- Songs are indexed with the names of their album and artist, so renames have to reach them
+        CREATE TRIGGER album_search_songs AFTER UPDATE OF name ON album BEGIN
+            UPDATE search_index SET extra = (
+                SELECT coalesce(artist.name, '') || ' ' || new.name || ' ' || coalesce(song.genre, '')
+                FROM song
+                LEFT JOIN artist ON artist.artist_id = song.artist_id
+                WHERE song.song_id = search_index.rowid / 4
+            )
+            WHERE rowid IN (SELECT song_id * 4 FROM song WHERE album_id = new.album_id);
+        END;
+
+        -- Artists, kind 2
+        CREATE TRIGGER artist_search_insert AFTER INSERT ON artist BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.artist_id * 4 + 2,
+                new.name,
+                NULL
+            );
+        END;
+
+        CREATE TRIGGER artist_search_delete AFTER DELETE ON artist BEGIN
+            DELETE FROM search_index WHERE rowid = old.artist_id * 4 + 2;
+        END;
+
+        CREATE TRIGGER artist_search_update AFTER UPDATE ON artist BEGIN
+            DELETE FROM search_index WHERE rowid = old.artist_id * 4 + 2;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.artist_id * 4 + 2,
+                new.name,
+                NULL
+            );
+        END;
+
+        CREATE TRIGGER artist_search_songs AFTER UPDATE OF name ON artist BEGIN
+            UPDATE search_index SET extra = (
+                SELECT new.name || ' ' || coalesce(album.name, '') || ' ' || coalesce(song.genre, '')
+                FROM song
+                LEFT JOIN album ON album.album_id = song.album_id
+                WHERE song.song_id = search_index.rowid / 4
+            )
+            WHERE rowid IN (SELECT song_id * 4 FROM song WHERE artist_id = new.artist_id);
+
+            UPDATE search_index SET extra = new.name
+            WHERE rowid IN (SELECT album_id * 4 + 1 FROM album WHERE artist_id = new.artist_id);
+        END;
+
+        -- Playlists, kind 3
+        CREATE TRIGGER playlist_search_insert AFTER INSERT ON playlist BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.playlist_id * 4 + 3,
+                new.name,
+                new.desc
+            );
+        END;
+
+        CREATE TRIGGER playlist_search_delete AFTER DELETE ON playlist BEGIN
+            DELETE FROM search_index WHERE rowid = old.playlist_id * 4 + 3;
+        END;
+
+        CREATE TRIGGER playlist_search_update AFTER UPDATE ON playlist BEGIN
+            DELETE FROM search_index WHERE rowid = old.playlist_id * 4 + 3;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.playlist_id * 4 + 3,
+                new.name,
+                new.desc
+            );
+        END;
+
+        -- Index everything that already exists
+        INSERT INTO search_index (rowid, name, extra)
+        SELECT
+            song.song_id * 4,
+            song.name,
+            coalesce(artist.name, '') || ' ' || coalesce(album.name, '') || ' ' || coalesce(song.genre, '')
+        FROM song
+        LEFT JOIN artist ON artist.artist_id = song.artist_id
+        LEFT JOIN album ON album.album_id = song.album_id;
+
+        INSERT INTO search_index (rowid, name, extra)
+        SELECT album.album_id * 4 + 1, album.name, artist.name
+        FROM album
+        LEFT JOIN artist ON artist.artist_id = album.artist_id;
+
+        INSERT INTO search_index (rowid, name, extra)
+        SELECT artist_id * 4 + 2, name, NULL FROM artist;
+
+        INSERT INTO search_index (rowid, name, extra)
+        SELECT playlist_id * 4 + 3, name, desc FROM playlist;
+        ",
+    )
+}
+
+// Version 4, foreign keys and indexes on the join and filter columns. SQLite can't add constraints
+// to existing tables so the referencing tables get rebuilt. References that point nowhere are
+// cleared on the way, older databases can have them since foreign keys were never enforced.
+fn foreign_keys_and_indexes(conn: &Connection) -> Result<(), Error> {
+    // Renaming the new tables must not touch the triggers and foreign keys that refer to them by
+    // name, they refer to the final names already
+    conn.execute("PRAGMA legacy_alter_table = ON")?;
+    let result = rebuild_with_foreign_keys(conn);
+    conn.execute("PRAGMA legacy_alter_table = OFF")?;
+    result?;
+
+    conn.execute(
+        "
+        CREATE INDEX album_year ON album (year);
+        CREATE INDEX song_artist_id ON song (artist_id);
+        CREATE INDEX song_album_id ON song (album_id);
+        CREATE INDEX song_genre ON song (genre);
+        CREATE INDEX playlist_song_playlist_id ON playlist_song (playlist_id, ordering);
+        CREATE INDEX playlist_song_song_id ON playlist_song (song_id);
+        CREATE INDEX playlist_tag_tag_id ON playlist_tag (tag_id);
+        CREATE INDEX album_tag_tag_id ON album_tag (tag_id);
+        ",
+    )?;
+
+    // Dropping the old tables dropped their triggers as well
+    conn.execute(
+        "
+        -- Songs, kind 0
+        CREATE TRIGGER song_search_insert AFTER INSERT ON song BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.song_id * 4,
+                new.name,
+                coalesce((SELECT name FROM artist WHERE artist_id = new.artist_id), '') || ' ' ||
+                coalesce((SELECT name FROM album WHERE album_id = new.album_id), '') || ' ' ||
+                coalesce(new.genre, '')
+            );
+        END;
+
+        CREATE TRIGGER song_search_delete AFTER DELETE ON song BEGIN
+            DELETE FROM search_index WHERE rowid = old.song_id * 4;
+        END;
+
+        CREATE TRIGGER song_search_update AFTER UPDATE ON song BEGIN
+            DELETE FROM search_index WHERE rowid = old.song_id * 4;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.song_id * 4,
+                new.name,
+                coalesce((SELECT name FROM artist WHERE artist_id = new.artist_id), '') || ' ' ||
+                coalesce((SELECT name FROM album WHERE album_id = new.album_id), '') || ' ' ||
+                coalesce(new.genre, '')
+            );
+        END;
+
+        -- Albums, kind 1
+        CREATE TRIGGER album_search_insert AFTER INSERT ON album BEGIN
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.album_id * 4 + 1,
+                new.name,
+                (SELECT name FROM artist WHERE artist_id = new.artist_id)
+            );
+        END;
+
+        CREATE TRIGGER album_search_delete AFTER DELETE ON album BEGIN
+            DELETE FROM search_index WHERE rowid = old.album_id * 4 + 1;
+        END;
+
+        CREATE TRIGGER album_search_update AFTER UPDATE ON album BEGIN
+            DELETE FROM search_index WHERE rowid = old.album_id * 4 + 1;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.album_id * 4 + 1,
+                new.name,
+                (SELECT name FROM artist WHERE artist_id = new.artist_id)
+            );
+        END;
+
+        -- Songs are indexed with the names of their album and artist, so renames have to reach them
+        CREATE TRIGGER album_search_songs AFTER UPDATE OF name ON album BEGIN
+            UPDATE search_index SET extra = (
+                SELECT coalesce(artist.name, '') || ' ' || new.name || ' ' || coalesce(song.genre, '')
+                FROM song
+                LEFT JOIN artist ON artist.artist_id = song.artist_id
+                WHERE song.song_id = search_index.rowid / 4
+            )
+            WHERE rowid IN (SELECT song_id * 4 FROM song WHERE album_id = new.album_id);
+        END;
+        ",
+    )?;
+    Ok(())
+}
+
+fn rebuild_with_foreign_keys(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "
+        CREATE TABLE album_new (
+            album_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            artist_id INTEGER REFERENCES artist (artist_id) ON DELETE SET NULL,
+            cover_path TEXT,
+            cover_path_small TEXT,
+            cover_path_tiny TEXT,
+            year INTEGER,
+            total_tracks INTEGER,
+            total_discs INTEGER,
+            UNIQUE (artist_id, name)
+        );
+
+        INSERT INTO album_new (
+            album_id, name, artist_id, cover_path, cover_path_small, cover_path_tiny, year,
+            total_tracks, total_discs
+        )
+        SELECT
+            album_id,
+            name,
+            (SELECT artist_id FROM artist WHERE artist.artist_id = album.artist_id),
+            cover_path,
+            cover_path_small,
+            cover_path_tiny,
+            year,
+            total_tracks,
+            total_discs
+        FROM album;
+
+        DROP TABLE album;
+        ALTER TABLE album_new RENAME TO album;
+
+        CREATE TABLE song_new (
+            song_id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            file_path TEXT NOT NULL UNIQUE,
+            track INTEGER,
+            disc INTEGER,
+            duration_s FLOATING,
+            quality INTEGER NOT NULL,
+            genre TEXT,
+            artist_id INTEGER REFERENCES artist (artist_id) ON DELETE SET NULL,
+            album_id INTEGER REFERENCES album (album_id) ON DELETE SET NULL
+        );
+
+        INSERT INTO song_new (
+            song_id, name, file_path, track, disc, duration_s, quality, genre, artist_id, album_id
+        )
+        SELECT
+            song_id,
+            name,
+            file_path,
+            track,
+            disc,
+            duration_s,
+            quality,
+            genre,
+            (SELECT artist_id FROM artist WHERE artist.artist_id = song.artist_id),
+            (SELECT album_id FROM album WHERE album.album_id = song.album_id)
+        FROM song;
+
+        DROP TABLE song;
+        ALTER TABLE song_new RENAME TO song;
+
+        CREATE TABLE playlist_song_new (
+            playlist_song_id INTEGER PRIMARY KEY,
+            song_id INTEGER NOT NULL REFERENCES song (song_id) ON DELETE CASCADE,
+            playlist_id INTEGER NOT NULL REFERENCES playlist (playlist_id) ON DELETE CASCADE,
+            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            ordering INTEGER DEFAULT 0
+        );
+
+        INSERT INTO playlist_song_new (playlist_song_id, song_id, playlist_id, added, ordering)
+        SELECT playlist_song_id, song_id, playlist_id, added, ordering
+        FROM playlist_song
+        WHERE song_id IN (SELECT song_id FROM song)
+        AND playlist_id IN (SELECT playlist_id FROM playlist);
+
+        DROP TABLE playlist_song;
+        ALTER TABLE playlist_song_new RENAME TO playlist_song;
+
+        CREATE TABLE playlist_tag_new (
+            playlist_tag_id INTEGER PRIMARY KEY,
+            playlist_id INTEGER NOT NULL REFERENCES playlist (playlist_id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tag (tag_id) ON DELETE CASCADE,
+            added TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (playlist_id, tag_id)
+        );
+
+        INSERT INTO playlist_tag_new (playlist_tag_id, playlist_id, tag_id, added)
+        SELECT playlist_tag_id, playlist_id, tag_id, added
+        FROM playlist_tag
+        WHERE playlist_id IN (SELECT playlist_id FROM playlist)
+        AND tag_id IN (SELECT tag_id FROM tag);
+
+        DROP TABLE playlist_tag;
+        ALTER TABLE playlist_tag_new RENAME TO playlist_tag;
+
+        CREATE TABLE album_tag_new (
+            album_tag_id INTEGER PRIMARY KEY,
+            album_id INTEGER NOT NULL REFERENCES album (album_id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tag (tag_id) ON DELETE CASCADE,
+            added TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (album_id, tag_id)
+        );
+
+        INSERT INTO album_tag_new (album_tag_id, album_id, tag_id, added)
+        SELECT album_tag_id, album_id, tag_id, added
+        FROM album_tag
+        WHERE album_id IN (SELECT album_id FROM album)
+        AND tag_id IN (SELECT tag_id FROM tag);
+
+        DROP TABLE album_tag;
+        ALTER TABLE album_tag_new RENAME TO album_tag;
+        ",
+    )?;
+    Ok(())
+}
+
+// Version 5, what the scanner last saw of each file so that rescans can skip unchanged ones. The
+// times are unix timestamps in seconds. Recording a scan updates every song, so the search index
+// is only refreshed when an indexed column changes.
+fn file_state(conn: &Connection) -> Result<(), Error> {
+    add_column(conn, "song", "file_mtime", "INTEGER")?;
+    add_column(conn, "song", "file_size", "INTEGER")?;
+    add_column(conn, "song", "last_scanned", "INTEGER")?;
+
+    conn.execute(
+        "
+        DROP TRIGGER song_search_update;
+        CREATE TRIGGER song_search_update
+        AFTER UPDATE OF name, genre, artist_id, album_id ON song BEGIN
+            DELETE FROM search_index WHERE rowid = old.song_id * 4;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.song_id * 4,
+                new.name,
+                coalesce((SELECT name FROM artist WHERE artist_id = new.artist_id), '') || ' ' ||
+                coalesce((SELECT name FROM album WHERE album_id = new.album_id), '') || ' ' ||
+                coalesce(new.genre, '')
+            );
+        END;
+
+        DROP TRIGGER album_search_update;
+        CREATE TRIGGER album_search_update AFTER UPDATE OF name, artist_id ON album BEGIN
+            DELETE FROM search_index WHERE rowid = old.album_id * 4 + 1;
+            INSERT INTO search_index (rowid, name, extra) VALUES (
+                new.album_id * 4 + 1,
+                new.name,
+                (SELECT name FROM artist WHERE artist_id = new.artist_id)
+            );
+        END;
+        ",
+    )?;
+    Ok(())
+}
+
+// Version 6, songs whose file is gone but that are kept as placeholders in playlists
+fn missing_songs(conn: &Connection) -> Result<(), Error> {
+    add_column(conn, "song", "missing", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// Version 7, hashes of the audio data of the files so that moved files can be recognized
+fn content_hashes(conn: &Connection) -> Result<(), Error> {
+    add_column(conn, "song", "content_hash", "TEXT")?;
+    conn.execute("CREATE INDEX song_content_hash ON song (content_hash)")?;
+    Ok(())
+}
+
+// Version 8, technical properties of the audio streams. The file states are reset so that the next
+// scan reads every file again and fills them in.
+fn audio_properties(conn: &Connection) -> Result<(), Error> {
+    add_column(conn, "song", "codec", "TEXT")?;
+    add_column(conn, "song", "bitrate_kbps", "INTEGER")?;
+    add_column(conn, "song", "sample_rate_hz", "INTEGER")?;
+    add_column(conn, "song", "bit_depth", "INTEGER")?;
+    add_column(conn, "song", "channels", "INTEGER")?;
+    conn.execute("UPDATE song SET file_mtime = NULL, file_size = NULL")?;
+    Ok(())
+}
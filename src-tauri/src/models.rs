@@ -1,24 +1,45 @@
 use serde::Serialize;
+use sqlite::State;
 
-use crate::param::{Condition, Order};
+use crate::{
+    error::Error,
+    param::{AsQuery, Condition, Field, Order, Page},
+};
 
 pub mod base_metadata;
 pub mod user_generated;
 
 // Helpers
 
-pub fn err(message: &str) -> Result<(), sqlite::Error> {
-    Err(sqlite::Error {
-        code: None,
-        message: Some(message.to_string()),
-    })
-}
-
-fn ensure_valid(object: &impl Store) -> Result<(), sqlite::Error> {
+fn ensure_valid(object: &impl Store) -> Result<(), Error> {
     if object.is_valid() {
         return Ok(());
     }
-    err("Object not valid")
+    Err(Error::Validation("Object not valid".into()))
+}
+
+// Counts the distinct objects matching the condition. The from clause includes the joins of the
+// retrieval query so that every field can be used in the condition.
+fn count_by<F: Field>(
+    conn: &sqlite::Connection,
+    from: &str,
+    id: &str,
+    condition: &Condition<F>,
+) -> Result<i64, Error> {
+    let condition = condition.as_query(Condition::None);
+    let query = format!(
+        "SELECT COUNT(DISTINCT {}) AS count {} WHERE {}",
+        id, from, condition.sql
+    );
+
+    let mut statement = conn.prepare(query)?;
+    condition.bind(&mut statement)?;
+
+    if let Ok(State::Row) = statement.next() {
+        return Ok(statement.read::<i64, _>("count")?);
+    }
+
+    Ok(0)
 }
 
 // A trait that implements database (SQLite) insertion and retrieval for the object
@@ -26,20 +47,20 @@ pub trait Store {
     // Inserts the object into the database
     //
     // Takes a mutable reference to the object and fills in the id field to the newly inserted id
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error>;
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error>;
 
     // Returns true if an "overlapping" data point is found in the database, fills in id field of
     // to the found id
     // This method returning true for an object blocks the insert-method if it would violate a
     // UNIQUE-constraint otherwise. In other cases without an UNIQUE-constraint, such as with PlaylistSong,
     // exists can return true without an insert operation being blocked by it.
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error>;
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error>;
 
     // Objects where this method returns false cannot be stored into the database, use this for
     // validation
     fn is_valid(&self) -> bool;
 
-    fn delete(&self, _conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn delete(&self, _conn: &sqlite::Connection) -> Result<(), Error> {
         todo!()
     }
 }
@@ -47,13 +68,16 @@ pub trait Store {
 pub trait StoreFull {
     // Inserts the object and all contained objects into the db
     // Fills in the id field similarly to insert of the trait Store
-    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error>;
+    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), Error>;
 }
 
 pub trait Retrieve {
+    // The fields of the retrieval query that can be used in conditions and ordering
+    type Field: Field;
+
     // Returns a vector of all items of a given type.
     // Defined in terms of get_by, no need to define this manually.
-    fn get_all(conn: &sqlite::Connection, order: Order) -> Result<Vec<Self>, sqlite::Error>
+    fn get_all(conn: &sqlite::Connection, order: Order<Self::Field>) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
@@ -61,13 +85,40 @@ pub trait Retrieve {
     }
 
     // Takes a condition and returns all objects of the type that match that condition
+    // Defined in terms of get_page, no need to define this manually.
     fn get_by(
+        conn: &sqlite::Connection,
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+    ) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized,
+    {
+        Self::get_page(conn, condition, order, None)
+    }
+
+    // Like get_by but only returns the objects on the given page, or all of them if there's no page
+    fn get_page(
         _conn: &sqlite::Connection,
-        _condition: Condition,
-        _order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        _condition: Condition<Self::Field>,
+        _order: Order<Self::Field>,
+        _page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized;
+
+    // Returns the total amount of objects that match the condition, used for pagination
+    fn count(_conn: &sqlite::Connection, _condition: &Condition<Self::Field>)
+        -> Result<i64, Error>;
+}
+
+// One page of results and the total amount of results over all pages
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Copy, Serialize)]
@@ -84,3 +135,61 @@ impl From<i64> for Quality {
         }
     }
 }
+
+// Codecs of the supported audio formats, stored by name
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Codec {
+    Mp3,
+    Aac,
+    Vorbis,
+    Opus,
+    Flac,
+    Alac,
+    // Uncompressed audio in WAV and AIFF files
+    Pcm,
+    WavPack,
+    // Compressed audio in WAV and AIFF files, such as ADPCM or µ-law
+    Other,
+}
+
+impl Codec {
+    const ALL: [Codec; 9] = [
+        Codec::Mp3,
+        Codec::Aac,
+        Codec::Vorbis,
+        Codec::Opus,
+        Codec::Flac,
+        Codec::Alac,
+        Codec::Pcm,
+        Codec::WavPack,
+        Codec::Other,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Mp3 => "Mp3",
+            Codec::Aac => "Aac",
+            Codec::Vorbis => "Vorbis",
+            Codec::Opus => "Opus",
+            Codec::Flac => "Flac",
+            Codec::Alac => "Alac",
+            Codec::Pcm => "Pcm",
+            Codec::WavPack => "WavPack",
+            Codec::Other => "Other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Codec> {
+        Codec::ALL.into_iter().find(|codec| codec.name() == name)
+    }
+}
+
+// Technical properties of the audio of a song, read from the audio stream rather than the tags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct AudioProperties {
+    pub codec: Option<Codec>,
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate_hz: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+}
@@ -1,14 +1,15 @@
 use crate::param::AsQuery;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlite::State;
 
 use crate::{
     database,
-    param::{asc, Condition, Order},
+    error::Error,
+    param::{limit_query, Condition, Field, Order, Page},
     utils::{self, option_as_slice, option_cast, IntoOption},
 };
 
-use super::{ensure_valid, Quality, Retrieve, Store, StoreFull};
+use super::{count_by, ensure_valid, AudioProperties, Codec, Quality, Retrieve, Store, StoreFull};
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Artist {
@@ -18,7 +19,7 @@ pub struct Artist {
 }
 
 impl Store for Artist {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         if self.exists(conn)? {
@@ -36,7 +37,7 @@ impl Store for Artist {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT artist_id FROM artist WHERE name = :name LIMIT 1";
 
         let mut statement = conn.prepare(query)?;
@@ -57,8 +58,10 @@ impl Store for Artist {
         self.name.len() > 0
     }
 
-    fn delete(&self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
-        let Some(artist_id) = self.artist_id else { return Ok(()); };
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(artist_id) = self.artist_id else {
+            return Ok(());
+        };
 
         let query = "DELETE FROM artist WHERE artist_id = :artist_id";
         let mut statement = conn.prepare(query)?;
@@ -71,37 +74,66 @@ impl Store for Artist {
     }
 }
 
+const ARTIST_FROM: &str = "
+    FROM artist
+
+    LEFT JOIN album
+    ON album.artist_id = artist.artist_id
+    AND album.cover_path IS NOT NULL
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtistField {
+    ArtistId,
+    Name,
+}
+
+impl Field for ArtistField {
+    fn column(&self) -> &'static str {
+        match self {
+            ArtistField::ArtistId => "artist.artist_id",
+            ArtistField::Name => "artist.name",
+        }
+    }
+}
+
 impl Retrieve for Artist {
-    fn get_by(
+    type Field = ArtistField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order.by_key(ArtistField::ArtistId).as_query(Order::None);
         let query = format!(
             "SELECT 
             artist.artist_id, 
             artist.name, 
             album.cover_path AS artist_image_path 
 
-            FROM artist 
-
-            LEFT JOIN album
-            ON album.artist_id = artist.artist_id
-            AND album.cover_path IS NOT NULL
+            {}
 
             WHERE {}
 
             GROUP BY artist.artist_id
-            ORDER BY {}",
-            condition.as_query(Condition::None),
-            order.as_query(asc("artist.artist_id")),
+            ORDER BY {}
+            {}",
+            ARTIST_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut artists: Vec<Artist> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             let artist = Artist {
@@ -113,6 +145,10 @@ impl Retrieve for Artist {
         }
         Ok(artists)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, ARTIST_FROM, "artist.artist_id", condition)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -129,7 +165,7 @@ pub struct Album {
 }
 
 impl Store for Album {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         if self.exists(conn)? {
@@ -170,7 +206,7 @@ impl Store for Album {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         ensure_valid(self)?;
 
         let artist_id = match &self.artist {
@@ -214,10 +250,25 @@ impl Store for Album {
     fn is_valid(&self) -> bool {
         self.name.len() > 0
     }
+
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(album_id) = self.album_id else {
+            return Ok(());
+        };
+
+        let query = "DELETE FROM album WHERE album_id = :album_id";
+        let mut statement = conn.prepare(query)?;
+
+        statement.bind((":album_id", album_id))?;
+
+        database::execute_statement(&mut statement)?;
+
+        Ok(())
+    }
 }
 
 impl StoreFull for Album {
-    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         if let Some(artist) = &mut self.artist {
             artist.insert(conn)?;
         }
@@ -225,15 +276,53 @@ impl StoreFull for Album {
     }
 }
 
+const ALBUM_FROM: &str = "
+    FROM album
+
+    LEFT JOIN artist AS ar
+    ON album.artist_id = ar.artist_id
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumField {
+    AlbumId,
+    Name,
+    ArtistId,
+    ArtistName,
+    Year,
+    TotalTracks,
+    TotalDiscs,
+}
+
+impl Field for AlbumField {
+    fn column(&self) -> &'static str {
+        match self {
+            AlbumField::AlbumId => "album.album_id",
+            AlbumField::Name => "album.name",
+            AlbumField::ArtistId => "album.artist_id",
+            AlbumField::ArtistName => "ar.name",
+            AlbumField::Year => "album.year",
+            AlbumField::TotalTracks => "album.total_tracks",
+            AlbumField::TotalDiscs => "album.total_discs",
+        }
+    }
+}
+
 impl Retrieve for Album {
-    fn get_by(
+    type Field = AlbumField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order.by_key(AlbumField::AlbumId).as_query(Order::None);
         let query = format!(
             "SELECT
 
@@ -241,20 +330,21 @@ impl Retrieve for Album {
             album.cover_path, album.cover_path_small, album.cover_path_tiny,
             album.year, album.total_tracks, album.total_discs, ar.name AS artist_name
 
-            FROM album
-
-            LEFT JOIN artist AS ar
-            ON album.artist_id = ar.artist_id
+            {}
 
             WHERE {}
             ORDER BY {}
+            {}
             ",
-            condition.as_query(Condition::None),
-            order.as_query(asc("album.album_id")),
+            ALBUM_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut albums: Vec<Album> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             let artist_id = statement.read::<Option<i64>, _>("artist_id")?;
@@ -283,6 +373,10 @@ impl Retrieve for Album {
         }
         Ok(albums)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, ALBUM_FROM, "album.album_id", condition)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -294,13 +388,16 @@ pub struct Song {
     pub disc: Option<u16>,
     pub duration_s: Option<f64>,
     pub quality: Quality,
+    pub audio: AudioProperties,
     pub genre: Option<String>,
     pub artist: Option<Artist>,
     pub album: Option<Album>,
+    // The file is gone but the song is kept as a placeholder in the playlists it's in
+    pub missing: bool,
 }
 
 impl Store for Song {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         if self.exists(conn)? {
@@ -308,9 +405,11 @@ impl Store for Song {
         }
 
         let query = "INSERT INTO song
-        (name, file_path, track, disc, duration_s, quality, genre, artist_id, album_id)
+        (name, file_path, track, disc, duration_s, quality, codec, bitrate_kbps, sample_rate_hz,
+        bit_depth, channels, genre, artist_id, album_id, missing)
         VALUES
-        (:name, :file_path, :track, :disc, :duration_s, :quality, :genre, :artist_id, :album_id)
+        (:name, :file_path, :track, :disc, :duration_s, :quality, :codec, :bitrate_kbps,
+        :sample_rate_hz, :bit_depth, :channels, :genre, :artist_id, :album_id, :missing)
         ";
 
         let mut statement = conn.prepare(query)?;
@@ -333,16 +432,18 @@ impl Store for Song {
         statement.bind((":disc", self.disc.option_into()))?;
         statement.bind((":duration_s", self.duration_s))?;
         statement.bind((":quality", self.quality as i64))?;
+        bind_audio_properties(&mut statement, &self.audio)?;
         statement.bind((":genre", option_as_slice(&self.genre)))?;
         statement.bind((":artist_id", artist_id))?;
         statement.bind((":album_id", album_id))?;
+        statement.bind((":missing", self.missing as i64))?;
 
         database::execute_statement(&mut statement)?;
         self.song_id = Some(database::last_id(conn)?);
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         s.song_id FROM song AS s
 
@@ -367,10 +468,25 @@ impl Store for Song {
     fn is_valid(&self) -> bool {
         self.name.len() > 0 && self.file_path.len() > 0
     }
+
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(song_id) = self.song_id else {
+            return Ok(());
+        };
+
+        let query = "DELETE FROM song WHERE song_id = :song_id";
+        let mut statement = conn.prepare(query)?;
+
+        statement.bind((":song_id", song_id))?;
+
+        database::execute_statement(&mut statement)?;
+
+        Ok(())
+    }
 }
 
 impl StoreFull for Song {
-    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         if let Some(album) = &mut self.album {
             album.insert_full(conn)?;
         }
@@ -383,19 +499,120 @@ impl StoreFull for Song {
     }
 }
 
+// Binds the audio properties of a song to the :codec, :bitrate_kbps, :sample_rate_hz, :bit_depth and
+// :channels parameters of a statement
+pub fn bind_audio_properties(
+    statement: &mut sqlite::Statement,
+    audio: &AudioProperties,
+) -> Result<(), Error> {
+    statement.bind((":codec", audio.codec.map(|codec| codec.name())))?;
+    statement.bind((":bitrate_kbps", audio.bitrate_kbps.map(i64::from)))?;
+    statement.bind((":sample_rate_hz", audio.sample_rate_hz.map(i64::from)))?;
+    statement.bind((":bit_depth", audio.bit_depth.map(i64::from)))?;
+    statement.bind((":channels", audio.channels.map(i64::from)))?;
+    Ok(())
+}
+
+const SONG_FROM: &str = "
+    FROM song
+
+    LEFT JOIN artist
+    ON artist.artist_id = song.artist_id
+
+    LEFT JOIN album
+    ON album.album_id = song.album_id
+
+    LEFT JOIN artist AS album_artist
+    ON album_artist.artist_id = album.artist_id
+
+    LEFT JOIN playlist_song
+    ON playlist_song.song_id = song.song_id
+";
+
+// Songs in playlists can be filtered by the playlist and ordered by their position in it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SongField {
+    SongId,
+    Name,
+    FilePath,
+    Track,
+    Disc,
+    Duration,
+    Quality,
+    Genre,
+    ArtistId,
+    ArtistName,
+    AlbumId,
+    AlbumName,
+    AlbumArtistId,
+    AlbumArtistName,
+    Year,
+    PlaylistId,
+    PlaylistOrdering,
+    Missing,
+    Codec,
+    Bitrate,
+    SampleRate,
+    BitDepth,
+    Channels,
+    // Lossless songs with a higher resolution than CDs, more than 16 bits or 48 kHz
+    HiRes,
+}
+
+impl Field for SongField {
+    fn column(&self) -> &'static str {
+        match self {
+            SongField::SongId => "song.song_id",
+            SongField::Name => "song.name",
+            SongField::FilePath => "song.file_path",
+            SongField::Track => "song.track",
+            SongField::Disc => "song.disc",
+            SongField::Duration => "song.duration_s",
+            SongField::Quality => "song.quality",
+            SongField::Genre => "song.genre",
+            SongField::ArtistId => "song.artist_id",
+            SongField::ArtistName => "artist.name",
+            SongField::AlbumId => "song.album_id",
+            SongField::AlbumName => "album.name",
+            SongField::AlbumArtistId => "album.artist_id",
+            SongField::AlbumArtistName => "album_artist.name",
+            SongField::Year => "album.year",
+            SongField::PlaylistId => "playlist_song.playlist_id",
+            SongField::PlaylistOrdering => "playlist_song.ordering",
+            SongField::Missing => "song.missing",
+            SongField::Codec => "song.codec",
+            SongField::Bitrate => "song.bitrate_kbps",
+            SongField::SampleRate => "song.sample_rate_hz",
+            SongField::BitDepth => "song.bit_depth",
+            SongField::Channels => "song.channels",
+            SongField::HiRes => {
+                "(song.quality = 0
+                AND (coalesce(song.bit_depth, 0) > 16 OR coalesce(song.sample_rate_hz, 0) > 48000))"
+            }
+        }
+    }
+}
+
 impl Retrieve for Song {
-    fn get_by(
+    type Field = SongField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order.by_key(SongField::SongId).as_query(Order::None);
         let query = format!(
             "SELECT
             song.song_id, song.name, song.file_path, song.track, song.disc, 
-            song.duration_s, song.quality, song.genre, song.artist_id, song.album_id,
+            song.duration_s, song.quality, song.genre, song.artist_id, song.album_id, song.missing,
+            song.codec, song.bitrate_kbps, song.sample_rate_hz, song.bit_depth, song.channels,
 
             artist.name AS artist_name,
 
@@ -404,31 +621,22 @@ impl Retrieve for Song {
 
             album_artist.name AS album_artist_name
 
-            FROM song
-            
-            LEFT JOIN artist
-            ON artist.artist_id = song.artist_id
-            
-            LEFT JOIN album
-            ON album.album_id = song.album_id
-
-            LEFT JOIN artist AS album_artist
-            ON album_artist.artist_id = album.artist_id
-
-            LEFT JOIN playlist_song
-            ON playlist_song.song_id = song.song_id
-
+            {}
 
             WHERE {}
             GROUP BY song.song_id
             ORDER BY {}
+            {}
             ",
-            condition.as_query(Condition::None),
-            order.as_query(asc("song.song_id")),
+            SONG_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut songs: Vec<Song> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             let artist_id = statement.read::<Option<i64>, _>("artist_id")?;
@@ -446,6 +654,23 @@ impl Retrieve for Song {
                 disc: option_cast::<i64, u16>(statement.read::<Option<i64>, _>("disc")?),
                 duration_s: statement.read::<Option<f64>, _>("duration_s")?,
                 quality: statement.read::<i64, _>("quality")?.into(),
+                audio: AudioProperties {
+                    codec: statement
+                        .read::<Option<String>, _>("codec")?
+                        .and_then(|name| Codec::from_name(&name)),
+                    bitrate_kbps: statement
+                        .read::<Option<i64>, _>("bitrate_kbps")?
+                        .map(|bitrate| bitrate as u32),
+                    sample_rate_hz: statement
+                        .read::<Option<i64>, _>("sample_rate_hz")?
+                        .map(|sample_rate| sample_rate as u32),
+                    bit_depth: statement
+                        .read::<Option<i64>, _>("bit_depth")?
+                        .map(|bit_depth| bit_depth as u8),
+                    channels: statement
+                        .read::<Option<i64>, _>("channels")?
+                        .map(|channels| channels as u8),
+                },
                 genre: statement.read::<Option<String>, _>("genre")?,
                 artist: if artist_id.is_some() && artist_name.is_some() {
                     Some(Artist {
@@ -480,9 +705,14 @@ impl Retrieve for Song {
                 } else {
                     None
                 },
+                missing: statement.read::<i64, _>("missing")? != 0,
             };
             songs.push(song);
         }
         Ok(songs)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, SONG_FROM, "song.song_id", condition)
+    }
 }
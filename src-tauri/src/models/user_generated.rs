@@ -4,11 +4,12 @@ use sqlite::State;
 
 use crate::{
     database,
-    param::{asc, Condition, Order},
+    error::Error,
+    param::{limit_query, Condition, Field, Order, Page},
     utils::option_as_slice,
 };
 
-use super::{ensure_valid, Retrieve, Store, StoreFull};
+use super::{count_by, ensure_valid, Retrieve, Store, StoreFull};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Playlist {
@@ -21,7 +22,7 @@ pub struct Playlist {
 }
 
 impl Store for Playlist {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         if self.exists(conn)? {
@@ -45,7 +46,7 @@ impl Store for Playlist {
         Ok(())
     }
 
-    fn exists(&mut self, _conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, _conn: &sqlite::Connection) -> Result<bool, Error> {
         // The user can create multiple playlists with all the same data, which is expected
         // behaviour
         Ok(false)
@@ -54,42 +55,94 @@ impl Store for Playlist {
     fn is_valid(&self) -> bool {
         self.name.len() > 0
     }
+
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(playlist_id) = self.playlist_id else {
+            return Ok(());
+        };
+
+        let query = "DELETE FROM playlist WHERE playlist_id = :playlist_id";
+        let mut statement = conn.prepare(query)?;
+
+        statement.bind((":playlist_id", playlist_id))?;
+
+        database::execute_statement(&mut statement)?;
+
+        Ok(())
+    }
+}
+
+const PLAYLIST_FROM: &str = "
+    FROM playlist
+
+    LEFT JOIN playlist_tag AS pt
+    ON pt.playlist_id = playlist.playlist_id
+
+    LEFT JOIN tag AS t
+    ON t.tag_id = pt.tag_id
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistField {
+    PlaylistId,
+    Name,
+    Desc,
+    Created,
+    Tag,
+}
+
+impl Field for PlaylistField {
+    fn column(&self) -> &'static str {
+        match self {
+            PlaylistField::PlaylistId => "playlist.playlist_id",
+            PlaylistField::Name => "playlist.name",
+            PlaylistField::Desc => "playlist.desc",
+            PlaylistField::Created => "playlist.created",
+            PlaylistField::Tag => "t.name",
+        }
+    }
 }
 
 impl Retrieve for Playlist {
-    fn get_by(
+    type Field = PlaylistField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order
+            .by_key(PlaylistField::PlaylistId)
+            .as_query(Order::None);
         let query = format!(
             "SELECT
             playlist.playlist_id, playlist.name, playlist.desc, 
             playlist.cover_path, playlist.created,
             GROUP_CONCAT(t.name) AS tags
 
-            FROM playlist
-
-            LEFT JOIN playlist_tag AS pt
-            ON pt.playlist_id = playlist.playlist_id
+            {}
 
-            LEFT JOIN tag AS t
-            ON t.tag_id = pt.tag_id
-            
             WHERE {}
 
             GROUP BY playlist.playlist_id
             ORDER BY {}
+            {}
             ",
-            condition.as_query(Condition::None),
-            order.as_query(asc("playlist.playlist_id")),
+            PLAYLIST_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut playlists: Vec<Playlist> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             // basically .collect() but converts to String also
@@ -115,10 +168,14 @@ impl Retrieve for Playlist {
         }
         Ok(playlists)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, PLAYLIST_FROM, "playlist.playlist_id", condition)
+    }
 }
 
 impl StoreFull for Playlist {
-    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert_full(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         self.insert(conn)?;
         for tag in &self.tags {
             let mut tag = Tag {
@@ -127,8 +184,12 @@ impl StoreFull for Playlist {
             };
             tag.insert(conn)?;
 
-            let Some(tag_id) = tag.tag_id else { continue; };
-            let Some(playlist_id) = self.playlist_id else { continue; };
+            let Some(tag_id) = tag.tag_id else {
+                continue;
+            };
+            let Some(playlist_id) = self.playlist_id else {
+                continue;
+            };
 
             let mut playlist_tag = PlaylistTag {
                 playlist_tag_id: None,
@@ -151,7 +212,7 @@ pub struct PlaylistSong {
 }
 
 impl Store for PlaylistSong {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         // Note the missing "exists check"
@@ -173,7 +234,7 @@ impl Store for PlaylistSong {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         playlist_song_id FROM playlist_song
 
@@ -209,7 +270,7 @@ pub struct Tag {
 }
 
 impl Store for Tag {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         if self.exists(conn)? {
@@ -231,7 +292,7 @@ impl Store for Tag {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         tag_id 
         FROM tag
@@ -255,30 +316,73 @@ impl Store for Tag {
     fn is_valid(&self) -> bool {
         self.name.len() > 0
     }
+
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(tag_id) = self.tag_id else {
+            return Ok(());
+        };
+
+        let query = "DELETE FROM tag WHERE tag_id = :tag_id";
+        let mut statement = conn.prepare(query)?;
+
+        statement.bind((":tag_id", tag_id))?;
+
+        database::execute_statement(&mut statement)?;
+
+        Ok(())
+    }
+}
+
+const TAG_FROM: &str = "FROM tag";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagField {
+    TagId,
+    Name,
+}
+
+impl Field for TagField {
+    fn column(&self) -> &'static str {
+        match self {
+            TagField::TagId => "tag.tag_id",
+            TagField::Name => "tag.name",
+        }
+    }
 }
 
 impl Retrieve for Tag {
-    fn get_by(
+    type Field = TagField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order.by_key(TagField::TagId).as_query(Order::None);
         let query = format!(
             "SELECT 
             tag.tag_id, tag.name
-            FROM tag
+            {}
+
             WHERE {}
             ORDER BY {}
+            {}
             ",
-            condition.as_query(Condition::None),
-            order.as_query(asc("tag.tag_id")),
+            TAG_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut tags: Vec<Tag> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             let tag = Tag {
@@ -289,6 +393,10 @@ impl Retrieve for Tag {
         }
         Ok(tags)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, TAG_FROM, "tag.tag_id", condition)
+    }
 }
 
 pub struct PlaylistTag {
@@ -298,7 +406,7 @@ pub struct PlaylistTag {
 }
 
 impl Store for PlaylistTag {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         let query = "INSERT INTO playlist_tag
@@ -317,7 +425,7 @@ impl Store for PlaylistTag {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         playlist_tag_id FROM playlist_tag
 
@@ -353,7 +461,7 @@ pub struct AlbumTag {
 }
 
 impl Store for AlbumTag {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         let query = "INSERT INTO album_tag
@@ -372,7 +480,7 @@ impl Store for AlbumTag {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         album_tag_id FROM album_tag
 
@@ -408,7 +516,7 @@ pub struct Directory {
 }
 
 impl Store for Directory {
-    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
+    fn insert(&mut self, conn: &sqlite::Connection) -> Result<(), Error> {
         ensure_valid(self)?;
 
         let query = "INSERT INTO directory
@@ -426,7 +534,7 @@ impl Store for Directory {
         Ok(())
     }
 
-    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, sqlite::Error> {
+    fn exists(&mut self, conn: &sqlite::Connection) -> Result<bool, Error> {
         let query = "SELECT 
         directory_id 
         FROM directory
@@ -451,8 +559,10 @@ impl Store for Directory {
         self.path.len() > 0
     }
 
-    fn delete(&self, conn: &sqlite::Connection) -> Result<(), sqlite::Error> {
-        let Some(directory_id) = self.directory_id else { return Ok(()); };
+    fn delete(&self, conn: &sqlite::Connection) -> Result<(), Error> {
+        let Some(directory_id) = self.directory_id else {
+            return Ok(());
+        };
 
         let query = "DELETE FROM directory WHERE directory_id = :directory_id";
         let mut statement = conn.prepare(query)?;
@@ -465,28 +575,57 @@ impl Store for Directory {
     }
 }
 
+const DIRECTORY_FROM: &str = "FROM directory";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryField {
+    DirectoryId,
+    Path,
+}
+
+impl Field for DirectoryField {
+    fn column(&self) -> &'static str {
+        match self {
+            DirectoryField::DirectoryId => "directory.directory_id",
+            DirectoryField::Path => "directory.path",
+        }
+    }
+}
+
 impl Retrieve for Directory {
-    fn get_by(
+    type Field = DirectoryField;
+
+    fn get_page(
         conn: &sqlite::Connection,
-        condition: Condition,
-        order: Order,
-    ) -> Result<Vec<Self>, sqlite::Error>
+        condition: Condition<Self::Field>,
+        order: Order<Self::Field>,
+        page: Option<Page>,
+    ) -> Result<Vec<Self>, Error>
     where
         Self: Sized,
     {
+        let condition = condition.as_query(Condition::None);
+        let order = order
+            .by_key(DirectoryField::DirectoryId)
+            .as_query(Order::None);
         let query = format!(
             "SELECT directory_id, path
-            FROM directory
+            {}
 
             WHERE {}
             ORDER BY {}
+            {}
             ",
-            condition.as_query(Condition::None),
-            order.as_query(asc("directory.directory_id")),
+            DIRECTORY_FROM,
+            condition.sql,
+            order.sql,
+            limit_query(page),
         );
         let mut directories: Vec<Directory> = Vec::new();
 
         let mut statement = conn.prepare(query)?;
+        condition.bind(&mut statement)?;
 
         while let Ok(State::Row) = statement.next() {
             let directory = Directory {
@@ -497,4 +636,8 @@ impl Retrieve for Directory {
         }
         Ok(directories)
     }
+
+    fn count(conn: &sqlite::Connection, condition: &Condition<Self::Field>) -> Result<i64, Error> {
+        count_by(conn, DIRECTORY_FROM, "directory.directory_id", condition)
+    }
 }
@@ -2,11 +2,14 @@ use once_cell::sync::Lazy;
 
 use crate::{
     models::{
-        base_metadata::{Album, Artist, Song},
-        user_generated::{Playlist, PlaylistSong, Tag},
-        Quality,
+        base_metadata::{Album, AlbumField, Artist, ArtistField, Song, SongField},
+        user_generated::{Playlist, PlaylistField, PlaylistSong, Tag},
+        AudioProperties, Codec, Quality,
+    },
+    param::{
+        and, asc, between, desc, eq, gt, gte, is_in, is_null, like, lt, not, not_null, or,
+        Condition, Filter, Order, Page, SortKey,
     },
-    param::{asc, desc, eq, gt, gte, like, lt, search, Order},
     test_utils::get_mock_db,
 };
 
@@ -42,6 +45,8 @@ static SAMPLE_ALBUMS: Lazy<[Album; 3]> = Lazy::new(|| {
             name: "Suuria Kuvioita".into(),
             artist: Some(SAMPLE_ARTISTS[0].clone()),
             cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
             year: Some(2003),
             total_tracks: Some(10),
             total_discs: Some(1),
@@ -51,6 +56,8 @@ static SAMPLE_ALBUMS: Lazy<[Album; 3]> = Lazy::new(|| {
             name: "Homogenic".into(),
             artist: Some(SAMPLE_ARTISTS[1].clone()),
             cover_path: Some("path/to/cover/".into()),
+            cover_path_small: None,
+            cover_path_tiny: None,
             year: Some(1997),
             total_tracks: Some(10),
             total_discs: Some(1),
@@ -60,6 +67,8 @@ static SAMPLE_ALBUMS: Lazy<[Album; 3]> = Lazy::new(|| {
             name: "Empty album".into(),
             artist: None,
             cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
             year: None,
             total_tracks: None,
             total_discs: None,
@@ -80,6 +89,14 @@ static SAMPLE_SONGS: Lazy<[Song; 4]> = Lazy::new(|| {
             album: Some(SAMPLE_ALBUMS[0].clone()),
             file_path: "/path/to/song/file".into(),
             disc: Some(1),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Flac),
+                bitrate_kbps: Some(2304),
+                sample_rate_hz: Some(96000),
+                bit_depth: Some(24),
+                channels: Some(2),
+            },
         },
         Song {
             song_id: None,
@@ -92,6 +109,8 @@ static SAMPLE_SONGS: Lazy<[Song; 4]> = Lazy::new(|| {
             album: None,
             file_path: "/path/to/other/song".into(),
             disc: None,
+            missing: false,
+            audio: Default::default(),
         },
         Song {
             song_id: None,
@@ -104,6 +123,8 @@ static SAMPLE_SONGS: Lazy<[Song; 4]> = Lazy::new(|| {
             album: None,
             file_path: "/path/".into(),
             disc: Some(1),
+            missing: false,
+            audio: Default::default(),
         },
         Song {
             song_id: None,
@@ -116,6 +137,14 @@ static SAMPLE_SONGS: Lazy<[Song; 4]> = Lazy::new(|| {
             album: Some(SAMPLE_ALBUMS[1].clone()),
             file_path: "/path/to/song/file/bachelorette.flac".into(),
             disc: Some(1),
+            missing: false,
+            audio: AudioProperties {
+                codec: Some(Codec::Flac),
+                bitrate_kbps: Some(880),
+                sample_rate_hz: Some(44100),
+                bit_depth: Some(16),
+                channels: Some(2),
+            },
         },
     ]
 });
@@ -174,7 +203,7 @@ fn insert_and_retrieve_artist() {
         db.insert(&mut artist).expect("Insert");
     }
     let db_artists = db
-        .get_all::<Artist>(Order::Asc("artist.artist_id".to_string()))
+        .get_all::<Artist>(asc(ArtistField::ArtistId))
         .expect("Retrieval");
 
     for (i, db_artist) in db_artists.clone().into_iter().enumerate() {
@@ -264,6 +293,8 @@ fn empty_album() {
         name: "".into(),
         artist: None,
         cover_path: None,
+        cover_path_small: None,
+        cover_path_tiny: None,
         year: None,
         total_tracks: None,
         total_discs: None,
@@ -286,6 +317,8 @@ fn empty_song() {
         genre: None,
         artist: None,
         album: None,
+        missing: false,
+        audio: Default::default(),
     })
     .expect("Expected error");
 }
@@ -380,6 +413,8 @@ fn album_exists() {
             name: "Does not exist".to_string(),
             artist: None,
             cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
             year: None,
             total_tracks: None,
             total_discs: None,
@@ -417,6 +452,7 @@ fn insert_and_retrieve_song() {
         assert_eq!(db_song.name, sample_song.name);
         assert_eq!(db_song.duration_s, sample_song.duration_s);
         assert_eq!(db_song.quality, sample_song.quality);
+        assert_eq!(db_song.audio, sample_song.audio);
         assert_eq!(db_song.genre, sample_song.genre);
         assert_eq!(db_song.album.is_some(), sample_song.album.is_some());
 
@@ -447,6 +483,32 @@ fn insert_and_retrieve_song() {
     }
 }
 
+#[test]
+fn filter_by_audio_properties() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+    let song_names = |condition| {
+        let songs = db.get_by::<Song>(condition, Order::Default).unwrap();
+        songs.into_iter().map(|s| s.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        song_names(eq(SongField::Codec, "Flac")),
+        vec!["Suuria Kuvioita", "Bachelorette"]
+    );
+    assert_eq!(
+        song_names(gte(SongField::BitDepth, 24i64)),
+        vec!["Suuria Kuvioita"]
+    );
+    assert_eq!(
+        song_names(eq(SongField::HiRes, 1i64)),
+        vec!["Suuria Kuvioita"]
+    );
+    assert_eq!(song_names(eq(SongField::HiRes, 0i64)).len(), 3);
+}
+
 #[test]
 fn insert_full_song() {
     let db = get_mock_db();
@@ -513,6 +575,8 @@ fn song_exists() {
             genre: None,
             artist: None,
             album: None,
+            missing: false,
+            audio: Default::default(),
         };
         assert!(db.exists(&mut song_to_search).expect("Exists check"));
         assert_eq!(song_to_search.song_id.expect(""), counter);
@@ -524,6 +588,8 @@ fn song_exists() {
             name: "Does not exist".to_string(),
             artist: None,
             cover_path: None,
+            cover_path_small: None,
+            cover_path_tiny: None,
             year: None,
             total_tracks: None,
             total_discs: None,
@@ -590,8 +656,8 @@ fn sorting() {
 
     // Default orders by artist_id ascending
     let db_songs_default = db.get_all::<Song>(Order::Default).unwrap();
-    let db_artist_name = db.get_all::<Artist>(desc("artist.name")).unwrap();
-    let db_album_artist_id = db.get_all::<Album>(asc("album.artist_id")).unwrap();
+    let db_artist_name = db.get_all::<Artist>(desc(ArtistField::Name)).unwrap();
+    let db_album_artist_id = db.get_all::<Album>(asc(AlbumField::ArtistId)).unwrap();
     let db_artist_default = db.get_all::<Artist>(Order::Default).unwrap();
     let db_album_default = db.get_all::<Album>(Order::Default).unwrap();
 
@@ -750,19 +816,19 @@ fn get_by() {
     }
 
     assert_eq!(
-        db.get_by::<Song>(eq("genre", "Lost wave"), Order::Default)
+        db.get_by::<Song>(eq(SongField::Genre, "Lost wave"), Order::Default)
             .unwrap()[0]
             .name,
         "Like the wind".to_string()
     );
     assert_eq!(
-        db.get_by::<Song>(eq("artist.name", "Anssi Kela"), Order::Default)
+        db.get_by::<Song>(eq(SongField::ArtistName, "Anssi Kela"), Order::Default)
             .unwrap()[0]
             .name,
         "Suuria Kuvioita".to_string()
     );
     assert_eq!(
-        db.get_by::<Song>(gte("track", "5"), Order::Default)
+        db.get_by::<Song>(gte(SongField::Track, 5i64), Order::Default)
             .unwrap()[0]
             .song_id
             .unwrap(),
@@ -770,7 +836,7 @@ fn get_by() {
     );
 
     assert_eq!(
-        db.get_by::<Album>(lt("year", "2000"), Order::Default)
+        db.get_by::<Album>(lt(AlbumField::Year, 2000i64), Order::Default)
             .unwrap()[0]
             .album_id
             .unwrap(),
@@ -778,28 +844,21 @@ fn get_by() {
     );
 
     assert_eq!(
-        db.get_by::<Artist>(gt("album.artist_id", "1"), Order::Default)
+        db.get_by::<Artist>(gt(ArtistField::ArtistId, 1i64), Order::Default)
             .unwrap()[0]
             .name,
         "Anssi Kela".to_string()
     );
 
     assert_eq!(
-        db.get_by::<Playlist>(like("desc", "flipping"), Order::Default)
-            .unwrap()[0]
-            .name,
-        "Flipping rocking tunes".to_string()
-    );
-
-    assert_eq!(
-        db.get_by::<Playlist>(like("desc", "FLippiNG"), Order::Default)
+        db.get_by::<Playlist>(like(PlaylistField::Desc, "flipping"), Order::Default)
             .unwrap()[0]
             .name,
         "Flipping rocking tunes".to_string()
     );
 
     assert_eq!(
-        db.get_by::<Playlist>(search("desc", "FLIPPING"), Order::Default)
+        db.get_by::<Playlist>(like(PlaylistField::Desc, "FLippiNG"), Order::Default)
             .unwrap()[0]
             .name,
         "Flipping rocking tunes".to_string()
@@ -807,8 +866,8 @@ fn get_by() {
 
     assert_eq!(
         db.get_by::<Song>(
-            eq("quality", &(Quality::Lossless as i64).to_string()),
-            asc("song.name"),
+            eq(SongField::Quality, Quality::Lossless as i64),
+            asc(SongField::Name),
         )
         .unwrap()[0]
             .name,
@@ -876,7 +935,7 @@ fn album_songs() {
     }
 
     let res = db
-        .get_by::<Song>(eq("song.album_id", "1"), Order::Asc("track".to_string()))
+        .get_by::<Song>(eq(SongField::AlbumId, 1i64), asc(SongField::Track))
         .unwrap();
 
     assert!(res.len() > 0);
@@ -885,3 +944,329 @@ fn album_songs() {
         SAMPLE_ALBUMS[0].clone().name
     );
 }
+
+#[test]
+fn get_by_quotes() {
+    let db = get_mock_db();
+    for mut artist in SAMPLE_ARTISTS.clone().into_iter() {
+        db.insert(&mut artist).unwrap();
+    }
+
+    let injector = &SAMPLE_ARTISTS[3].name;
+    let res = db
+        .get_by::<Artist>(eq(ArtistField::Name, &injector[..]), Order::Default)
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(&res[0].name, injector);
+
+    let res = db
+        .get_by::<Artist>(like(ArtistField::Name, "'`"), Order::Default)
+        .unwrap();
+    assert_eq!(res.len(), 1);
+
+    // Would match everything if the value was interpolated into the query
+    let res = db
+        .get_by::<Artist>(eq(ArtistField::Name, "' OR '1' = '1"), Order::Default)
+        .unwrap();
+    assert_eq!(res.len(), 0);
+
+    // Wildcards are matched literally
+    let res = db
+        .get_by::<Artist>(like(ArtistField::Name, "%"), Order::Default)
+        .unwrap();
+    assert_eq!(res.len(), 0);
+}
+
+#[test]
+fn filter_from_frontend() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+
+    let filter: Filter<SongField> = serde_json::from_str(
+        r#"{ "field": "quality", "value": 0, "order_by": "name", "descending": true }"#,
+    )
+    .unwrap();
+    let res = db
+        .get_by::<Song>(filter.condition().unwrap(), filter.order())
+        .unwrap();
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0].name, "Suuria Kuvioita");
+
+    let filter: Filter<SongField> =
+        serde_json::from_str(r#"{ "field": "genre", "value": null }"#).unwrap();
+    let res = db
+        .get_by::<Song>(filter.condition().unwrap(), filter.order())
+        .unwrap();
+    let names: Vec<_> = res.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Empty song"]);
+
+    // Only fields of the model are accepted
+    assert!(serde_json::from_str::<Filter<SongField>>(
+        r#"{ "field": "song.name; DROP TABLE song", "value": 0 }"#
+    )
+    .is_err());
+    assert!(
+        serde_json::from_str::<Filter<AlbumField>>(r#"{ "field": "genre", "value": 0 }"#).is_err()
+    );
+}
+
+#[test]
+fn song_multiple_criteria() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+    let song_names = |condition| {
+        let songs = db.get_by::<Song>(condition, Order::Default).unwrap();
+        songs.into_iter().map(|s| s.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        song_names(and(vec![
+            eq(SongField::Quality, Quality::Lossless as i64),
+            gt(SongField::Year, 2000i64),
+        ])),
+        vec!["Suuria Kuvioita"]
+    );
+    assert_eq!(
+        song_names(or(vec![
+            is_null(SongField::Genre),
+            eq(SongField::Genre, "Lost wave")
+        ])),
+        vec!["Empty song", "Like the wind"]
+    );
+    assert_eq!(
+        song_names(not(is_in(
+            SongField::Name,
+            vec!["Empty song", "Bachelorette"]
+        ))),
+        vec!["Suuria Kuvioita", "Like the wind"]
+    );
+    assert_eq!(
+        song_names(and(vec![
+            between(SongField::Duration, 200.0, 230.0),
+            not_null(SongField::AlbumId),
+            or(vec![
+                eq(SongField::ArtistName, "Björk"),
+                eq(SongField::AlbumArtistName, "Björk"),
+            ]),
+        ])),
+        vec!["Suuria Kuvioita", "Bachelorette"]
+    );
+
+    // Degenerate cases
+    assert_eq!(
+        song_names(is_in(SongField::SongId, Vec::<i64>::new())).len(),
+        0
+    );
+    assert_eq!(song_names(and(vec![])).len(), SAMPLE_SONGS.len());
+    assert_eq!(song_names(or(vec![])).len(), 0);
+}
+
+#[test]
+fn album_multiple_criteria() {
+    let db = get_mock_db();
+    for mut album in SAMPLE_ALBUMS.clone().into_iter() {
+        db.insert_full(&mut album).unwrap();
+    }
+    let album_names = |condition| {
+        let albums = db.get_by::<Album>(condition, Order::Default).unwrap();
+        albums.into_iter().map(|a| a.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        album_names(and(vec![
+            eq(AlbumField::ArtistName, "Björk"),
+            gt(AlbumField::Year, 2000i64),
+        ])),
+        vec!["Suuria Kuvioita"]
+    );
+    assert_eq!(
+        album_names(or(vec![
+            lt(AlbumField::Year, 2000i64),
+            is_null(AlbumField::Year)
+        ])),
+        vec!["Homogenic", "Empty album"]
+    );
+    assert_eq!(
+        album_names(between(AlbumField::Year, 1990i64, 2000i64)),
+        vec!["Homogenic"]
+    );
+    assert_eq!(
+        album_names(and(vec![
+            not_null(AlbumField::ArtistId),
+            not(eq(AlbumField::Name, "Homogenic")),
+        ])),
+        vec!["Suuria Kuvioita"]
+    );
+}
+
+#[test]
+fn playlist_multiple_criteria() {
+    let db = get_mock_db();
+    for mut playlist in SAMPLE_PLAYLISTS.clone().into_iter() {
+        db.insert_full(&mut playlist).unwrap();
+    }
+    let playlist_names = |condition| {
+        let playlists = db.get_by::<Playlist>(condition, Order::Default).unwrap();
+        playlists.into_iter().map(|p| p.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        playlist_names(and(vec![
+            like(PlaylistField::Desc, "rock"),
+            not(like(PlaylistField::Name, "flipping")),
+        ])),
+        vec!["Alternative rock"]
+    );
+    assert_eq!(
+        playlist_names(is_in(PlaylistField::PlaylistId, vec![1i64, 3i64])),
+        vec!["Alternative rock", "Bangers"]
+    );
+    assert_eq!(
+        playlist_names(or(vec![
+            eq(PlaylistField::Desc, "Yipii"),
+            and(vec![
+                like(PlaylistField::Name, "rock"),
+                is_null(PlaylistField::Created)
+            ]),
+        ])),
+        vec!["Bangers"]
+    );
+}
+
+#[test]
+fn pagination() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+
+    let first = db
+        .get_page::<Song>(Condition::None, Order::Default, Page::new(3, 0))
+        .unwrap();
+    assert_eq!(first.total, 4);
+    let ids: Vec<_> = first.items.iter().map(|s| s.song_id.unwrap()).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    let page = Page::new(3, 0).next();
+    let second = db
+        .get_page::<Song>(Condition::None, Order::Default, page)
+        .unwrap();
+    assert_eq!(second.total, 4);
+    assert_eq!(second.offset, 3);
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].song_id, Some(4));
+
+    let past_end = db
+        .get_page::<Song>(Condition::None, Order::Default, Page::new(3, 6))
+        .unwrap();
+    assert_eq!(past_end.total, 4);
+    assert!(past_end.items.is_empty());
+
+    let lossless = db
+        .get_page::<Song>(
+            eq(SongField::Quality, Quality::Lossless as i64),
+            desc(SongField::Name),
+            Page::new(1, 0),
+        )
+        .unwrap();
+    assert_eq!(lossless.total, 2);
+    assert_eq!(lossless.items[0].name, "Suuria Kuvioita");
+
+    assert_eq!(db.count::<Artist>(&Condition::None).unwrap(), 2);
+    assert_eq!(
+        db.count::<Album>(&eq(AlbumField::ArtistName, "Björk"))
+            .unwrap(),
+        1
+    );
+}
+
+#[test]
+fn pages_with_tied_sort_keys() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+    // Two songs of each quality, one song per page
+    let page_ids = |order: fn() -> Order<SongField>| {
+        let mut ids = vec![];
+        let mut page = Page::new(1, 0);
+        loop {
+            let songs = db.get_page::<Song>(Condition::None, order(), page).unwrap();
+            if songs.items.is_empty() {
+                return ids;
+            }
+            ids.extend(songs.items.iter().map(|s| s.song_id.unwrap()));
+            page = page.next();
+        }
+    };
+
+    // Songs with the same quality come by id, no song is skipped or repeated
+    assert_eq!(page_ids(|| asc(SongField::Quality)), vec![1, 4, 2, 3]);
+    assert_eq!(page_ids(|| desc(SongField::Quality)), vec![2, 3, 1, 4]);
+    assert_eq!(
+        page_ids(|| desc(SongField::Quality).then(desc(SongField::SongId))),
+        vec![3, 2, 4, 1]
+    );
+}
+
+#[test]
+fn multi_key_ordering() {
+    let db = get_mock_db();
+    for mut song in SAMPLE_SONGS.clone().into_iter() {
+        db.insert_full(&mut song).unwrap();
+    }
+    for mut album in SAMPLE_ALBUMS.clone().into_iter() {
+        db.insert_full(&mut album).unwrap();
+    }
+    let song_names = |order| {
+        let songs = db.get_by::<Song>(Condition::None, order).unwrap();
+        songs.into_iter().map(|s| s.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        song_names(
+            desc(SongField::Duration)
+                .nulls_last()
+                .then(asc(SongField::Name))
+        ),
+        vec![
+            "Bachelorette",
+            "Suuria Kuvioita",
+            "Like the wind",
+            "Empty song"
+        ]
+    );
+    assert_eq!(
+        song_names(asc(SongField::Genre)),
+        vec![
+            "Empty song",
+            "Bachelorette",
+            "Like the wind",
+            "Suuria Kuvioita"
+        ]
+    );
+    assert_eq!(
+        song_names(asc(SongField::Genre).nulls_last()),
+        vec![
+            "Bachelorette",
+            "Like the wind",
+            "Suuria Kuvioita",
+            "Empty song"
+        ]
+    );
+
+    // Sort keys as sent by the frontend
+    let keys: Vec<SortKey<AlbumField>> =
+        serde_json::from_str(r#"[{ "field": "year", "direction": "desc", "nulls": "first" }]"#)
+            .unwrap();
+    let albums = db.get_by::<Album>(Condition::None, keys.into()).unwrap();
+    let album_names: Vec<_> = albums.into_iter().map(|a| a.name).collect();
+    assert_eq!(
+        album_names,
+        vec!["Empty album", "Suuria Kuvioita", "Homogenic"]
+    );
+}
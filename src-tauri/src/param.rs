@@ -1,92 +1,402 @@
 // A mini domain specific language thing to abstractly query the database
 
+use serde::Deserialize;
+use sqlite::{Statement, Value};
+
+use crate::error::Error;
+
 pub trait AsQuery {
-    fn as_query(&self, default: Self) -> String;
+    fn as_query(&self, default: Self) -> Fragment;
+}
+
+// A piece of SQL with positional "?" placeholders and the values to bind to them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub sql: String,
+    pub values: Vec<Value>,
+}
+
+impl Fragment {
+    pub fn new(sql: &str) -> Fragment {
+        Fragment {
+            sql: sql.into(),
+            values: Vec::new(),
+        }
+    }
+
+    fn with_value(sql: String, value: Value) -> Fragment {
+        Fragment {
+            sql,
+            values: vec![value],
+        }
+    }
+
+    // Binds the values of the fragment starting from the first parameter of the statement. Meant
+    // for statements where the fragment holds the only placeholders.
+    pub fn bind(&self, statement: &mut Statement) -> Result<(), Error> {
+        for (i, value) in self.values.iter().enumerate() {
+            statement.bind((i + 1, value))?;
+        }
+        Ok(())
+    }
+}
+
+// Identifies a field of a model that can be used in conditions and ordering. Every model that
+// implements Retrieve has its own field enum, so only fields that exist in the query of that
+// model can be used.
+pub trait Field {
+    // The SQL expression of the field in the query of the model
+    fn column(&self) -> &'static str;
 }
 
-//  TODO: Come up with something better for identifying database fields than a string, the current
-//  implementation requires the user of the API to have knowledge of the underlying SQL query to
-//  know which fields are valid or not.
-//  Works for now as a convenience thing but should be improved with something like an enum.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
 
-pub enum Order {
-    Asc(String),
-    Desc(String),
+// Where rows with a NULL value in the sort field end up, by default SQLite puts them first when
+// ascending and last when descending
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Nulls {
+    #[default]
+    Default,
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SortKey<F> {
+    pub field: F,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub nulls: Nulls,
+}
+
+impl<F: Field> SortKey<F> {
+    fn as_sql(&self) -> String {
+        let direction = match self.direction {
+            Direction::Asc => "ASC",
+            Direction::Desc => "DESC",
+        };
+        let nulls = match self.nulls {
+            Nulls::Default => "",
+            Nulls::First => " NULLS FIRST",
+            Nulls::Last => " NULLS LAST",
+        };
+        format!("{} {}{}", self.field.column(), direction, nulls)
+    }
+}
+
+pub enum Order<F> {
+    // Sorts by the first key, ties are broken by the following ones
+    By(Vec<SortKey<F>>),
     Default,
     None,
 }
 
-pub fn asc(field: &str) -> Order {
-    Order::Asc(field.to_string())
+fn key<F>(field: F, direction: Direction) -> Order<F> {
+    Order::By(vec![SortKey {
+        field,
+        direction,
+        nulls: Nulls::Default,
+    }])
+}
+
+pub fn asc<F>(field: F) -> Order<F> {
+    key(field, Direction::Asc)
 }
 
-pub fn desc(field: &str) -> Order {
-    Order::Desc(field.to_string())
+pub fn desc<F>(field: F) -> Order<F> {
+    key(field, Direction::Desc)
+}
+
+impl<F> Order<F> {
+    // Appends the keys of the other order, for example asc(Disc).then(asc(Track))
+    pub fn then(self, next: Order<F>) -> Order<F> {
+        match (self, next) {
+            (Order::By(mut keys), Order::By(next)) => {
+                keys.extend(next);
+                Order::By(keys)
+            }
+            (Order::By(keys), _) => Order::By(keys),
+            (_, next) => next,
+        }
+    }
+
+    // Sets the null placement of the last key
+    pub fn nulls(mut self, nulls: Nulls) -> Order<F> {
+        if let Order::By(keys) = &mut self {
+            if let Some(last) = keys.last_mut() {
+                last.nulls = nulls;
+            }
+        }
+        self
+    }
+
+    pub fn nulls_first(self) -> Order<F> {
+        self.nulls(Nulls::First)
+    }
+
+    pub fn nulls_last(self) -> Order<F> {
+        self.nulls(Nulls::Last)
+    }
+}
+
+impl<F: PartialEq> Order<F> {
+    // Sorts by the order and then by the key of the model, which is also the default order. Rows
+    // that tie on every other key then come in the same order on every query, which paging with
+    // LIMIT and OFFSET relies on.
+    pub fn by_key(self, key: F) -> Order<F> {
+        match self {
+            Order::By(keys) if keys.iter().any(|k| k.field == key) => Order::By(keys),
+            Order::By(keys) => Order::By(keys).then(asc(key)),
+            Order::Default | Order::None => asc(key),
+        }
+    }
+}
+
+impl<F> From<Vec<SortKey<F>>> for Order<F> {
+    fn from(keys: Vec<SortKey<F>>) -> Self {
+        if keys.is_empty() {
+            return Order::Default;
+        }
+        Order::By(keys)
+    }
 }
 
-impl AsQuery for Order {
-    fn as_query(&self, default: Order) -> String {
+impl<F: Field> AsQuery for Order<F> {
+    fn as_query(&self, default: Order<F>) -> Fragment {
         match self {
-            Order::Asc(field) => format!("{} ASC", field),
-            Order::Desc(field) => format!("{} DESC", field),
-            Order::None => "".to_string(),
+            Order::By(keys) if keys.is_empty() => Fragment::new(""),
+            Order::By(keys) => {
+                let keys: Vec<String> = keys.iter().map(|k| k.as_sql()).collect();
+                Fragment::new(&keys.join(", "))
+            }
+            Order::None => Fragment::new(""),
             Order::Default => default.as_query(Order::None),
         }
     }
 }
 
-pub enum Condition {
-    Eq(String, String),
-    Lte(String, String),
-    Gte(String, String),
-    Lt(String, String),
-    Gt(String, String),
-    Like(String, String),
-    Search(String, String),
+// Limit and offset pagination
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Page {
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+impl Page {
+    pub fn new(limit: i64, offset: i64) -> Page {
+        Page { limit, offset }
+    }
+
+    // The next page after this one
+    pub fn next(&self) -> Page {
+        Page::new(self.limit, self.offset + self.limit)
+    }
+}
+
+// LIMIT clause for the page, integers only so these are safe to format into the query
+pub fn limit_query(page: Option<Page>) -> String {
+    match page {
+        Some(page) => format!("LIMIT {} OFFSET {}", page.limit.max(0), page.offset.max(0)),
+        None => "".into(),
+    }
+}
+
+pub enum Condition<F> {
+    Eq(F, Value),
+    Lte(F, Value),
+    Gte(F, Value),
+    Lt(F, Value),
+    Gt(F, Value),
+    Like(F, String),
+    Between(F, Value, Value),
+    In(F, Vec<Value>),
+    IsNull(F),
+    NotNull(F),
+    And(Vec<Condition<F>>),
+    Or(Vec<Condition<F>>),
+    Not(Box<Condition<F>>),
     None,
 }
 
-impl AsQuery for Condition {
-    fn as_query(&self, _default: Condition) -> String {
+impl<F: Field> AsQuery for Condition<F> {
+    fn as_query(&self, _default: Condition<F>) -> Fragment {
         match self {
-            Condition::Eq(field, value) => format!("{} = '{}'", field, value),
-            Condition::Lte(field, value) => format!("{} <= '{}'", field, value),
-            Condition::Gte(field, value) => format!("{} >= '{}'", field, value),
-            Condition::Lt(field, value) => format!("{} < '{}'", field, value),
-            Condition::Gt(field, value) => format!("{} > '{}'", field, value),
-            Condition::Like(field, value) => format!("{} LIKE '%{}%'", field, value),
-            // Note that at the moment search is just an alias for like. I'll still keep them
-            // separate if I want to use a more sophisticated search method in the future.
-            Condition::Search(field, value) => format!("{} LIKE '%{}%'", field, value),
-            Condition::None => "1 = 1".into(),
+            Condition::Eq(field, value) => compare(field.column(), "=", value),
+            Condition::Lte(field, value) => compare(field.column(), "<=", value),
+            Condition::Gte(field, value) => compare(field.column(), ">=", value),
+            Condition::Lt(field, value) => compare(field.column(), "<", value),
+            Condition::Gt(field, value) => compare(field.column(), ">", value),
+            Condition::Like(field, value) => contains(field.column(), value),
+            Condition::Between(field, low, high) => Fragment {
+                sql: format!("{} BETWEEN ? AND ?", field.column()),
+                values: vec![low.clone(), high.clone()],
+            },
+            // An empty list matches nothing, "IN ()" would be a syntax error
+            Condition::In(_, values) if values.is_empty() => Fragment::new("1 = 0"),
+            Condition::In(field, values) => Fragment {
+                sql: format!(
+                    "{} IN ({})",
+                    field.column(),
+                    vec!["?"; values.len()].join(", ")
+                ),
+                values: values.clone(),
+            },
+            Condition::IsNull(field) => Fragment::new(&format!("{} IS NULL", field.column())),
+            Condition::NotNull(field) => Fragment::new(&format!("{} IS NOT NULL", field.column())),
+            // Empty conjunctions are true and empty disjunctions false, like in logic
+            Condition::And(conditions) => join(conditions, "AND", "1 = 1"),
+            Condition::Or(conditions) => join(conditions, "OR", "1 = 0"),
+            Condition::Not(condition) => {
+                let inner = condition.as_query(Condition::None);
+                Fragment {
+                    sql: format!("NOT ({})", inner.sql),
+                    values: inner.values,
+                }
+            }
+            Condition::None => Fragment::new("1 = 1"),
         }
     }
 }
 
-pub fn eq(field: &str, value: &str) -> Condition {
-    Condition::Eq(field.into(), value.into())
+// Combines the conditions with the operator, every condition gets wrapped in parentheses so that
+// nested expressions keep their meaning
+fn join<F: Field>(conditions: &[Condition<F>], operator: &str, empty: &str) -> Fragment {
+    if conditions.is_empty() {
+        return Fragment::new(empty);
+    }
+
+    let mut parts = Vec::new();
+    let mut values = Vec::new();
+
+    for condition in conditions {
+        let fragment = condition.as_query(Condition::None);
+        parts.push(format!("({})", fragment.sql));
+        values.extend(fragment.values);
+    }
+
+    Fragment {
+        sql: parts.join(&format!(" {} ", operator)),
+        values,
+    }
+}
+
+fn compare(field: &str, operator: &str, value: &Value) -> Fragment {
+    Fragment::with_value(format!("{} {} ?", field, operator), value.clone())
+}
+
+// Case insensitive substring match, the wildcard characters in the value are matched literally
+fn contains(field: &str, value: &str) -> Fragment {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    Fragment::with_value(
+        format!("{} LIKE ? ESCAPE '\\'", field),
+        Value::String(format!("%{}%", escaped)),
+    )
+}
+
+pub fn eq<F>(field: F, value: impl Into<Value>) -> Condition<F> {
+    Condition::Eq(field, value.into())
+}
+
+pub fn lte<F>(field: F, value: impl Into<Value>) -> Condition<F> {
+    Condition::Lte(field, value.into())
+}
+
+pub fn gte<F>(field: F, value: impl Into<Value>) -> Condition<F> {
+    Condition::Gte(field, value.into())
+}
+
+pub fn lt<F>(field: F, value: impl Into<Value>) -> Condition<F> {
+    Condition::Lt(field, value.into())
+}
+
+pub fn gt<F>(field: F, value: impl Into<Value>) -> Condition<F> {
+    Condition::Gt(field, value.into())
+}
+
+pub fn like<F>(field: F, value: &str) -> Condition<F> {
+    Condition::Like(field, value.into())
 }
 
-pub fn lte(field: &str, value: &str) -> Condition {
-    Condition::Lte(field.into(), value.into())
+pub fn between<F>(field: F, low: impl Into<Value>, high: impl Into<Value>) -> Condition<F> {
+    Condition::Between(field, low.into(), high.into())
 }
 
-pub fn gte(field: &str, value: &str) -> Condition {
-    Condition::Gte(field.into(), value.into())
+pub fn is_in<F, T: Into<Value>>(field: F, values: Vec<T>) -> Condition<F> {
+    Condition::In(field, values.into_iter().map(|v| v.into()).collect())
 }
 
-pub fn lt(field: &str, value: &str) -> Condition {
-    Condition::Lt(field.into(), value.into())
+pub fn is_null<F>(field: F) -> Condition<F> {
+    Condition::IsNull(field)
 }
 
-pub fn gt(field: &str, value: &str) -> Condition {
-    Condition::Gt(field.into(), value.into())
+pub fn not_null<F>(field: F) -> Condition<F> {
+    Condition::NotNull(field)
 }
 
-pub fn like(field: &str, value: &str) -> Condition {
-    Condition::Like(field.into(), value.into())
+pub fn and<F>(conditions: Vec<Condition<F>>) -> Condition<F> {
+    Condition::And(conditions)
 }
 
-pub fn search(field: &str, value: &str) -> Condition {
-    Condition::Search(field.into(), value.into())
+pub fn or<F>(conditions: Vec<Condition<F>>) -> Condition<F> {
+    Condition::Or(conditions)
+}
+
+pub fn not<F>(condition: Condition<F>) -> Condition<F> {
+    Condition::Not(Box::new(condition))
+}
+
+// A single field filter sent by the frontend. The field names are checked when deserializing, so
+// only fields of the requested model are accepted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Filter<F> {
+    pub field: F,
+    pub value: serde_json::Value,
+    pub order_by: Option<F>,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl<F: Copy> Filter<F> {
+    pub fn condition(&self) -> Result<Condition<F>, Error> {
+        // "field = NULL" never matches anything
+        if self.value.is_null() {
+            return Ok(Condition::IsNull(self.field));
+        }
+        Ok(Condition::Eq(self.field, from_json(&self.value)?))
+    }
+
+    pub fn order(&self) -> Order<F> {
+        match (self.order_by, self.descending) {
+            (Some(field), false) => asc(field),
+            (Some(field), true) => desc(field),
+            (None, _) => Order::Default,
+        }
+    }
+}
+
+// Converts a JSON value from the frontend into a bindable value, only scalars are accepted
+pub fn from_json(value: &serde_json::Value) -> Result<Value, Error> {
+    match value {
+        serde_json::Value::Null => Ok(Value::Null),
+        serde_json::Value::Bool(value) => Ok(Value::Integer(*value as i64)),
+        serde_json::Value::String(value) => Ok(Value::String(value.clone())),
+        serde_json::Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(value), _) => Ok(Value::Integer(value)),
+            (None, Some(value)) => Ok(Value::Float(value)),
+            _ => Err(Error::Validation(format!("Unsupported number {}", number))),
+        },
+        _ => Err(Error::Validation(format!("Unsupported value {}", value))),
+    }
 }
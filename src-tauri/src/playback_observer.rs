@@ -0,0 +1,238 @@
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::{
+    audio_playback::{MpvCommand, MpvRequest, MpvResponse, Playback},
+    error::Error,
+};
+
+// Properties the observer asks mpv to report, path tells which file is playing
+pub const OBSERVED: [&str; 7] = [
+    "time-pos",
+    "pause",
+    "playlist-pos",
+    "volume",
+    "duration",
+    "idle-active",
+    "path",
+];
+
+// The playback state goes out at most this often while only the time changes
+pub const THROTTLE: Duration = Duration::from_millis(250);
+
+// How long to wait before connecting again when mpv isn't running
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    State(Playback),
+    // Path of the file that started playing
+    TrackChanged(String),
+    // The last file of the playlist played to its end
+    PlaybackEnded,
+}
+
+// Keeps track of the properties mpv reports and turns them into events
+pub struct Observer {
+    playback: Playback,
+    path: Option<String>,
+    // The last file played to its end rather than being stopped
+    reached_end: bool,
+    throttle: Duration,
+    last_sent: Option<Instant>,
+    changed: bool,
+}
+
+impl Observer {
+    pub fn new(throttle: Duration) -> Observer {
+        Observer {
+            playback: Playback::default(),
+            path: None,
+            reached_end: false,
+            throttle,
+            last_sent: None,
+            changed: false,
+        }
+    }
+
+    // Takes in a property-change event. Anything but the time going forward goes out right away.
+    pub fn property_changed(&mut self, name: &str, data: &Value, now: Instant) -> Vec<PlayerEvent> {
+        let mut events = vec![];
+        let playback = &mut self.playback;
+        match name {
+            "time-pos" => playback.time_pos = data.as_f64(),
+            "pause" => playback.paused = data.as_bool().unwrap_or(false),
+            "playlist-pos" => {
+                playback.playlist_pos = data.as_i64().and_then(|p| usize::try_from(p).ok())
+            }
+            "volume" => playback.volume = data.as_f64(),
+            "duration" => playback.duration = data.as_f64(),
+            "idle-active" => {
+                // mpv also goes idle when it starts and when the player stops it to replace or
+                // clear the queue, only going idle after a file played to its end is an end
+                let idle = data.as_bool();
+                if idle == Some(true) && self.reached_end {
+                    events.push(PlayerEvent::PlaybackEnded);
+                    self.reached_end = false;
+                }
+                playback.idle = idle;
+            }
+            "path" => {
+                let path = data.as_str().map(String::from);
+                if let Some(started) = path.as_ref().filter(|p| self.path.as_ref() != Some(*p)) {
+                    events.push(PlayerEvent::TrackChanged(started.clone()));
+                }
+                self.path = path;
+                return events;
+            }
+            _ => return events,
+        }
+
+        self.changed = true;
+        if name != "time-pos" {
+            self.last_sent = None;
+        }
+        events.extend(self.flush(now));
+        events
+    }
+
+    // Takes in an end-file event, reason is eof when the file played to its end
+    pub fn file_ended(&mut self, reason: Option<&str>) {
+        self.reached_end = reason == Some("eof");
+    }
+
+    // The playback state if it has changed since it last went out and the throttle time has passed
+    pub fn flush(&mut self, now: Instant) -> Option<PlayerEvent> {
+        if !self.changed {
+            return None;
+        }
+        if let Some(last_sent) = self.last_sent {
+            if now.duration_since(last_sent) < self.throttle {
+                return None;
+            }
+        }
+
+        self.changed = false;
+        self.last_sent = Some(now);
+        Some(PlayerEvent::State(self.playback.clone()))
+    }
+}
+
+// Listens to mpv on a connection of its own and calls on_event with what it reports. Connects
+// again whenever mpv goes away, stops when dropped.
+pub struct PlaybackObserver {
+    stop: Arc<AtomicBool>,
+}
+
+impl PlaybackObserver {
+    pub fn start<F>(socket_path: &str, on_event: F) -> PlaybackObserver
+    where
+        F: FnMut(PlayerEvent) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let socket_path = PathBuf::from(socket_path);
+        let stopped = stop.clone();
+        thread::spawn(move || run(&socket_path, &stopped, on_event));
+        PlaybackObserver { stop }
+    }
+}
+
+impl Drop for PlaybackObserver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run<F>(socket_path: &Path, stop: &AtomicBool, mut on_event: F)
+where
+    F: FnMut(PlayerEvent),
+{
+    let mut observer = Observer::new(THROTTLE);
+    while !stop.load(Ordering::Relaxed) {
+        if let Err(err) = observe(socket_path, stop, &mut observer, &mut on_event) {
+            // Not running yet or restarted, nothing to report
+            if !matches!(err, Error::Io(_)) {
+                println!("Error in observing mpv: {}", err);
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    }
+}
+
+fn observe<F>(
+    socket_path: &Path,
+    stop: &AtomicBool,
+    observer: &mut Observer,
+    on_event: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(PlayerEvent),
+{
+    let mut stream = UnixStream::connect(socket_path)?;
+    // A restarted mpv reports from the start, what the last one said no longer holds
+    observer.playback.idle = None;
+    observer.reached_end = false;
+    // Wakes up now and then to send throttled changes and to see if it should stop
+    stream.set_read_timeout(Some(THROTTLE))?;
+
+    for (i, name) in OBSERVED.iter().enumerate() {
+        let request = MpvRequest {
+            command: MpvCommand::ObserveProperty(i as u64 + 1, name.to_string()).args(),
+            request_id: 0,
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+    }
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err(Error::Io("mpv closed the connection".into())),
+            Ok(_) => {
+                let message = serde_json::from_str::<MpvResponse>(&line);
+                line.clear();
+                // Leaves out the responses to the observe_property requests and other events
+                match message {
+                    Ok(MpvResponse {
+                        event: Some(event),
+                        name: Some(name),
+                        data,
+                        ..
+                    }) if event == "property-change" => {
+                        let data = data.unwrap_or(Value::Null);
+                        for event in observer.property_changed(&name, &data, Instant::now()) {
+                            on_event(event);
+                        }
+                    }
+                    Ok(MpvResponse {
+                        event: Some(event),
+                        reason,
+                        ..
+                    }) if event == "end-file" => observer.file_ended(reason.as_deref()),
+                    _ => {}
+                }
+            }
+            // Whatever was read of a line stays in it
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        if let Some(event) = observer.flush(Instant::now()) {
+            on_event(event);
+        }
+    }
+    Ok(())
+}
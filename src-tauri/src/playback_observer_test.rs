@@ -0,0 +1,157 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    sync::mpsc::channel,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    audio_playback::Playback,
+    fs_utils::get_unique_path,
+    playback_observer::{Observer, PlaybackObserver, PlayerEvent, OBSERVED},
+};
+
+#[test]
+fn throttles_time_changes() {
+    let start = Instant::now();
+    let after = |ms: u64| start + Duration::from_millis(ms);
+    let mut observer = Observer::new(Duration::from_secs(1));
+    let playing = |time_pos: f64, paused: bool| {
+        PlayerEvent::State(Playback {
+            time_pos: Some(time_pos),
+            paused,
+            ..Default::default()
+        })
+    };
+
+    assert_eq!(
+        observer.property_changed("time-pos", &json!(1.0), start),
+        vec![playing(1.0, false)]
+    );
+    assert!(observer
+        .property_changed("time-pos", &json!(1.5), after(300))
+        .is_empty());
+    assert_eq!(observer.flush(after(500)), None);
+    assert_eq!(observer.flush(after(1000)), Some(playing(1.5, false)));
+    assert_eq!(observer.flush(after(3000)), None);
+
+    // Other changes go out right away
+    assert!(observer
+        .property_changed("time-pos", &json!(2.0), after(1100))
+        .is_empty());
+    assert_eq!(
+        observer.property_changed("pause", &json!(true), after(1200)),
+        vec![playing(2.0, true)]
+    );
+}
+
+#[test]
+fn reports_tracks_and_end() {
+    let now = Instant::now();
+    let mut observer = Observer::new(Duration::from_secs(1));
+    let mut changed = |name: &str, data: Value| observer.property_changed(name, &data, now);
+
+    assert_eq!(
+        changed("path", json!("/music/a.flac")),
+        vec![PlayerEvent::TrackChanged("/music/a.flac".into())]
+    );
+    assert!(changed("path", json!("/music/a.flac")).is_empty());
+    assert_eq!(
+        changed("playlist-pos", json!(-1)),
+        vec![PlayerEvent::State(Playback::default())]
+    );
+
+    assert!(!changed("idle-active", json!(false)).contains(&PlayerEvent::PlaybackEnded));
+    observer.file_ended(Some("eof"));
+    let mut changed = |name: &str, data: Value| observer.property_changed(name, &data, now);
+    let ended = changed("idle-active", json!(true));
+    assert_eq!(ended[0], PlayerEvent::PlaybackEnded);
+    assert!(!changed("idle-active", json!(true)).contains(&PlayerEvent::PlaybackEnded));
+
+    // The same file again after nothing was playing is a new track
+    assert!(changed("path", Value::Null).is_empty());
+    assert_eq!(
+        changed("path", json!("/music/a.flac")),
+        vec![PlayerEvent::TrackChanged("/music/a.flac".into())]
+    );
+}
+
+#[test]
+fn starting_idle_is_not_an_end() {
+    let now = Instant::now();
+    let mut observer = Observer::new(Duration::from_secs(1));
+
+    assert_eq!(
+        observer.property_changed("idle-active", &json!(true), now),
+        vec![PlayerEvent::State(Playback {
+            idle: Some(true),
+            ..Default::default()
+        })]
+    );
+}
+
+#[test]
+fn stopping_is_not_an_end() {
+    let now = Instant::now();
+    let mut observer = Observer::new(Duration::from_secs(1));
+    observer.property_changed("idle-active", &json!(false), now);
+
+    // Replacing or clearing the queue stops mpv, which goes idle
+    observer.file_ended(Some("stop"));
+    let events = observer.property_changed("idle-active", &json!(true), now);
+    assert!(!events.contains(&PlayerEvent::PlaybackEnded));
+
+    // A file that played to its end with another one after it
+    observer.property_changed("idle-active", &json!(false), now);
+    observer.file_ended(Some("eof"));
+    observer.file_ended(Some("stop"));
+    let events = observer.property_changed("idle-active", &json!(true), now);
+    assert!(!events.contains(&PlayerEvent::PlaybackEnded));
+}
+
+#[test]
+fn observes_mpv() {
+    let path = get_unique_path(&env::temp_dir().to_string_lossy(), "sock").unwrap();
+    let listener = UnixListener::bind(&path).unwrap();
+    let (sender, receiver) = channel();
+    let observer = PlaybackObserver::start(&path, move |event| {
+        let _ = sender.send(event);
+    });
+
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    for (i, name) in OBSERVED.iter().enumerate() {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let request: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(request["command"], json!(["observe_property", i + 1, name]));
+    }
+
+    for message in [
+        json!({ "request_id": 0, "error": "success" }),
+        json!({ "event": "property-change", "id": 7, "name": "path", "data": "/music/a.flac" }),
+        json!({ "event": "start-file", "playlist_entry_id": 1 }),
+        json!({ "event": "property-change", "id": 2, "name": "pause", "data": true }),
+    ] {
+        writeln!(stream, "{}", message).unwrap();
+    }
+
+    let timeout = Duration::from_secs(5);
+    assert_eq!(
+        receiver.recv_timeout(timeout),
+        Ok(PlayerEvent::TrackChanged("/music/a.flac".into()))
+    );
+    assert_eq!(
+        receiver.recv_timeout(timeout),
+        Ok(PlayerEvent::State(Playback {
+            paused: true,
+            ..Default::default()
+        }))
+    );
+
+    drop(observer);
+    fs::remove_file(path).unwrap();
+}
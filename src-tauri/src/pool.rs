@@ -0,0 +1,204 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, RwLock},
+};
+
+use crate::{database::ConnectionWrapper, error::Error};
+
+pub const DEFAULT_READERS: usize = 4;
+
+// How long a connection waits for another one to release a lock before giving up
+const BUSY_TIMEOUT_MS: i64 = 5000;
+
+// Connections to one database: a single writer and up to max_readers readers. The database is in
+// WAL mode so reads never wait for the writer and the writer never waits for reads.
+pub struct Pool {
+    path: PathBuf,
+    writer: Mutex<ConnectionWrapper>,
+    readers: Mutex<Readers>,
+    reader_returned: Condvar,
+    max_readers: usize,
+}
+
+struct Readers {
+    idle: Vec<ConnectionWrapper>,
+    open: usize,
+}
+
+impl Pool {
+    // Opens the database, bringing its schema up to date. Readers are opened when needed.
+    pub fn open(path: &Path, max_readers: usize) -> Result<Pool, Error> {
+        let writer = ConnectionWrapper::open(path)?;
+        configure(&writer)?;
+        writer.conn.execute("PRAGMA journal_mode = WAL")?;
+        writer.migrate()?;
+
+        Ok(Pool {
+            path: path.into(),
+            writer: Mutex::new(writer),
+            readers: Mutex::new(Readers {
+                idle: vec![],
+                open: 0,
+            }),
+            reader_returned: Condvar::new(),
+            max_readers,
+        })
+    }
+
+    // A pool without readers where everything goes through the given connection, for databases
+    // that can't be opened twice such as in-memory ones
+    pub fn single(db: ConnectionWrapper) -> Pool {
+        Pool {
+            path: ":memory:".into(),
+            writer: Mutex::new(db),
+            readers: Mutex::new(Readers {
+                idle: vec![],
+                open: 0,
+            }),
+            reader_returned: Condvar::new(),
+            max_readers: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Waits for the writer, hold on to it only as long as needed
+    pub fn write(&self) -> Result<MutexGuard<'_, ConnectionWrapper>, Error> {
+        match self.writer.lock() {
+            Ok(writer) => Ok(writer),
+            Err(_) => Err(pool_error("Database writer unavailable")),
+        }
+    }
+
+    // Takes an idle reader, opens a new one or waits for one to be returned if all of them are
+    // in use. Readers can't modify the database.
+    pub fn read(&self) -> Result<PooledConnection<'_>, Error> {
+        if self.max_readers == 0 {
+            return Ok(PooledConnection::Writer(self.write()?));
+        }
+
+        let mut readers = match self.readers.lock() {
+            Ok(readers) => readers,
+            Err(_) => return Err(pool_error("Database readers unavailable")),
+        };
+
+        loop {
+            if let Some(db) = readers.idle.pop() {
+                return Ok(self.lend(db));
+            }
+
+            if readers.open < self.max_readers {
+                readers.open += 1;
+                // Don't hold the lock while opening, other readers can be returned meanwhile
+                drop(readers);
+
+                return match self.open_reader() {
+                    Ok(db) => Ok(self.lend(db)),
+                    Err(error) => {
+                        if let Ok(mut readers) = self.readers.lock() {
+                            readers.open -= 1;
+                        }
+                        Err(error)
+                    }
+                };
+            }
+
+            readers = match self.reader_returned.wait(readers) {
+                Ok(readers) => readers,
+                Err(_) => return Err(pool_error("Database readers unavailable")),
+            };
+        }
+    }
+
+    fn open_reader(&self) -> Result<ConnectionWrapper, Error> {
+        let db = ConnectionWrapper::open(&self.path)?;
+        configure(&db)?;
+        db.conn.execute("PRAGMA query_only = ON")?;
+        Ok(db)
+    }
+
+    fn lend(&self, db: ConnectionWrapper) -> PooledConnection<'_> {
+        PooledConnection::Reader {
+            pool: self,
+            db: Some(db),
+        }
+    }
+
+    fn return_reader(&self, db: ConnectionWrapper) {
+        if let Ok(mut readers) = self.readers.lock() {
+            readers.idle.push(db);
+        }
+        self.reader_returned.notify_one();
+    }
+}
+
+fn configure(db: &ConnectionWrapper) -> Result<(), Error> {
+    db.conn
+        .execute(format!("PRAGMA busy_timeout = {}", BUSY_TIMEOUT_MS))?;
+    Ok(())
+}
+
+fn pool_error(message: &str) -> Error {
+    Error::Internal(message.into())
+}
+
+// A connection borrowed from the pool, readers go back to the pool when dropped
+pub enum PooledConnection<'a> {
+    Reader {
+        pool: &'a Pool,
+        db: Option<ConnectionWrapper>,
+    },
+    Writer(MutexGuard<'a, ConnectionWrapper>),
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = ConnectionWrapper;
+
+    fn deref(&self) -> &ConnectionWrapper {
+        match self {
+            PooledConnection::Reader { db, .. } => db.as_ref().expect("Reader already returned"),
+            PooledConnection::Writer(db) => db,
+        }
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let PooledConnection::Reader { pool, db } = self {
+            if let Some(db) = db.take() {
+                pool.return_reader(db);
+            }
+        }
+    }
+}
+
+// The pool of the active profile, managed as tauri state. Commands clone the Arc so that switching
+// profiles never pulls the database out from under a running query or scan.
+pub struct SharedPool {
+    pool: RwLock<Arc<Pool>>,
+}
+
+impl SharedPool {
+    pub fn new(pool: Pool) -> SharedPool {
+        SharedPool {
+            pool: RwLock::new(Arc::new(pool)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<Pool> {
+        match self.pool.read() {
+            Ok(pool) => pool.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn replace(&self, pool: Pool) {
+        match self.pool.write() {
+            Ok(mut current) => *current = Arc::new(pool),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(pool),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    fs_utils::get_unique_path,
+    models::{base_metadata::Artist, Store},
+    param::Order,
+    pool::Pool,
+    test_utils::get_mock_db,
+};
+
+fn temp_pool(max_readers: usize) -> (Pool, PathBuf) {
+    let path = get_unique_path(&env::temp_dir().to_string_lossy(), "db").unwrap();
+    let path = PathBuf::from(path);
+    (Pool::open(&path, max_readers).expect("Open pool"), path)
+}
+
+// WAL mode leaves the -wal and -shm files next to the database
+fn remove_database(path: PathBuf) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.to_string_lossy(), suffix));
+    }
+}
+
+fn artist(name: &str) -> Artist {
+    Artist {
+        artist_id: None,
+        name: name.into(),
+        artist_image_path: None,
+    }
+}
+
+#[test]
+fn wal_mode() {
+    let (pool, path) = temp_pool(2);
+    {
+        let db = pool.write().unwrap();
+        let mut statement = db.conn.prepare("PRAGMA journal_mode").unwrap();
+        statement.next().unwrap();
+        assert_eq!(statement.read::<String, _>(0).unwrap(), "wal");
+    }
+    remove_database(path);
+}
+
+#[test]
+fn readers_are_read_only() {
+    let (pool, path) = temp_pool(2);
+    {
+        let db = pool.read().unwrap();
+        assert!(db.conn.execute("DELETE FROM artist").is_err());
+        assert!(db.insert(&mut artist("Reader")).is_err());
+
+        let writer = pool.write().unwrap();
+        writer.insert(&mut artist("Writer")).unwrap();
+    }
+    remove_database(path);
+}
+
+#[test]
+fn read_while_writing() {
+    let (pool, path) = temp_pool(2);
+    {
+        let writer = pool.write().unwrap();
+        let transaction = writer.transaction().unwrap();
+        writer.insert(&mut artist("Uncommitted")).unwrap();
+
+        // Readers see the last committed state without waiting for the writer
+        let reader = pool.read().unwrap();
+        assert!(reader.get_all::<Artist>(Order::Default).unwrap().is_empty());
+
+        transaction.commit().unwrap();
+        assert_eq!(reader.get_all::<Artist>(Order::Default).unwrap().len(), 1);
+    }
+    remove_database(path);
+}
+
+#[test]
+fn wait_for_free_reader() {
+    let (pool, path) = temp_pool(1);
+    let pool = Arc::new(pool);
+
+    let reader = pool.read().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let waiting = {
+        let pool = pool.clone();
+        thread::spawn(move || {
+            let db = pool.read().unwrap();
+            db.get_all::<Artist>(Order::Default).unwrap();
+            sender.send(()).unwrap();
+        })
+    };
+
+    // The only reader is taken so the thread has to wait for it
+    assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    drop(reader);
+    assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    waiting.join().unwrap();
+
+    drop(pool);
+    remove_database(path);
+}
+
+#[test]
+fn single_connection() {
+    let pool = Pool::single(get_mock_db());
+
+    pool.write()
+        .unwrap()
+        .insert(&mut artist("Through the writer"))
+        .unwrap();
+    let db = pool.read().unwrap();
+    assert_eq!(db.get_all::<Artist>(Order::Default).unwrap().len(), 1);
+}
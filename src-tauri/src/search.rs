@@ -0,0 +1,121 @@
+use serde::Serialize;
+use sqlite::{Connection, State};
+
+use crate::{
+    error::Error,
+    models::{
+        base_metadata::{Album, AlbumField, Artist, ArtistField, Song, SongField},
+        user_generated::{Playlist, PlaylistField},
+        Retrieve,
+    },
+    param::{is_in, Order},
+};
+
+// The search index is a single FTS5 table, see the search_index migration. Its rowid packs the id
+// of the indexed object and the kind of object together: rowid = id * KINDS + kind.
+const KINDS: i64 = 4;
+const SONG: i64 = 0;
+const ALBUM: i64 = 1;
+const ARTIST: i64 = 2;
+const PLAYLIST: i64 = 3;
+
+pub const DEFAULT_LIMIT: i64 = 20;
+
+// Matches for each kind of object, best match first
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SearchResults {
+    pub songs: Vec<Song>,
+    pub albums: Vec<Album>,
+    pub artists: Vec<Artist>,
+    pub playlists: Vec<Playlist>,
+}
+
+// Searches the library for the words in text, returning at most limit results of each kind.
+// Every word has to match the beginning of a word in the name or the related names (artist,
+// album, genre or description), ignoring case and diacritics.
+pub fn search(conn: &Connection, text: &str, limit: i64) -> Result<SearchResults, Error> {
+    let Some(query) = match_query(text) else {
+        return Ok(SearchResults::default());
+    };
+
+    Ok(SearchResults {
+        songs: get_ranked::<Song>(conn, &query, SONG, limit, SongField::SongId, |s| s.song_id)?,
+        albums: get_ranked::<Album>(conn, &query, ALBUM, limit, AlbumField::AlbumId, |a| {
+            a.album_id
+        })?,
+        artists: get_ranked::<Artist>(conn, &query, ARTIST, limit, ArtistField::ArtistId, |a| {
+            a.artist_id
+        })?,
+        playlists: get_ranked::<Playlist>(
+            conn,
+            &query,
+            PLAYLIST,
+            limit,
+            PlaylistField::PlaylistId,
+            |p| p.playlist_id,
+        )?,
+    })
+}
+
+// Turns user input into an FTS5 query, every word becomes a quoted prefix query so that characters
+// with a special meaning in the query syntax are matched literally
+pub fn match_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+    Some(terms.join(" "))
+}
+
+// Ids of the matching objects of one kind, best match first. Matches in the name count for more
+// than matches in the related names.
+fn ranked_ids(conn: &Connection, query: &str, kind: i64, limit: i64) -> Result<Vec<i64>, Error> {
+    let sql = format!(
+        "SELECT rowid / {kinds} AS id FROM search_index
+        WHERE search_index MATCH ? AND rowid % {kinds} = ?
+        ORDER BY bm25(search_index, 10.0, 1.0)
+        LIMIT ?",
+        kinds = KINDS
+    );
+
+    let mut statement = conn.prepare(sql)?;
+    statement.bind((1, query))?;
+    statement.bind((2, kind))?;
+    statement.bind((3, limit))?;
+
+    let mut ids = vec![];
+    while let Ok(State::Row) = statement.next() {
+        ids.push(statement.read::<i64, _>("id")?);
+    }
+    Ok(ids)
+}
+
+fn get_ranked<T: Retrieve>(
+    conn: &Connection,
+    query: &str,
+    kind: i64,
+    limit: i64,
+    id_field: T::Field,
+    id_of: fn(&T) -> Option<i64>,
+) -> Result<Vec<T>, Error> {
+    let ids = ranked_ids(conn, query, kind, limit)?;
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut items = T::get_by(conn, is_in(id_field, ids.clone()), Order::Default)?;
+
+    // Back into the order of relevance
+    items.sort_by_key(|item| {
+        id_of(item)
+            .and_then(|id| ids.iter().position(|i| *i == id))
+            .unwrap_or(ids.len())
+    });
+    Ok(items)
+}
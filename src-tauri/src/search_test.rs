@@ -0,0 +1,176 @@
+use crate::{
+    database::ConnectionWrapper,
+    models::{
+        base_metadata::{Album, Artist, Song},
+        user_generated::Playlist,
+        Quality, Store, StoreFull,
+    },
+    search::{match_query, search},
+    test_utils::get_mock_db,
+};
+
+fn artist(name: &str) -> Artist {
+    Artist {
+        artist_id: None,
+        name: name.into(),
+        artist_image_path: None,
+    }
+}
+
+fn album(name: &str, artist_name: &str) -> Album {
+    Album {
+        album_id: None,
+        name: name.into(),
+        artist: Some(artist(artist_name)),
+        cover_path: None,
+        cover_path_small: None,
+        cover_path_tiny: None,
+        year: None,
+        total_tracks: None,
+        total_discs: None,
+    }
+}
+
+fn song(name: &str, album_name: &str, artist_name: &str, genre: &str) -> Song {
+    Song {
+        song_id: None,
+        name: name.into(),
+        file_path: format!("/music/{}/{}.flac", album_name, name),
+        track: None,
+        disc: None,
+        duration_s: None,
+        quality: Quality::Lossless,
+        genre: Some(genre.into()),
+        artist: Some(artist(artist_name)),
+        album: Some(album(album_name, artist_name)),
+        missing: false,
+        audio: Default::default(),
+    }
+}
+
+fn get_search_db() -> ConnectionWrapper {
+    let db = get_mock_db();
+    let songs = [
+        song("Bachelorette", "Homogenic", "Björk", "Art pop"),
+        song("Jóga", "Homogenic", "Björk", "Art pop"),
+        song(
+            "Paranoid Android",
+            "OK Computer",
+            "Radiohead",
+            "Alternative rock",
+        ),
+        song(
+            "Karma Police",
+            "OK Computer",
+            "Radiohead",
+            "Alternative rock",
+        ),
+    ];
+    for mut song in songs {
+        song.insert_full(&db.conn).unwrap();
+    }
+
+    let mut playlist = Playlist {
+        playlist_id: None,
+        name: "Road trip".into(),
+        desc: "Songs for long drives".into(),
+        cover_path: None,
+        created: None,
+        tags: vec![],
+    };
+    playlist.insert(&db.conn).unwrap();
+    db
+}
+
+#[test]
+fn query_syntax() {
+    assert_eq!(match_query("   "), None);
+    assert_eq!(match_query("ok comp"), Some("\"ok\"* \"comp\"*".into()));
+    assert_eq!(
+        match_query("\"AND\" NOT (x)"),
+        Some("\"AND\"* \"NOT\"* \"(x)\"*".into())
+    );
+}
+
+#[test]
+fn ignores_diacritics() {
+    let db = get_search_db();
+
+    let results = search(&db.conn, "bjork", 10).unwrap();
+    assert_eq!(results.artists.len(), 1);
+    assert_eq!(results.artists[0].name, "Björk");
+    assert_eq!(results.albums.len(), 1);
+    assert_eq!(results.songs.len(), 2);
+
+    let results = search(&db.conn, "JOGA", 10).unwrap();
+    assert_eq!(results.songs.len(), 1);
+    assert_eq!(results.songs[0].name, "Jóga");
+}
+
+#[test]
+fn prefix_and_all_words() {
+    let db = get_search_db();
+
+    let results = search(&db.conn, "bache", 10).unwrap();
+    assert_eq!(results.songs.len(), 1);
+    assert_eq!(results.songs[0].name, "Bachelorette");
+
+    // Every word has to match, but they can match different columns
+    let results = search(&db.conn, "karma radio", 10).unwrap();
+    assert_eq!(results.songs.len(), 1);
+    assert_eq!(results.songs[0].name, "Karma Police");
+    assert!(results.artists.is_empty());
+
+    let results = search(&db.conn, "drives", 10).unwrap();
+    assert_eq!(results.playlists.len(), 1);
+    assert_eq!(results.playlists[0].name, "Road trip");
+
+    // Special characters of the query syntax are matched as text
+    assert!(search(&db.conn, "\"OR\" (", 10).is_ok());
+}
+
+#[test]
+fn ranks_name_matches_first() {
+    let db = get_search_db();
+
+    // All Radiohead songs match through the artist name, only one through its own name
+    let mut cover = song("Radiohead", "Misc", "Someone else", "Cover");
+    cover.insert_full(&db.conn).unwrap();
+
+    let results = search(&db.conn, "radiohead", 10).unwrap();
+    assert_eq!(results.songs.len(), 3);
+    assert_eq!(results.songs[0].song_id, cover.song_id);
+
+    let results = search(&db.conn, "radiohead", 1).unwrap();
+    assert_eq!(results.songs.len(), 1);
+}
+
+#[test]
+fn index_follows_changes() {
+    let db = get_search_db();
+
+    db.conn
+        .execute("UPDATE album SET name = 'Post' WHERE name = 'Homogenic'")
+        .unwrap();
+    let results = search(&db.conn, "post", 10).unwrap();
+    assert_eq!(results.albums.len(), 1);
+    // Songs are found through the new album name too
+    assert_eq!(results.songs.len(), 2);
+    assert!(search(&db.conn, "homogenic", 10).unwrap().albums.is_empty());
+
+    db.conn
+        .execute("DELETE FROM song WHERE name = 'Bachelorette'")
+        .unwrap();
+    assert!(search(&db.conn, "bachelorette", 10)
+        .unwrap()
+        .songs
+        .is_empty());
+
+    let mut artist = artist("Sigur Rós");
+    artist.insert(&db.conn).unwrap();
+    let results = search(&db.conn, "sigur ros", 10).unwrap();
+    assert_eq!(results.artists, vec![artist.clone()]);
+
+    artist.delete(&db.conn).unwrap();
+    assert!(search(&db.conn, "sigur", 10).unwrap().artists.is_empty());
+}
@@ -1,9 +1,29 @@
-use crate::database::ConnectionWrapper;
+use std::{env, fs, path::Path};
+
+use crate::{database::ConnectionWrapper, fs_utils::get_unique_path};
 
 pub fn get_mock_db() -> ConnectionWrapper {
-    let db = ConnectionWrapper {
-        conn: sqlite::open(":memory:").expect("Connection failed"),
-    };
-    db.create_schema().unwrap();
+    let db = ConnectionWrapper::open(":memory:").expect("Connection failed");
+    db.migrate().unwrap();
     db
 }
+
+// Copies the test audio files into a new temporary directory so that tests can modify them
+pub fn get_test_audio_copy() -> String {
+    let temp_dir = env::temp_dir().to_string_lossy().to_string();
+    let dir = get_unique_path(&temp_dir, "music").unwrap();
+    fs::create_dir(&dir).unwrap();
+    for entry in fs::read_dir("test_audio").unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, Path::new(&dir).join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+// An empty temporary directory for the covers saved by a test's scans
+pub fn get_image_cache() -> String {
+    let temp_dir = env::temp_dir().to_string_lossy().to_string();
+    let dir = get_unique_path(&temp_dir, "images").unwrap();
+    fs::create_dir(&dir).unwrap();
+    dir
+}
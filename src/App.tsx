@@ -12,11 +12,7 @@ import ContextMenu, {
 } from './components/ContextMenu';
 import { PlayerContext } from './components/player/Player';
 import { listen } from '@tauri-apps/api/event';
-
-interface QueueRequest {
-    songs: Song[];
-    start: boolean;
-}
+import { backend } from './ipc_commands';
 
 export const WebserverContext = createContext<string | null>(null);
 
@@ -26,10 +22,6 @@ function App() {
     );
     const [mainViewForceRefresh, setMainViewForceRefresh] = useState(0);
 
-    const [queue, setQueue] = useState<Song[]>([]);
-    const [queuePos, setQueuePos] = useState<number>(0);
-    const [shouldReset, setShouldReset] = useState<boolean>(false);
-
     const [contextMenuState, setContextMenuState] = useState<ContextMenuState>({
         items: [],
         mousePosX: 0,
@@ -37,37 +29,23 @@ function App() {
     });
     const [showContextMenu, setShowContextMenu] = useState(false);
 
-    const [queueRequest, setQueueRequest] = useState<QueueRequest | null>(null);
     const [webserverAddress, setWebserverAddress] = useState<string | null>(
         null,
     );
 
-    //  NOTE: We can't read current state from within a function called through PlayerContext,
-    //  hence this approach
-    useEffect(() => {
-        if (!queueRequest) return;
-
-        setQueue((old) => {
-            if (!queueRequest.start) return [...old, ...queueRequest.songs];
-            return [
-                ...old.slice(0, queuePos + 1),
-                ...queueRequest.songs,
-                ...old.slice(queuePos + 1),
-            ];
-        });
-
-        setQueueRequest(null);
-    }, [queueRequest, queuePos]);
-
+    // The queue lives in the backend, the player follows its changes
     const onQueue = (songs: Song[], start: boolean = false) => {
-        alert('onQueue');
-        setQueueRequest({ songs, start });
+        backend.queueSongs(
+            songs.map((song) => song.song_id!),
+            start ? 'next' : 'last',
+        );
     };
 
     const onPlay = (newQueue: Song[], newQueuePos: number) => {
-        setQueue(newQueue);
-        setQueuePos(newQueuePos);
-        setShouldReset(true);
+        backend.playSongs(
+            newQueue.map((song) => song.song_id!),
+            newQueuePos,
+        );
     };
 
     const onMainViewSelected = (state: MainViewState) => {
@@ -129,14 +107,7 @@ function App() {
                                 key={mainViewForceRefresh}
                             />
                         </div>
-                        <Player
-                            queue={queue}
-                            queuePos={queuePos}
-                            setQueue={setQueue}
-                            setQueuePos={setQueuePos}
-                            shouldReset={shouldReset}
-                            setShouldReset={setShouldReset}
-                        />
+                        <Player />
                     </WebserverContext.Provider>
                 </MainViewContext.Provider>
             </PlayerContext.Provider>
@@ -6,6 +6,7 @@ import BulletButtons from '../BulletButtons';
 import Library from './Library';
 import { backend } from '../../ipc_commands';
 import { listen } from '@tauri-apps/api/event';
+import { AppError, ScanProgress } from '../../ipc_types';
 
 type Props = {};
 
@@ -21,6 +22,9 @@ export default function LeftPanel({}: Props) {
         libraryViews[0],
     );
     const [syncWorking, setSyncWorking] = useState(false);
+    const [scanProgress, setScanProgress] = useState<ScanProgress | null>(
+        null,
+    );
     const [forceLibraryRefresh, setForceLibraryRefresh] = useState(0);
 
     function startDrag(e: MouseEvent) {
@@ -49,18 +53,46 @@ export default function LeftPanel({}: Props) {
         setPanelWidthOffset(0);
     }, [dragging, panelWidthOffset]);
 
-    // Listen for an event from the backend
+    // Listen for events from the backend
     useEffect(() => {
-        const unlisten = listen('scan_done', () => {
+        const unlistenProgress = listen<ScanProgress>(
+            'scan_progress',
+            (event) => {
+                setSyncWorking(true);
+                setScanProgress(event.payload);
+            },
+        );
+        const unlistenDone = listen('scan_done', () => {
             setSyncWorking(false);
+            setScanProgress(null);
+            setForceLibraryRefresh((old) => old + 1);
+        });
+        // Files added to or removed from the music directories
+        const unlistenChanged = listen('library_changed', () => {
             setForceLibraryRefresh((old) => old + 1);
         });
 
         return () => {
-            unlisten.then((f) => f());
+            unlistenProgress.then((f) => f());
+            unlistenDone.then((f) => f());
+            unlistenChanged.then((f) => f());
         };
     }, []);
 
+    function toggleScan() {
+        if (syncWorking) {
+            backend.cancel_scan();
+            return;
+        }
+        setSyncWorking(true);
+        backend.scan().catch((err: AppError) => {
+            // A scan or an update from the library watcher is running already. The progress
+            // events of a scan turn the button back on.
+            if (err.kind !== 'busy') console.log('Error in scan', err);
+            setSyncWorking(false);
+        });
+    }
+
     useEffect(() => {
         window.addEventListener('mouseup', stopDrag);
         window.addEventListener('mousemove', mouseMove);
@@ -87,14 +119,22 @@ export default function LeftPanel({}: Props) {
                             <AlbumOpen />
                         </div>
                         <div className="text">Library</div>
+                        {scanProgress && (
+                            <div
+                                className="scan-progress"
+                                title={scanProgress.directory}
+                            >
+                                {scanProgress.processed} /{' '}
+                                {scanProgress.total}
+                                {scanProgress.errors > 0 &&
+                                    ` (${scanProgress.errors} errors)`}
+                            </div>
+                        )}
                     </div>
                     <button
                         className={`icon-btn sync ${syncWorking ? 'working' : ''}`}
-                        onClick={() => {
-                            if (syncWorking) return;
-                            setSyncWorking(true);
-                            backend.scan();
-                        }}
+                        title={syncWorking ? 'Cancel scan' : 'Scan library'}
+                        onClick={toggleScan}
                     >
                         <RefreshDouble />
                     </button>
@@ -3,7 +3,7 @@ import { Album, Artist, Playlist, Song } from '../../ipc_types';
 import ImagePlaceholder from '../ImagePlaceholder';
 import { PlaySolid, Shuffle } from 'iconoir-react';
 import Button from '../Button';
-import { capitalize, formatTime, shuffleArray } from '../../utils';
+import { capitalize, formatAudio, formatTime, shuffleArray } from '../../utils';
 import { backend } from '../../ipc_commands';
 import { convertFileSrc } from '@tauri-apps/api/tauri';
 import { PlayerContext } from '../player/Player';
@@ -213,13 +213,14 @@ export default function AlbumView({ id, isPlaylist = false }: Props) {
                     <div className="number">#</div>
                     <div className="title">TITLE</div>
                     <div className="artist">ARTIST</div>
+                    <div className="format">FORMAT</div>
                     <div className="length">LENGTH</div>
                 </div>
                 {viewData.songs.map((song, i) => (
                     <div
-                        className="song-item"
+                        className={`song-item ${song.missing ? 'missing' : ''}`}
                         key={i}
-                        onClick={() => play(i)}
+                        onClick={() => !song.missing && play(i)}
                         onContextMenu={(e) => {
                             openContextMenu({
                                 items: [
@@ -261,6 +262,9 @@ export default function AlbumView({ id, isPlaylist = false }: Props) {
                         <div className="artist">
                             {song.artist?.name ?? 'Unknown'}
                         </div>
+                        <div className="format">
+                            {formatAudio(song.audio)}
+                        </div>
                         <div className="length">
                             {formatTime(song.duration_s)}
                         </div>
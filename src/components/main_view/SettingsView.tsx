@@ -10,6 +10,7 @@ type Props = {};
 export default function SettingsView({}: Props) {
     const webserverAddress = useContext(WebserverContext);
     const [directories, setDirectories] = useState<Directory[]>([]);
+    const [watchLibrary, setWatchLibrary] = useState(false);
 
     async function selectDirectory() {
         backend.select_directory().then(updateDirectoryList);
@@ -19,8 +20,14 @@ export default function SettingsView({}: Props) {
         setDirectories(await backend.get_all_directories());
     }
 
+    async function toggleWatchLibrary(enabled: boolean) {
+        await backend.set_watch_library(enabled);
+        setWatchLibrary(await backend.get_watch_library());
+    }
+
     useEffect(() => {
         updateDirectoryList();
+        backend.get_watch_library().then(setWatchLibrary);
     }, []);
 
     return (
@@ -62,6 +69,17 @@ export default function SettingsView({}: Props) {
             </div>
 
             <h2>Other</h2>
+            <div className="setting">
+                <label className="label">
+                    <input
+                        type="checkbox"
+                        checked={watchLibrary}
+                        onChange={(e) => toggleWatchLibrary(e.target.checked)}
+                    />{' '}
+                    Update the library when files in the music directories
+                    change
+                </label>
+            </div>
         </div>
     );
 }
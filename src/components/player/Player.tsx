@@ -1,9 +1,6 @@
 import {
     CSSProperties,
-    Dispatch,
-    SetStateAction,
     createContext,
-    useCallback,
     useContext,
     useEffect,
     useRef,
@@ -23,11 +20,12 @@ import {
     Xmark,
 } from 'iconoir-react';
 import { MainViewContext } from '../main_view/MainView';
-import { Song } from '../../ipc_types';
+import { PlayQueue, PlayerState, Song } from '../../ipc_types';
 import { backend } from '../../ipc_commands';
 import { convertFileSrc } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
 import ImagePlaceholder from '../ImagePlaceholder';
-import { albumCover, songClass, wrap } from '../../utils';
+import { albumCover, songClass } from '../../utils';
 import FullscreenView from './FullScreenView';
 import SafeImage from '../SafeImage';
 
@@ -41,60 +39,51 @@ export const PlayerContext = createContext<PlayerContextState>({
     onQueue: () => {},
 });
 
-type Props = {
-    queue: Song[];
-    queuePos: number;
-    setQueue: Dispatch<SetStateAction<Song[]>>;
-    setQueuePos: Dispatch<SetStateAction<number>>;
-    shouldReset: boolean;
-    setShouldReset: Dispatch<SetStateAction<boolean>>;
-};
-
-export default function Player({
-    queue,
-    setQueue,
-    queuePos,
-    setQueuePos,
-    shouldReset,
-    setShouldReset,
-}: Props) {
+export default function Player() {
     const onMainViewSelected = useContext(MainViewContext).onMainViewSelected;
 
-    const [songStartTime, setSongStartTime] = useState(0);
-    const [totalTime, setTotalTime] = useState(0);
-    const [currentSong, setCurrentSong] = useState<Song | null>(null);
-    const [playing, setPlaying] = useState(false);
-    const [time, setTime] = useState(0);
-    const [loadedNextSong, setLoadedNextSong] = useState<number | null>(null);
+    const [queue, setQueue] = useState<PlayQueue>({
+        songs: [],
+        position: null,
+    });
+    const [playerState, setPlayerState] = useState<PlayerState | null>(null);
     const [enableQueuePanel, setEnableQueuePanel] = useState(false);
-    const [disabled, setDisabled] = useState(true);
-    const [shuffle, setShuffle] = useState(false);
-    const [repeat, setRepeat] = useState(false);
     const [fullscreen, setFullscreen] = useState(false);
 
-    const currentSongQueueItem = useRef<HTMLDivElement | null>(null);
-    useEffect(() => {
-        if (queueItem(1)?.song_id != loadedNextSong)
-            console.log('TODO: Should replace the next song');
-
-        console.log('Queue changed: ', queue);
-    }, [queue]);
+    // The backend owns the queue and mpv the playback, this only shows them
+    const queuePos = queue.position ?? -1;
+    const currentSong =
+        queue.position !== null ? queue.songs[queue.position] : null;
+    const playback = playerState?.playback;
+    const playing = !!playback && !playback.paused && !playback.idle;
+    const time = playback?.time_pos ?? 0;
+    const totalTime = playback?.duration ?? currentSong?.duration_s ?? 0;
+    const shuffle = playerState?.shuffle ?? 'off';
+    const repeat = playerState?.repeat ?? 'off';
+    const disabled = !currentSong;
 
     useEffect(() => {
-        if (shouldReset) reset();
-    }, [shouldReset]);
-
-    // Safer way to get items from the queue.
-    // (as opposed to queue[someindex])
-    const queueItem = useCallback(
-        (offset: number = 0) => {
-            const index = queuePos + offset;
-            if (index >= queue.length) return null;
-            return queue[index];
-        },
-        [queuePos, queue],
-    );
+        backend.getQueue().then(setQueue);
+        backend.getPlayerState().then(setPlayerState);
+
+        const unlistenQueue = listen<PlayQueue>('queue_changed', (event) =>
+            setQueue(event.payload),
+        );
+        const unlistenState = listen<PlayerState>('player_state', (event) =>
+            setPlayerState(event.payload),
+        );
+        // mpv goes on to the next song by itself, refresh the position
+        const unlistenTrack = listen('track_changed', () =>
+            backend.getQueue().then(setQueue),
+        );
+        return () => {
+            unlistenQueue.then((f) => f());
+            unlistenState.then((f) => f());
+            unlistenTrack.then((f) => f());
+        };
+    }, []);
 
+    const currentSongQueueItem = useRef<HTMLDivElement | null>(null);
     useEffect(() => {
         if (!currentSongQueueItem.current) return;
         currentSongQueueItem.current.scrollIntoView({
@@ -103,71 +92,6 @@ export default function Player({
         });
     }, [queuePos]);
 
-    function reset() {
-        const current = queueItem();
-        const next = queueItem(1);
-
-        if (!current) return;
-
-        // Start immediately playing the first item and queue the next
-        backend.playSong(current.song_id!, false);
-
-        // if (next) {
-        //     backend.playSong(next.song_id!, true);
-        //     setLoadedNextSong(next.song_id!);
-        // }
-
-        setSongStartTime(Date.now());
-        setTotalTime(current.duration_s ?? 0);
-        setCurrentSong(current);
-        setPlaying(true);
-        setShouldReset(false);
-        setDisabled(false);
-    }
-
-    const nextSong = useCallback(() => {
-        const current = queueItem(1);
-        const next = queueItem(2);
-
-        if (!current) {
-            if (repeat) {
-                setQueuePos(0);
-                setShouldReset(true);
-            } else {
-                setPlaying(false);
-            }
-
-            return;
-        }
-
-        // Add next song to queue
-        if (next) backend.playSong(next.song_id!, true);
-
-        setCurrentSong(current);
-        setSongStartTime(Date.now());
-        setTotalTime(current.duration_s ?? 0);
-        setPlaying(true);
-        setCurrentSong(current);
-        setQueuePos((old) => old + 1);
-    }, [repeat, queueItem]);
-
-    function seek(time: number) {
-        setSongStartTime(Date.now() - time * 1000);
-    }
-
-    // Updates the time and calls nextSong() when needed
-    useEffect(() => {
-        const interval = setInterval(() => {
-            if (!playing) return;
-
-            const elapsedTime = (Date.now() - songStartTime) / 1000;
-
-            if (elapsedTime >= totalTime) nextSong();
-            else setTime(elapsedTime);
-        }, 250);
-        return () => clearInterval(interval);
-    }, [songStartTime, playing, totalTime]);
-
     return (
         <>
             {currentSong && (
@@ -175,7 +99,7 @@ export default function Player({
                     song={currentSong}
                     fullscreen={fullscreen}
                     setFullscreen={setFullscreen}
-                    queue={queue}
+                    queue={queue.songs}
                     queuePos={queuePos}
                     totalTime={totalTime}
                     elapsedTime={time}
@@ -183,13 +107,10 @@ export default function Player({
             )}
             <div className={`queue-panel ${enableQueuePanel ? 'enable' : ''}`}>
                 <div className="content">
-                    {queue.map((song, i) => (
+                    {queue.songs.map((song, i) => (
                         <div
                             className={`song ${songClass(i, queuePos)}`}
-                            onClick={() => {
-                                setQueuePos(i);
-                                setShouldReset(true);
-                            }}
+                            onClick={() => backend.jumpInQueue(i)}
                             ref={
                                 i == queuePos ? currentSongQueueItem : undefined
                             }
@@ -213,11 +134,7 @@ export default function Player({
                             <button
                                 className="icon-btn"
                                 onClick={(e) => {
-                                    setQueue((old) => {
-                                        const copy = [...old];
-                                        copy.splice(i, 1);
-                                        return copy;
-                                    });
+                                    backend.removeFromQueue(i);
                                     e.stopPropagation();
                                 }}
                             >
@@ -258,26 +175,25 @@ export default function Player({
                 <div className="controls">
                     <div className="buttons">
                         <button
-                            className={`shuffle ${shuffle ? 'selected' : ''}`}
-                            onClick={() => setShuffle((old) => !old)}
+                            className={`shuffle ${shuffle != 'off' ? 'selected' : ''}`}
+                            onClick={() =>
+                                backend.setShuffle(
+                                    shuffle == 'off' ? 'songs' : 'off',
+                                )
+                            }
                         >
                             <Shuffle />
                         </button>
                         <button
                             className="prev"
-                            onClick={() => {
-                                setQueuePos((old) =>
-                                    wrap(old - 1, queue.length),
-                                );
-                                setShouldReset(true);
-                            }}
+                            onClick={() => backend.previousSong()}
                         >
                             <SkipPrevSolid />
                         </button>
                         <button
                             className="play"
                             onClick={() =>
-                                disabled ? null : setPlaying((old) => !old)
+                                disabled ? null : backend.setPaused(playing)
                             }
                         >
                             {playing && !disabled ? (
@@ -288,18 +204,17 @@ export default function Player({
                         </button>
                         <button
                             className="next"
-                            onClick={() => {
-                                setQueuePos((old) =>
-                                    wrap(old + 1, queue.length),
-                                );
-                                setShouldReset(true);
-                            }}
+                            onClick={() => backend.nextSong()}
                         >
                             <SkipNextSolid />
                         </button>
                         <button
-                            className={`repeat ${repeat ? 'selected' : ''}`}
-                            onClick={() => setRepeat((old) => !old)}
+                            className={`repeat ${repeat != 'off' ? 'selected' : ''}`}
+                            onClick={() =>
+                                backend.setRepeat(
+                                    repeat == 'off' ? 'all' : 'off',
+                                )
+                            }
                         >
                             <Repeat />
                         </button>
@@ -307,7 +222,7 @@ export default function Player({
                     <ProgressBar
                         totalTime={totalTime}
                         elapsedTime={time}
-                        onTimeSet={seek}
+                        onTimeSet={(time) => backend.seek(time)}
                     />
                 </div>
                 <div className="options side">
@@ -1,13 +1,124 @@
 import { invoke } from '@tauri-apps/api';
-import { Album, Directory, Playlist, Song, Tag } from './ipc_types';
+import {
+    Album,
+    Artist,
+    CleanReport,
+    Directory,
+    Filter,
+    Page,
+    Paged,
+    PlayMode,
+    PlayQueue,
+    PlayerState,
+    Playlist,
+    Profile,
+    RepeatMode,
+    SearchResults,
+    ShuffleMode,
+    Song,
+    SortKey,
+    Tag,
+} from './ipc_types';
 
+// Commands reject with an AppError when they fail
 export namespace backend {
-    export async function playSong(songId: number, queue: boolean) {
-        await invoke('play_song', { songId, queue });
+    // Queue commands return the queue after the change
+    export async function playSong(
+        songId: number,
+        mode: PlayMode,
+    ): Promise<PlayQueue> {
+        return await invoke('play_song', { songId, mode });
     }
 
-    export async function replaceSong(songId: number) {
-        await invoke('replace_song', { songId });
+    export async function queueSongs(
+        songIds: number[],
+        mode: PlayMode,
+    ): Promise<PlayQueue> {
+        return await invoke('queue_songs', { songIds, mode });
+    }
+
+    export async function replaceSong(songId: number): Promise<PlayQueue> {
+        return await invoke('replace_song', { songId });
+    }
+
+    export async function playSongs(
+        songIds: number[],
+        index: number,
+    ): Promise<PlayQueue> {
+        return await invoke('play_songs', { songIds, index });
+    }
+
+    export async function playAlbum(
+        albumId: number,
+        index: number,
+    ): Promise<PlayQueue> {
+        return await invoke('play_album', { albumId, index });
+    }
+
+    export async function playPlaylist(
+        playlistId: number,
+        index: number,
+    ): Promise<PlayQueue> {
+        return await invoke('play_playlist', { playlistId, index });
+    }
+
+    export async function getQueue(): Promise<PlayQueue> {
+        return await invoke('get_queue');
+    }
+
+    export async function insertIntoQueue(
+        songIds: number[],
+        index: number,
+    ): Promise<PlayQueue> {
+        return await invoke('insert_into_queue', { songIds, index });
+    }
+
+    export async function moveInQueue(
+        from: number,
+        to: number,
+    ): Promise<PlayQueue> {
+        return await invoke('move_in_queue', { from, to });
+    }
+
+    export async function removeFromQueue(index: number): Promise<PlayQueue> {
+        return await invoke('remove_from_queue', { index });
+    }
+
+    export async function clearQueue(): Promise<PlayQueue> {
+        return await invoke('clear_queue');
+    }
+
+    export async function jumpInQueue(index: number): Promise<PlayQueue> {
+        return await invoke('jump_in_queue', { index });
+    }
+
+    export async function nextSong(): Promise<PlayQueue> {
+        return await invoke('next_song');
+    }
+
+    export async function previousSong(): Promise<PlayQueue> {
+        return await invoke('previous_song');
+    }
+
+    export async function getPlayerState(): Promise<PlayerState> {
+        return await invoke('get_player_state');
+    }
+
+    export async function setShuffle(mode: ShuffleMode): Promise<PlayQueue> {
+        return await invoke('set_shuffle', { mode });
+    }
+
+    export async function setRepeat(mode: RepeatMode): Promise<PlayerState> {
+        return await invoke('set_repeat', { mode });
+    }
+
+    export async function setPaused(paused: boolean): Promise<void> {
+        return await invoke('set_paused', { paused });
+    }
+
+    // Seconds from the start of the song
+    export async function seek(seconds: number): Promise<void> {
+        return await invoke('seek', { seconds });
     }
 
     export async function get_artist_albums(
@@ -65,7 +176,7 @@ export namespace backend {
     export async function select_cover(
         id: number,
         playlist: boolean,
-    ): Promise<Song[]> {
+    ): Promise<void> {
         return await invoke('select_cover', {
             id,
             playlist,
@@ -94,7 +205,85 @@ export namespace backend {
         return await invoke('edit_playlist', { playlist });
     }
 
+    // Rejects if a scan is already running
     export async function scan() {
         return await invoke('scan');
     }
+
+    // Resolves to false if there was no scan to cancel
+    export async function cancel_scan(): Promise<boolean> {
+        return await invoke('cancel_scan');
+    }
+
+    export async function clean(): Promise<CleanReport> {
+        return await invoke('clean');
+    }
+
+    export async function get_watch_library(): Promise<boolean> {
+        return await invoke('get_watch_library');
+    }
+
+    export async function set_watch_library(enabled: boolean) {
+        return await invoke('set_watch_library', { enabled });
+    }
+
+    export async function get_profiles(): Promise<Profile[]> {
+        return await invoke('get_profiles');
+    }
+
+    export async function get_active_profile(): Promise<Profile | undefined> {
+        return await invoke('get_active_profile');
+    }
+
+    export async function create_profile(name: string): Promise<Profile> {
+        return await invoke('create_profile', { name });
+    }
+
+    export async function switch_profile(name: string) {
+        return await invoke('switch_profile', { name });
+    }
+
+    export async function get_songs_by(filter: Filter): Promise<Song[]> {
+        return await invoke('get_songs_by', { filter });
+    }
+
+    export async function get_albums_by(filter: Filter): Promise<Album[]> {
+        return await invoke('get_albums_by', { filter });
+    }
+
+    export async function get_artists_by(filter: Filter): Promise<Artist[]> {
+        return await invoke('get_artists_by', { filter });
+    }
+
+    export async function get_playlists_by(filter: Filter): Promise<Playlist[]> {
+        return await invoke('get_playlists_by', { filter });
+    }
+
+    export async function get_albums_page(
+        page: Page,
+        sort?: SortKey[],
+    ): Promise<Paged<Album> | undefined> {
+        return await invoke('get_albums_page', { page, sort });
+    }
+
+    export async function get_artists_page(
+        page: Page,
+        sort?: SortKey[],
+    ): Promise<Paged<Artist> | undefined> {
+        return await invoke('get_artists_page', { page, sort });
+    }
+
+    export async function get_songs_page(
+        page: Page,
+        sort?: SortKey[],
+    ): Promise<Paged<Song> | undefined> {
+        return await invoke('get_songs_page', { page, sort });
+    }
+
+    export async function search(
+        query: string,
+        limit?: number,
+    ): Promise<SearchResults> {
+        return await invoke('search', { query, limit });
+    }
 }
@@ -16,6 +16,27 @@ export interface Album {
     total_discs?: number;
 }
 
+export type Codec =
+    | 'Mp3'
+    | 'Aac'
+    | 'Vorbis'
+    | 'Opus'
+    | 'Flac'
+    | 'Alac'
+    | 'Pcm'
+    | 'WavPack'
+    | 'Other';
+
+// Read from the audio stream, empty for values the format doesn't have or songs that haven't
+// been scanned since these were added
+export interface AudioProperties {
+    codec?: Codec;
+    bitrate_kbps?: number;
+    sample_rate_hz?: number;
+    bit_depth?: number;
+    channels?: number;
+}
+
 export interface Song {
     song_id?: number;
     name: string;
@@ -24,9 +45,12 @@ export interface Song {
     disc?: number;
     duration_s?: number;
     quality: number; // Quality;
+    audio: AudioProperties;
     genre?: string;
     artist?: Artist;
     album?: Album;
+    // The file is gone, the song is only kept as a placeholder in playlists
+    missing: boolean;
 }
 
 export interface Playlist {
@@ -54,3 +78,122 @@ export interface Duration {
     secs: number;
     nanos: number;
 }
+
+export interface Profile {
+    name: string;
+    database_path?: string;
+    image_cache_dir?: string;
+}
+
+// Field names are snake_case versions of the field enums in the backend, for example "album_id"
+export interface Filter {
+    field: string;
+    value: string | number | boolean | null;
+    order_by?: string;
+    descending?: boolean;
+}
+
+export interface SortKey {
+    field: string;
+    direction?: 'asc' | 'desc';
+    nulls?: 'default' | 'first' | 'last';
+}
+
+export interface Page {
+    limit: number;
+    offset?: number;
+}
+
+export interface Paged<T> {
+    items: T[];
+    total: number;
+    limit: number;
+    offset: number;
+}
+
+// Where play_song puts the song: in place of the whole queue, right after the current song or
+// at the end of the queue
+export type PlayMode = 'now' | 'next' | 'last';
+
+// Payload of the queue_changed event, position is the index of the song that is playing
+export interface PlayQueue {
+    songs: Song[];
+    position: number | null;
+}
+
+export type ShuffleMode = 'off' | 'songs' | 'albums';
+
+export type RepeatMode = 'off' | 'one' | 'all';
+
+// What mpv reports about the file it plays, time_pos and duration are in seconds
+export interface Playback {
+    time_pos: number | null;
+    duration: number | null;
+    paused: boolean;
+    volume: number | null;
+    idle: boolean | null;
+    playlist_pos: number | null;
+}
+
+// Payload of the player_state event
+export interface PlayerState {
+    shuffle: ShuffleMode;
+    repeat: RepeatMode;
+    playback: Playback;
+}
+
+export interface SearchResults {
+    songs: Song[];
+    albums: Album[];
+    artists: Artist[];
+    playlists: Playlist[];
+}
+
+// Every command rejects with one of these when it fails
+export interface AppError {
+    kind:
+        | 'database'
+        | 'tag'
+        | 'io'
+        | 'image'
+        | 'playback'
+        | 'validation'
+        | 'not_found'
+        | 'config'
+        | 'busy'
+        | 'internal';
+    message: string;
+}
+
+// Payload of the scan_done event
+export interface ScanReport {
+    added: number;
+    updated: number;
+    unchanged: number;
+    moved: number;
+    errors: number;
+    cancelled: boolean;
+}
+
+// Payload of the scan_progress events
+export interface ScanProgress {
+    directory: string;
+    processed: number;
+    total: number;
+    added: number;
+    errors: number;
+}
+
+// Result of the clean command
+export interface CleanReport {
+    songs_removed: number;
+    songs_missing: number;
+    albums_removed: number;
+    artists_removed: number;
+}
+
+// Payload of the library_changed event, sent when the library watcher has applied changes
+export interface LibraryChanges {
+    scan: ScanReport;
+    clean: CleanReport;
+}
@@ -1,5 +1,5 @@
 import { convertFileSrc } from '@tauri-apps/api/tauri';
-import { Album } from './ipc_types';
+import { Album, AudioProperties, Codec } from './ipc_types';
 
 export function clamp(val: number, min: number, max: number) {
     return Math.min(Math.max(val, min), max);
@@ -24,6 +24,30 @@ export function formatTime(s: number | undefined) {
     return `${Math.floor(s / 60)}:${(seconds % 60).toString().padStart(2, '0')}`;
 }
 
+const CODEC_NAMES: Record<Codec, string> = {
+    Mp3: 'MP3',
+    Aac: 'AAC',
+    Vorbis: 'Vorbis',
+    Opus: 'Opus',
+    Flac: 'FLAC',
+    Alac: 'ALAC',
+    Pcm: 'PCM',
+    WavPack: 'WavPack',
+    Other: '',
+};
+
+// Short description of the audio format, like "FLAC 24/96" for lossless and "MP3 320" for lossy
+export function formatAudio(audio: AudioProperties) {
+    if (!audio.codec) return '';
+    const codec = CODEC_NAMES[audio.codec];
+    if (audio.bit_depth && audio.sample_rate_hz) {
+        const khz = Math.round(audio.sample_rate_hz / 100) / 10;
+        return `${codec} ${audio.bit_depth}/${khz}`.trim();
+    }
+    if (audio.bitrate_kbps) return `${codec} ${audio.bitrate_kbps}`.trim();
+    return codec;
+}
+
 export function songClass(i: number, queuePos: number) {
     if (i == queuePos) return 'current';
     if (i < queuePos) return 'past';